@@ -0,0 +1,369 @@
+use std::{
+    fs::{ self, DirBuilder, Permissions },
+    io::{ self, BufRead, BufReader, Write },
+    os::unix::{ fs::{ DirBuilderExt, PermissionsExt }, net::{ UnixListener, UnixStream } },
+    path::{ Path, PathBuf },
+    thread,
+    time::{ Duration, Instant },
+};
+
+use serde::{ Deserialize, Serialize };
+use zeroize::Zeroizing;
+
+use crate::{
+    config::Config,
+    credential::Credential,
+    crypto::KEY_LENGTH,
+    password::PasswordEntry,
+    store::{ backend::StorageBackend, directory::DirectoryBackend },
+};
+
+/** How often the agent checks for new connections and whether it was idle for too long */
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/** How long either side may take to send its half of a request */
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/** A request to the agent, sent as one line of JSON */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "lowercase")]
+pub enum Request {
+    /** The names of all services */
+    List,
+    /** The entry of a service */
+    Get {
+        service: String,
+    },
+    /** The entry that matches a git credential most closely */
+    Credential(Credential),
+    /** Whether the agent is running and when it locks */
+    Status,
+    /** Forget the key and stop */
+    Lock,
+}
+
+/** The answer of the agent, sent as one line of JSON */
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<String>,
+    /** The requested entry, `None` when the service does not exist */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<PasswordEntry>,
+    /** Seconds until the agent locks itself when it is not used */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locks_in: Option<u64>,
+}
+
+/**
+ * Where the agent listens: `agent_socket` from the config, or a socket in the user's runtime
+ * folder, falling back to a folder of the user in the temporary folder.
+ *
+ * @param config: &Config
+ * @return PathBuf
+ */
+pub fn socket_path(config: &Config) -> PathBuf {
+    if let Some(socket) = &config.agent_socket {
+        return socket.clone();
+    }
+
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("stashpass").join("agent.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("stashpass-{user}")).join("agent.sock")
+        }
+    }
+}
+
+/**
+ * Create the folder of the socket accessible only by its owner. A folder that other users can
+ * get into is refused, since they could replace the socket.
+ */
+fn prepare_socket_folder(socket: &Path) -> io::Result<()> {
+    let folder = socket.parent().unwrap_or(Path::new("."));
+    DirBuilder::new().recursive(true).mode(0o700).create(folder)?;
+
+    if fs::metadata(folder)?.permissions().mode() & 0o077 != 0 {
+        return Err(
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "The folder of the agent socket can be accessed by other users"
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/**
+ * Send a request to a running agent.
+ *
+ * @param socket: &Path
+ * @param request: &Request
+ * @return Result<Response, &'static str>
+ */
+pub fn send(socket: &Path, request: &Request) -> Result<Response, &'static str> {
+    let mut stream = UnixStream::connect(socket).map_err(|_| "The agent is not running")?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT)).map_err(|_| "Unable to talk to the agent")?;
+
+    let request = serde_json::to_string(request).unwrap();
+    writeln!(stream, "{request}").map_err(|_| "Unable to talk to the agent")?;
+
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new(stream).read_line(&mut line).map_err(|_| "The agent did not answer")?;
+
+    serde_json::from_str(&line).map_err(|_| "The agent sent an answer that can not be read")
+}
+
+/**
+ * Agent
+ * A background process that holds the key of the encrypted directory vault, so the master
+ * password is only asked for once. It answers list and get requests on a Unix socket that only
+ * its owner can use, and forgets the key when it is locked or was not used for a while.
+ *
+ * The key itself is never handed out, and the vault is read again for every request, so
+ * changes made by other processes are seen.
+ */
+pub struct Agent {
+    directory: PathBuf,
+    key: Zeroizing<[u8; KEY_LENGTH]>,
+    idle_timeout: Duration,
+}
+
+impl Agent {
+    /**
+     * Create an agent for a vault, the key is checked before the agent is started.
+     *
+     * @param directory: PathBuf
+     * @param key: [u8; KEY_LENGTH]
+     * @param idle_timeout: Duration
+     * @return Result<Agent, &'static str>
+     */
+    pub fn new(
+        directory: PathBuf,
+        key: [u8; KEY_LENGTH],
+        idle_timeout: Duration
+    ) -> Result<Agent, &'static str> {
+        let key = Zeroizing::new(key);
+        DirectoryBackend::open(directory.clone(), *key)?;
+
+        Ok(Agent { directory, key, idle_timeout })
+    }
+
+    fn entries(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        DirectoryBackend::open(self.directory.clone(), *self.key)?.load()
+    }
+
+    fn answer(&self, request: &Request, locks_in: Duration) -> Response {
+        let entries = match request {
+            Request::List | Request::Get { .. } | Request::Credential(_) => self.entries(),
+            Request::Status | Request::Lock => Ok(vec![]),
+        };
+        let entries = match entries {
+            Ok(entries) => entries,
+            Err(err) => {
+                return Response { error: Some(err.to_string()), ..Response::default() };
+            }
+        };
+
+        match request {
+            Request::List => {
+                let mut services: Vec<String> = entries
+                    .into_iter()
+                    .map(|entry| entry.service)
+                    .collect();
+                services.sort();
+
+                Response { services, ..Response::default() }
+            }
+            Request::Get { service } => {
+                let entry = entries.into_iter().find(|entry| &entry.service == service);
+
+                Response { entry, ..Response::default() }
+            }
+            Request::Credential(credential) => {
+                let entry = credential.find(&entries).cloned();
+
+                Response { entry, ..Response::default() }
+            }
+            Request::Status => Response {
+                locks_in: Some(locks_in.as_secs()),
+                ..Response::default()
+            },
+            Request::Lock => Response::default(),
+        }
+    }
+
+    /**
+     * Read one request from a connection and answer it.
+     *
+     * @param stream: UnixStream
+     * @param locks_in: Duration
+     * @return io::Result<Option<Request>> the request, `None` when it could not be read
+     */
+    fn respond(&self, stream: UnixStream, locks_in: Duration) -> io::Result<Option<Request>> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+        let mut line = String::new();
+        let mut reader = BufReader::new(&stream);
+        reader.read_line(&mut line)?;
+
+        let (request, response) = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let response = self.answer(&request, locks_in);
+                (Some(request), response)
+            }
+            Err(_) => {
+                let error = Some("The request can not be read".to_string());
+                (None, Response { error, ..Response::default() })
+            }
+        };
+
+        let response = Zeroizing::new(serde_json::to_string(&response).unwrap());
+        writeln!(&stream, "{}", response.as_str())?;
+
+        Ok(request)
+    }
+
+    /**
+     * Listen on the socket until the agent is locked or was idle for too long. The socket is
+     * only readable and writable by its owner, and it is removed when the agent stops. Only
+     * list, get and credential requests count as use.
+     *
+     * @param socket: &Path
+     * @return io::Result<()>
+     */
+    pub fn serve(self, socket: &Path) -> io::Result<()> {
+        prepare_socket_folder(socket)?;
+
+        if socket.exists() {
+            if UnixStream::connect(socket).is_ok() {
+                return Err(
+                    io::Error::new(io::ErrorKind::AddrInUse, "An agent is already running")
+                );
+            }
+            fs::remove_file(socket)?;
+        }
+
+        let listener = UnixListener::bind(socket)?;
+        fs::set_permissions(socket, Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+
+        let mut last_used = Instant::now();
+        let result = loop {
+            let idle = last_used.elapsed();
+            if idle >= self.idle_timeout {
+                break Ok(());
+            }
+
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    // A client that goes away half way does not stop the agent
+                    match self.respond(stream, self.idle_timeout - idle) {
+                        Ok(Some(Request::Lock)) => {
+                            break Ok(());
+                        }
+                        Ok(Some(Request::List | Request::Get { .. } | Request::Credential(_))) => {
+                            last_used = Instant::now();
+                        }
+                        _ => {}
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(err) => {
+                    break Err(err);
+                }
+            }
+        };
+
+        let _ = fs::remove_file(socket);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KdfParams;
+
+    fn start_agent(name: &str, idle_timeout: Duration) -> (PathBuf, thread::JoinHandle<()>) {
+        let root = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&root);
+        let vault = root.join("vault");
+
+        let mut backend = DirectoryBackend::create(
+            vault.clone(),
+            "master",
+            KdfParams::fast()
+        ).unwrap();
+        let github = PasswordEntry::new("github".to_string(), "me".to_string(), "pw".to_string());
+        let mail = PasswordEntry::new("mail".to_string(), "me".to_string(), "pw".to_string());
+        backend.save(&github).unwrap();
+        backend.save(&mail).unwrap();
+
+        let key = DirectoryBackend::unlock(&vault, "master").unwrap();
+        let agent = Agent::new(vault, key, idle_timeout).unwrap();
+        let socket = root.join("agent").join("agent.sock");
+        let serving = socket.clone();
+        let handle = thread::spawn(move || agent.serve(&serving).unwrap());
+
+        while UnixStream::connect(&socket).is_err() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        (socket, handle)
+    }
+
+    #[test]
+    fn test_agent_answers_until_locked() {
+        let (socket, handle) = start_agent("stashpass_test_agent", Duration::from_secs(60));
+
+        let mode = fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let response = send(&socket, &Request::List).unwrap();
+        assert_eq!(response.services, vec!["github", "mail"]);
+
+        let request = Request::Get { service: "github".to_string() };
+        let response = send(&socket, &request).unwrap();
+        assert_eq!(response.entry.unwrap().password, "pw");
+
+        let request = Request::Get { service: "bank".to_string() };
+        assert_eq!(send(&socket, &request), Ok(Response::default()));
+
+        let credential = Credential {
+            protocol: "https".to_string(),
+            host: "mail".to_string(),
+            ..Credential::default()
+        };
+        let response = send(&socket, &Request::Credential(credential)).unwrap();
+        assert_eq!(response.entry.unwrap().service, "mail");
+
+        assert!(send(&socket, &Request::Status).unwrap().locks_in.is_some());
+        send(&socket, &Request::Lock).unwrap();
+        handle.join().unwrap();
+
+        assert!(!socket.exists());
+        assert_eq!(send(&socket, &Request::Status), Err("The agent is not running"));
+
+        fs::remove_dir_all(std::env::temp_dir().join("stashpass_test_agent")).unwrap();
+    }
+
+    #[test]
+    fn test_agent_locks_when_idle() {
+        let (socket, handle) = start_agent(
+            "stashpass_test_agent_idle",
+            Duration::from_millis(300)
+        );
+
+        handle.join().unwrap();
+
+        assert!(!socket.exists());
+        fs::remove_dir_all(std::env::temp_dir().join("stashpass_test_agent_idle")).unwrap();
+    }
+}
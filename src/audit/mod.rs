@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use crate::password::{ PasswordEntry, strength::Strength };
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/**
+ * Audit Options
+ * Thresholds for the vault health report.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditOptions {
+    /** Passwords with a strength score below this are reported as weak */
+    pub min_score: u8,
+    /** Passwords that have not been changed for more days than this are reported as old */
+    pub max_age_days: u64,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        AuditOptions {
+            min_score: 3,
+            max_age_days: 365,
+        }
+    }
+}
+
+/**
+ * Audit Report
+ * The result of checking every entry in the vault. Entries are referred to by service name only,
+ * the report never contains a password.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AuditReport {
+    pub total: usize,
+    /** Groups of services that share the same password */
+    pub reused: Vec<Vec<String>>,
+    /** Services with a weak password and the password's strength score */
+    pub weak: Vec<(String, u8)>,
+    /** Services whose password is older than the threshold and its age in days */
+    pub old: Vec<(String, u64)>,
+    /** Services where it is unknown when the password was last changed */
+    pub unknown_age: Vec<String>,
+    pub missing_username: Vec<String>,
+    /** Percentage of entries without any findings */
+    pub score: u8,
+}
+
+/**
+ * Check every entry for reused, weak and old passwords and missing usernames.
+ *
+ * @param entries: &[&PasswordEntry]
+ * @param options: &AuditOptions
+ * @param now: u64
+ * @return AuditReport
+ */
+pub fn audit(entries: &[&PasswordEntry], options: &AuditOptions, now: u64) -> AuditReport {
+    let mut report = AuditReport {
+        total: entries.len(),
+        ..AuditReport::default()
+    };
+
+    let mut by_password: HashMap<&str, Vec<String>> = HashMap::new();
+    for entry in entries.iter() {
+        by_password.entry(&entry.password).or_default().push(entry.service.clone());
+    }
+    report.reused = by_password
+        .into_values()
+        .filter(|services| services.len() > 1)
+        .collect();
+    report.reused.sort();
+
+    for entry in entries.iter() {
+        let strength = Strength::estimate(&entry.password, &[&entry.service, &entry.username]);
+        if strength.score < options.min_score {
+            report.weak.push((entry.service.clone(), strength.score));
+        }
+
+        match entry.password_changed_at() {
+            Some(changed_at) => {
+                let age_days = now.saturating_sub(changed_at) / SECONDS_PER_DAY;
+                if age_days > options.max_age_days {
+                    report.old.push((entry.service.clone(), age_days));
+                }
+            }
+            None => report.unknown_age.push(entry.service.clone()),
+        }
+
+        if entry.username.trim().is_empty() {
+            report.missing_username.push(entry.service.clone());
+        }
+    }
+
+    let flagged = entries
+        .iter()
+        .filter(|entry| report.has_findings(&entry.service))
+        .count();
+    report.score = if entries.is_empty() {
+        100
+    } else {
+        (((entries.len() - flagged) * 100) / entries.len()) as u8
+    };
+
+    report
+}
+
+impl AuditReport {
+    fn has_findings(&self, service: &str) -> bool {
+        self.reused.iter().any(|group| group.iter().any(|s| s == service)) ||
+            self.weak.iter().any(|(s, _)| s == service) ||
+            self.old.iter().any(|(s, _)| s == service) ||
+            self.unknown_age.iter().any(|s| s == service) ||
+            self.missing_username.iter().any(|s| s == service)
+    }
+
+    /**
+     * A human readable version of the report, with one section per kind of finding.
+     *
+     * @return String
+     */
+    pub fn report(&self) -> String {
+        let mut lines = vec![
+            format!("Vault health: {}/100 ({} entries checked)", self.score, self.total)
+        ];
+
+        lines.push(format!("\nReused passwords: {}", self.reused.len()));
+        for group in self.reused.iter() {
+            lines.push(format!("  - shared by {}", group.join(", ")));
+        }
+
+        lines.push(format!("\nWeak passwords: {}", self.weak.len()));
+        for (service, score) in self.weak.iter() {
+            lines.push(format!("  - {service} (strength {score}/4)"));
+        }
+
+        lines.push(format!("\nOld passwords: {}", self.old.len() + self.unknown_age.len()));
+        for (service, age_days) in self.old.iter() {
+            lines.push(format!("  - {service} (last changed {age_days} days ago)"));
+        }
+        for service in self.unknown_age.iter() {
+            lines.push(format!("  - {service} (last change unknown)"));
+        }
+
+        lines.push(format!("\nMissing usernames: {}", self.missing_username.len()));
+        for service in self.missing_username.iter() {
+            lines.push(format!("  - {service}"));
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const STRONG: &str = "kT9vQ2mXz7LwP4rB8nYc";
+
+    fn entry(service: &str, username: &str, password: &str, created_at: u64) -> PasswordEntry {
+        let mut entry = PasswordEntry::new(
+            service.to_string(),
+            username.to_string(),
+            password.to_string()
+        );
+        entry.created_at = created_at;
+        entry
+    }
+
+    #[test]
+    fn test_healthy_vault() {
+        let entries = [entry("github", "me", STRONG, NOW)];
+        let report = audit(&entries.iter().collect::<Vec<_>>(), &AuditOptions::default(), NOW);
+
+        assert_eq!(report.score, 100);
+        assert!(report.reused.is_empty());
+        assert!(report.weak.is_empty());
+    }
+
+    #[test]
+    fn test_findings() {
+        let entries = [
+            entry("github", "me", STRONG, NOW),
+            entry("gitlab", "me", STRONG, NOW),
+            entry("forum", "", "password1", NOW),
+            entry("bank", "me", "Xb7!qLz9#pVw2mTr", NOW - 400 * SECONDS_PER_DAY),
+            entry("legacy", "me", "Hq4@nRt8$kWy3zPd", 0),
+        ];
+        let report = audit(&entries.iter().collect::<Vec<_>>(), &AuditOptions::default(), NOW);
+
+        assert_eq!(report.reused, vec![vec!["github".to_string(), "gitlab".to_string()]]);
+        assert_eq!(report.weak, vec![("forum".to_string(), 0)]);
+        assert_eq!(report.old, vec![("bank".to_string(), 400)]);
+        assert_eq!(report.unknown_age, vec!["legacy".to_string()]);
+        assert_eq!(report.missing_username, vec!["forum".to_string()]);
+        assert_eq!(report.score, 0);
+        assert!(!report.report().contains(STRONG));
+    }
+
+    #[test]
+    fn test_rotation_resets_age() {
+        let mut old = entry("bank", "me", STRONG, NOW - 400 * SECONDS_PER_DAY);
+        old.change_password("Xb7!qLz9#pVw2mTr".to_string());
+        old.history[0].changed_at = NOW - SECONDS_PER_DAY;
+
+        let report = audit(&[&old], &AuditOptions::default(), NOW);
+
+        assert!(report.old.is_empty());
+        assert_eq!(report.score, 100);
+    }
+}
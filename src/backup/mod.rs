@@ -0,0 +1,184 @@
+use std::{ collections::HashMap, fs, path::{ Path, PathBuf }, time::{ SystemTime, UNIX_EPOCH } };
+
+use crate::{ config::Config, password::PasswordEntry };
+
+/**
+ * Backups
+ * Timestamped copies of the vault file, taken before every write. Only the newest `count`
+ * copies are kept, so the folder does not grow forever.
+ *
+ * Only the JSON file and the database are backed up, and a backup is a plain copy of the file:
+ * it is exactly as unencrypted as the vault it was taken from, there is nothing to decrypt.
+ * The encrypted directory vault keeps no backups, its history is in git with `git_sync`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backups {
+    pub directory: PathBuf,
+    pub count: usize,
+}
+
+/** A backup file and the time it was taken, in milliseconds since the epoch */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub created_at: u128,
+}
+
+impl Backups {
+    /**
+     * The backups configured for a vault. Without a configured folder they are kept in a
+     * `backups` folder next to the vault file.
+     *
+     * @param config: &Config
+     * @param vault: &Path
+     * @return Backups
+     */
+    pub fn from_config(config: &Config, vault: &Path) -> Backups {
+        let directory = config.backup_dir.clone().unwrap_or_else(|| {
+            vault.parent().unwrap_or(Path::new("")).join("backups")
+        });
+
+        Backups { directory, count: config.backup_count }
+    }
+
+    fn prefix(vault: &Path) -> String {
+        let stem = vault.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+
+        format!("{stem}-")
+    }
+
+    /** Backups keep the extension of the vault file, so they are opened the same way */
+    fn suffix(vault: &Path) -> String {
+        let extension = vault.extension().map(|extension| extension.to_string_lossy());
+
+        format!(".{}", extension.unwrap_or("json".into()))
+    }
+
+    /**
+     * Copy the vault into the backup folder and remove the oldest backups over the limit.
+     * Nothing is copied when backups are turned off or the vault is still empty.
+     *
+     * @param vault: &Path
+     * @return std::io::Result<Option<PathBuf>> the new backup
+     */
+    pub fn create(&self, vault: &Path) -> std::io::Result<Option<PathBuf>> {
+        let is_empty = fs::metadata(vault).map(|metadata| metadata.len() == 0).unwrap_or(true);
+
+        if self.count == 0 || is_empty {
+            return Ok(None);
+        }
+
+        fs::create_dir_all(&self.directory)?;
+
+        let (prefix, suffix) = (Self::prefix(vault), Self::suffix(vault));
+        let mut now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+        let mut path = self.directory.join(format!("{prefix}{now}{suffix}"));
+
+        // Several writes within the same millisecond each get their own backup
+        while path.exists() {
+            now += 1;
+            path = self.directory.join(format!("{prefix}{now}{suffix}"));
+        }
+
+        fs::copy(vault, &path)?;
+
+        for backup in self.list(vault)?.into_iter().skip(self.count) {
+            fs::remove_file(backup.path)?;
+        }
+
+        Ok(Some(path))
+    }
+
+    /**
+     * The backups of a vault, newest first.
+     *
+     * @param vault: &Path
+     * @return std::io::Result<Vec<Backup>>
+     */
+    pub fn list(&self, vault: &Path) -> std::io::Result<Vec<Backup>> {
+        if !self.directory.exists() {
+            return Ok(vec![]);
+        }
+
+        let (prefix, suffix) = (Self::prefix(vault), Self::suffix(vault));
+        let mut backups = vec![];
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string());
+            let created_at = name
+                .as_deref()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|name| name.strip_suffix(&suffix))
+                .and_then(|timestamp| timestamp.parse().ok());
+
+            if let Some(created_at) = created_at {
+                backups.push(Backup { path, created_at });
+            }
+        }
+
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+
+        Ok(backups)
+    }
+}
+
+/**
+ * Check that a backup of the JSON file can be read as a vault, so a damaged file is never
+ * restored. The backup is plaintext JSON like the vault, so being readable is all there is to
+ * check.
+ *
+ * @param path: &Path
+ * @return Result<usize, &'static str> the number of entries
+ */
+pub fn verify(path: &Path) -> Result<usize, &'static str> {
+    let content = fs::read_to_string(path).map_err(|_| "Unable to read the backup")?;
+    let entries: HashMap<String, PasswordEntry> = serde_json
+        ::from_str(&content)
+        .map_err(|_| "The backup is damaged and can not be read")?;
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_rotate() {
+        let directory = std::env::temp_dir().join("stashpass_test_backups");
+        let vault = std::env::temp_dir().join("stashpass_test_backups_vault.json");
+        let _ = fs::remove_dir_all(&directory);
+        let backups = Backups { directory: directory.clone(), count: 2 };
+
+        fs::write(&vault, "").unwrap();
+        assert_eq!(backups.create(&vault).unwrap(), None);
+
+        for version in 1..=3 {
+            fs::write(&vault, format!("{{\"v{version}\": {{}}}}")).unwrap();
+            backups.create(&vault).unwrap();
+        }
+
+        let list = backups.list(&vault).unwrap();
+
+        assert_eq!(list.len(), 2);
+        assert_eq!(fs::read_to_string(&list[0].path).unwrap(), "{\"v3\": {}}");
+        assert_eq!(fs::read_to_string(&list[1].path).unwrap(), "{\"v2\": {}}");
+
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_file(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
+        let path = std::env::temp_dir().join("stashpass_test_verify_backup.json");
+
+        fs::write(&path, r#"{ "github": { "username": "me", "password": "pw" } }"#).unwrap();
+        assert_eq!(verify(&path), Ok(1));
+
+        fs::write(&path, r#"{ "github": { "username": "me", "pass"#).unwrap();
+        assert!(verify(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
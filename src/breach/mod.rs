@@ -0,0 +1,296 @@
+use std::{
+    fs::{ self, File },
+    io::{ BufRead, BufReader, Seek, SeekFrom },
+    path::{ Path, PathBuf },
+};
+
+use sha1::{ Digest, Sha1 };
+
+use crate::password::PasswordEntry;
+
+/** Length of the hash prefix used to name the files of a range directory */
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+/**
+ * Breach Index
+ * A local copy of the Pwned Passwords SHA-1 list, in one of the two formats it can be
+ * downloaded in:
+ *
+ * - a single file with one `HASH:COUNT` line per password, sorted by hash, which is searched with
+ *   a binary search over the file so it never has to be loaded into memory
+ * - a directory of range files named after the first five characters of the hash, each with
+ *   `SUFFIX:COUNT` lines for the hashes that start with that prefix
+ *
+ * Only SHA-1 hashes are compared, passwords are never written anywhere.
+ */
+#[derive(Debug)]
+pub enum BreachIndex {
+    SortedFile(PathBuf),
+    RangeDirectory(PathBuf),
+}
+
+/**
+ * Uppercase hex SHA-1 hash of a password, as used by Pwned Passwords.
+ *
+ * @param password: &str
+ * @return String
+ */
+pub fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect()
+}
+
+/**
+ * Split a `HASH:COUNT` line into its hash and count.
+ * The count is optional since some dumps only contain the hashes.
+ *
+ * @param line: &str
+ * @return Option<(String, u64)>
+ */
+fn parse_line(line: &str) -> Option<(String, u64)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    let mut parts = line.splitn(2, ':');
+    let hash = parts.next()?.to_uppercase();
+    let count = parts
+        .next()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(1);
+
+    Some((hash, count))
+}
+
+impl BreachIndex {
+    /**
+     * Open a breach index at the given path.
+     * A directory is read as range files, anything else as a single sorted file.
+     *
+     * @param path: PathBuf
+     * @return Result<BreachIndex, &'static str>
+     */
+    pub fn open(path: PathBuf) -> Result<BreachIndex, &'static str> {
+        if path.is_dir() {
+            Ok(BreachIndex::RangeDirectory(path))
+        } else if path.is_file() {
+            Ok(BreachIndex::SortedFile(path))
+        } else {
+            Err("The breach hash file could not be found")
+        }
+    }
+
+    /**
+     * Look up how often a password has been seen in breaches.
+     *
+     * @param password: &str
+     * @return Result<Option<u64>, &'static str>
+     */
+    pub fn lookup(&self, password: &str) -> Result<Option<u64>, &'static str> {
+        let hash = sha1_hex(password);
+
+        match self {
+            BreachIndex::SortedFile(path) => lookup_sorted_file(path, &hash),
+            BreachIndex::RangeDirectory(path) => lookup_range_directory(path, &hash),
+        }
+    }
+
+    /**
+     * Check the current password of every entry.
+     *
+     * @param entries: &[&PasswordEntry]
+     * @return Result<Vec<(String, u64)>, &'static str> services with their breach count
+     */
+    pub fn check_entries(
+        &self,
+        entries: &[&PasswordEntry]
+    ) -> Result<Vec<(String, u64)>, &'static str> {
+        let mut compromised = Vec::new();
+
+        for entry in entries.iter() {
+            if let Some(count) = self.lookup(&entry.password)? {
+                compromised.push((entry.service.clone(), count));
+            }
+        }
+
+        Ok(compromised)
+    }
+}
+
+/**
+ * Read the first full line that starts at or after the given offset.
+ *
+ * @param reader: &mut BufReader<File>
+ * @param offset: u64
+ * @return Result<Option<(String, u64)>, &'static str> the line and the offset right after it
+ */
+fn line_at_or_after(
+    reader: &mut BufReader<File>,
+    offset: u64
+) -> Result<Option<(String, u64)>, &'static str> {
+    let mut position = offset;
+    let mut line = String::new();
+
+    // Step back one byte, so a line starting exactly at the offset is not skipped
+    if offset > 0 {
+        reader.seek(SeekFrom::Start(offset - 1)).map_err(|_| "Unable to read breach hash file")?;
+        let mut partial = Vec::new();
+        position = offset - 1 +
+            (reader
+                .read_until(b'\n', &mut partial)
+                .map_err(|_| "Unable to read breach hash file")? as u64);
+    } else {
+        reader.seek(SeekFrom::Start(0)).map_err(|_| "Unable to read breach hash file")?;
+    }
+
+    let read = reader.read_line(&mut line).map_err(|_| "Unable to read breach hash file")?;
+
+    if read == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some((line, position + (read as u64))))
+}
+
+fn lookup_sorted_file(path: &Path, hash: &str) -> Result<Option<u64>, &'static str> {
+    let file = File::open(path).map_err(|_| "Unable to open breach hash file")?;
+    let mut high = file.metadata().map_err(|_| "Unable to read breach hash file")?.len();
+    let mut reader = BufReader::new(file);
+    let mut low = 0;
+
+    while low < high {
+        let middle = low + (high - low) / 2;
+
+        match line_at_or_after(&mut reader, middle)? {
+            None => {
+                high = middle;
+            }
+            Some((line, end)) =>
+                match parse_line(&line) {
+                    Some((line_hash, count)) if line_hash.as_str() == hash => {
+                        return Ok(Some(count));
+                    }
+                    Some((line_hash, _)) if line_hash.as_str() < hash => {
+                        low = end;
+                    }
+                    Some(_) => {
+                        high = middle;
+                    }
+                    // Blank lines sort before every hash
+                    None => {
+                        low = end;
+                    }
+                }
+        }
+    }
+
+    Ok(None)
+}
+
+fn lookup_range_directory(path: &Path, hash: &str) -> Result<Option<u64>, &'static str> {
+    let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+
+    let range_file = [prefix.to_string(), format!("{prefix}.txt")]
+        .into_iter()
+        .map(|name| path.join(name))
+        .find(|file| file.is_file());
+
+    let range_file = match range_file {
+        Some(range_file) => range_file,
+        None => {
+            return Ok(None);
+        }
+    };
+
+    let content = fs::read_to_string(range_file).map_err(|_| "Unable to read breach range file")?;
+
+    Ok(
+        content
+            .lines()
+            .filter_map(parse_line)
+            .find(|(line_suffix, _)| line_suffix.as_str() == suffix)
+            .map(|(_, count)| count)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BREACHED: [&str; 4] = ["password", "123456", "qwerty", "letmein"];
+
+    fn sorted_file(name: &str) -> PathBuf {
+        let mut lines: Vec<String> = BREACHED.iter()
+            .enumerate()
+            .map(|(count, password)| format!("{}:{}", sha1_hex(password), count + 1))
+            .collect();
+        for i in 0..500 {
+            lines.push(format!("{}:7", sha1_hex(&format!("filler{i}"))));
+        }
+        lines.sort();
+
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, lines.join("\r\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_sha1_hex() {
+        assert_eq!(sha1_hex("password"), "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8");
+    }
+
+    #[test]
+    fn test_sorted_file_lookup() {
+        let path = sorted_file("stashpass_test_breach_sorted.txt");
+        let index = BreachIndex::open(path.clone()).unwrap();
+
+        for (count, password) in BREACHED.iter().enumerate() {
+            assert_eq!(index.lookup(password).unwrap(), Some((count as u64) + 1));
+        }
+        for i in 0..500 {
+            assert_eq!(index.lookup(&format!("filler{i}")).unwrap(), Some(7));
+        }
+        assert_eq!(index.lookup("kT9vQ2mXz7LwP4rB8nYc").unwrap(), None);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_range_directory_lookup() {
+        let path = std::env::temp_dir().join("stashpass_test_breach_ranges");
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+
+        let hash = sha1_hex("password");
+        let range_file = path.join(format!("{}.txt", &hash[..5]));
+        fs::write(range_file, format!("{}:42\n", &hash[5..])).unwrap();
+
+        let index = BreachIndex::open(path.clone()).unwrap();
+
+        assert_eq!(index.lookup("password").unwrap(), Some(42));
+        assert_eq!(index.lookup("letmein").unwrap(), None);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let path = sorted_file("stashpass_test_breach_entries.txt");
+        let index = BreachIndex::open(path.clone()).unwrap();
+        let weak = PasswordEntry::new("forum".to_string(), "me".to_string(), "qwerty".to_string());
+        let strong = PasswordEntry::new(
+            "bank".to_string(),
+            "me".to_string(),
+            "kT9vQ2mXz7LwP4rB8nYc".to_string()
+        );
+
+        let compromised = index.check_entries(&[&weak, &strong]).unwrap();
+
+        assert_eq!(compromised, vec![("forum".to_string(), 3)]);
+
+        fs::remove_file(path).unwrap();
+    }
+}
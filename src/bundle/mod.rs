@@ -0,0 +1,158 @@
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use serde::{ Deserialize, Serialize };
+
+use crate::{ crypto::{ self, KdfParams }, password::{ unix_timestamp, PasswordEntry } };
+
+/** Marks a file as a Stashpass backup bundle */
+pub const BUNDLE_FORMAT: &str = "stashpass-bundle";
+pub const BUNDLE_VERSION: u32 = 1;
+const CIPHER: &str = "xchacha20poly1305";
+
+/**
+ * Bundle Header
+ * The unencrypted part of a bundle: what it is and how to derive its key. It is authenticated
+ * along with the entries, so it can be read without the passphrase but not changed.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleHeader {
+    pub format: String,
+    pub version: u32,
+    pub created_at: u64,
+    pub kdf: KdfParams,
+    pub cipher: String,
+}
+
+/**
+ * Bundle
+ * A portable, encrypted backup of entries. The file is JSON with its own passphrase and
+ * everything else needed to decrypt it, so it can be restored without the original vault or
+ * config and handed to offsite storage.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bundle {
+    #[serde(flatten)]
+    pub header: BundleHeader,
+    /** Base64 encoded nonce */
+    pub nonce: String,
+    /** Base64 encoded entries, encrypted */
+    pub ciphertext: String,
+}
+
+impl Bundle {
+    /**
+     * Encrypt entries into a bundle.
+     *
+     * @param entries: &[&PasswordEntry]
+     * @param passphrase: &str
+     * @param kdf: KdfParams
+     * @return Result<Bundle, &'static str>
+     */
+    pub fn seal(
+        entries: &[&PasswordEntry],
+        passphrase: &str,
+        kdf: KdfParams
+    ) -> Result<Bundle, &'static str> {
+        let header = BundleHeader {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            created_at: unix_timestamp(),
+            kdf,
+            cipher: CIPHER.to_string(),
+        };
+
+        let key = header.kdf.derive_key(passphrase)?;
+        let plaintext = serde_json::to_vec(entries).map_err(|_| "Unable to serialize entries")?;
+        let (nonce, ciphertext) = crypto::encrypt(&key, &plaintext, &header.associated_data())?;
+
+        Ok(Bundle {
+            header,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    /**
+     * Decrypt the entries of a bundle.
+     *
+     * @param passphrase: &str
+     * @return Result<Vec<PasswordEntry>, &'static str>
+     */
+    pub fn open(&self, passphrase: &str) -> Result<Vec<PasswordEntry>, &'static str> {
+        if self.header.format != BUNDLE_FORMAT {
+            return Err("The file is not a Stashpass bundle");
+        }
+
+        if self.header.version > BUNDLE_VERSION || self.header.cipher != CIPHER {
+            return Err("The bundle was written by a newer version of Stashpass");
+        }
+
+        let nonce = STANDARD.decode(&self.nonce).map_err(|_| "The bundle nonce is not base64")?;
+        let ciphertext = STANDARD
+            .decode(&self.ciphertext)
+            .map_err(|_| "The bundle ciphertext is not base64")?;
+
+        let key = self.header.kdf.derive_key(passphrase)?;
+        let associated_data = self.header.associated_data();
+        let plaintext = crypto::decrypt(&key, &nonce, &ciphertext, &associated_data)?;
+
+        serde_json::from_slice(&plaintext).map_err(|_| "The bundle entries could not be read")
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(content: &str) -> Result<Bundle, &'static str> {
+        serde_json::from_str(content).map_err(|_| "The file is not a Stashpass bundle")
+    }
+}
+
+impl BundleHeader {
+    fn associated_data(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<PasswordEntry> {
+        let mut github = PasswordEntry::new(
+            "github".to_string(),
+            "octocat".to_string(),
+            "hunter2".to_string()
+        );
+        github.folder = Some("Work".to_string());
+        github.change_password("hunter3".to_string());
+
+        vec![github, PasswordEntry::new("mail".to_string(), "me".to_string(), "pw".to_string())]
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let entries = entries();
+        let references: Vec<&PasswordEntry> = entries.iter().collect();
+        let bundle = Bundle::seal(&references, "offsite passphrase", KdfParams::fast()).unwrap();
+        let json = bundle.to_json();
+
+        assert!(!json.contains("hunter"));
+        assert!(json.contains(BUNDLE_FORMAT));
+
+        let bundle = Bundle::from_json(&json).unwrap();
+
+        assert_eq!(bundle.open("offsite passphrase"), Ok(entries));
+        assert!(bundle.open("wrong passphrase").is_err());
+    }
+
+    #[test]
+    fn test_changed_header_is_rejected() {
+        let entries = entries();
+        let references: Vec<&PasswordEntry> = entries.iter().collect();
+        let mut bundle = Bundle::seal(&references, "passphrase", KdfParams::fast()).unwrap();
+        bundle.header.created_at += 1;
+
+        assert!(bundle.open("passphrase").is_err());
+        assert!(Bundle::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,379 @@
+use std::path::PathBuf;
+
+use clap::{ Args, Parser, Subcommand };
+
+use crate::{
+    audit::AuditOptions,
+    cli::completion::Shell,
+    export::ExportFormat,
+    import::{ ColumnMapping, ImportFormat },
+    password::{ generator::PasswordPolicy, passphrase::PassphrasePolicy },
+};
+
+/**
+ * Command line arguments.
+ * Running without a subcommand starts the interactive dialog.
+ */
+#[derive(Debug, Parser)]
+#[command(name = "stashpass", version, about = "A CLI password manager")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /** Generate a password and print it */
+    Generate(GenerateArgs),
+    /** Generate a diceware-style passphrase and print it */
+    Passphrase(PassphraseArgs),
+    /** Report reused, weak and old passwords and entries without a username */
+    Audit(AuditArgs),
+    /** Check every password against a local copy of the Pwned Passwords SHA-1 list */
+    BreachCheck(BreachCheckArgs),
+    /** Import entries from another password manager's export */
+    Import(ImportArgs),
+    /** Export every entry to a file that other password managers can import */
+    Export(ExportArgs),
+    /** Set or change the master password asked for before sensitive operations */
+    MasterPassword,
+    /** List the automatic backups of an unencrypted vault or restore one */
+    Backups(BackupsArgs),
+    /** Merge two diverged copies of a vault into one */
+    Merge(MergeArgs),
+    /** Push the vault to its git remote or pull changes from it, needs git_sync in the config */
+    Sync(SyncArgs),
+    /** Start, check or lock the background agent that keeps the vault unlocked */
+    #[cfg(unix)]
+    Agent(AgentArgs),
+    /** Copy the password of a service to the clipboard, through the agent when it runs */
+    Get(GetArgs),
+    /** Print the name of every service, through the agent when it runs */
+    List,
+    /** Browse, search and edit the vault in a full-screen terminal interface */
+    Tui,
+    /** Print the completion script for a shell, service names complete from the vault */
+    Completions(CompletionsArgs),
+    /** Print the service names for the completion scripts, never asks for a password */
+    #[command(name = "complete-services", hide = true)]
+    CompleteServices,
+    /**
+     * Act as a git credential helper, set it up with
+     * `git config --global credential.helper '!stashpass git-credential -C <vault folder>'`
+     */
+    GitCredential(GitCredentialArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    /** Number of characters in the password */
+    #[arg(short, long, default_value_t = 30)]
+    pub length: usize,
+
+    /** Leave out uppercase letters */
+    #[arg(long)]
+    pub no_uppercase: bool,
+
+    /** Leave out lowercase letters */
+    #[arg(long)]
+    pub no_lowercase: bool,
+
+    /** Leave out digits */
+    #[arg(long)]
+    pub no_digits: bool,
+
+    /** Include symbols */
+    #[arg(short, long)]
+    pub symbols: bool,
+
+    /** Extra characters to include */
+    #[arg(long, default_value = "")]
+    pub custom: String,
+
+    /** Minimum number of uppercase letters */
+    #[arg(long, default_value_t = 0)]
+    pub min_uppercase: usize,
+
+    /** Minimum number of lowercase letters */
+    #[arg(long, default_value_t = 0)]
+    pub min_lowercase: usize,
+
+    /** Minimum number of digits */
+    #[arg(long, default_value_t = 0)]
+    pub min_digits: usize,
+
+    /** Minimum number of symbols */
+    #[arg(long, default_value_t = 0)]
+    pub min_symbols: usize,
+
+    /** Leave out characters that are easily confused, like 0/O and 1/l */
+    #[arg(long)]
+    pub exclude_ambiguous: bool,
+
+    /** Characters the password must not contain */
+    #[arg(long, default_value = "")]
+    pub forbid: String,
+
+    /** Use every character at most once */
+    #[arg(long)]
+    pub no_repeat: bool,
+
+    /** Print a strength estimate after the password */
+    #[arg(long)]
+    pub show_strength: bool,
+}
+
+impl GenerateArgs {
+    pub fn policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            length: self.length,
+            uppercase: !self.no_uppercase,
+            lowercase: !self.no_lowercase,
+            digits: !self.no_digits,
+            symbols: self.symbols,
+            custom: self.custom.clone(),
+            min_uppercase: self.min_uppercase,
+            min_lowercase: self.min_lowercase,
+            min_digits: self.min_digits,
+            min_symbols: self.min_symbols,
+            exclude_ambiguous: self.exclude_ambiguous,
+            forbidden: self.forbid.clone(),
+            no_repeat: self.no_repeat,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct PassphraseArgs {
+    /** Number of words in the passphrase */
+    #[arg(short, long, default_value_t = 6)]
+    pub words: usize,
+
+    /** Text placed between the words */
+    #[arg(long, default_value = "-")]
+    pub separator: String,
+
+    /** Capitalize the first letter of every word */
+    #[arg(short, long)]
+    pub capitalize: bool,
+
+    /** Append a random digit to one of the words */
+    #[arg(short, long)]
+    pub number: bool,
+
+    /** Append a random symbol to one of the words */
+    #[arg(short, long)]
+    pub symbol: bool,
+}
+
+impl PassphraseArgs {
+    pub fn policy(&self) -> PassphrasePolicy {
+        PassphrasePolicy {
+            word_count: self.words,
+            separator: self.separator.clone(),
+            capitalize: self.capitalize,
+            include_number: self.number,
+            include_symbol: self.symbol,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AuditArgs {
+    /** Passwords with a strength score (0-4) below this are reported as weak */
+    #[arg(long, default_value_t = AuditOptions::default().min_score)]
+    pub min_score: u8,
+
+    /** Passwords not changed for more than this many days are reported as old */
+    #[arg(long, default_value_t = AuditOptions::default().max_age_days)]
+    pub max_age_days: u64,
+}
+
+impl AuditArgs {
+    pub fn options(&self) -> AuditOptions {
+        AuditOptions {
+            min_score: self.min_score,
+            max_age_days: self.max_age_days,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct BreachCheckArgs {
+    /** Sorted HASH:COUNT file, or a directory of range files named by hash prefix */
+    #[arg(long)]
+    pub hashes: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    /** The exported file, or the store directory for pass */
+    pub file: PathBuf,
+
+    /** Which password manager the file was exported from */
+    #[arg(long, value_enum)]
+    pub format: ImportFormat,
+
+    /** Only show what would be imported, without saving anything */
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /** CSV column with the service name */
+    #[arg(long)]
+    pub service_column: Option<String>,
+
+    /** CSV column with the username */
+    #[arg(long)]
+    pub username_column: Option<String>,
+
+    /** CSV column with the password */
+    #[arg(long)]
+    pub password_column: Option<String>,
+
+    /** CSV column with the URL, used as service name when there is none */
+    #[arg(long)]
+    pub url_column: Option<String>,
+
+    /** CSV column with notes */
+    #[arg(long)]
+    pub notes_column: Option<String>,
+
+    /** CSV column with a TOTP secret or otpauth:// URI */
+    #[arg(long)]
+    pub totp_column: Option<String>,
+
+    /** Command that prints the pass file given as last argument, e.g. "gpg --quiet --decrypt" */
+    #[arg(long)]
+    pub decrypt_command: Option<String>,
+
+    /** Replace the whole vault with the imported entries, asks before dropping the old ones */
+    #[arg(long)]
+    pub replace: bool,
+}
+
+impl ImportArgs {
+    pub fn mapping(&self) -> ColumnMapping {
+        ColumnMapping::for_format(self.format).with_overrides(ColumnMapping {
+            service: self.service_column.clone(),
+            username: self.username_column.clone(),
+            password: self.password_column.clone(),
+            url: self.url_column.clone(),
+            notes: self.notes_column.clone(),
+            totp: self.totp_column.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /** The file to write */
+    pub file: PathBuf,
+
+    /** Which format to write */
+    #[arg(long, value_enum)]
+    pub format: ExportFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupsArgs {
+    #[command(subcommand)]
+    pub command: BackupsCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupsCommand {
+    /** List the backups, newest first */
+    List,
+    /** Replace the vault with a backup, after checking that it can be read */
+    Restore {
+        /** Number of the backup in the list, or its file name */
+        backup: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct MergeArgs {
+    /** Our copy of the vault, the JSON file or the folder of an encrypted vault */
+    pub ours: PathBuf,
+
+    /** Their copy of the vault */
+    pub theirs: PathBuf,
+
+    /** The common ancestor of both copies, so deletions and true conflicts can be detected */
+    #[arg(long)]
+    pub base: Option<PathBuf>,
+
+    /**
+     * Where to write the merged vault, may be one of the copies. Encrypted copies are only
+     * written into an encrypted vault
+     */
+    #[arg(short, long)]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    #[command(subcommand)]
+    pub command: SyncCommand,
+
+    /** The remote to sync with, git_remote from the config by default */
+    #[arg(long, global = true)]
+    pub remote: Option<String>,
+
+    /** The branch of the remote, git_branch from the config by default */
+    #[arg(long, global = true)]
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum SyncCommand {
+    /** Send the local commits of the vault to the remote */
+    Push,
+    /** Take the changes from the remote, merging entry by entry when both sides changed */
+    Pull,
+}
+
+#[derive(Debug, Args)]
+pub struct AgentArgs {
+    #[command(subcommand)]
+    pub command: AgentCommand,
+}
+
+#[derive(Debug, Clone, Copy, Subcommand)]
+pub enum AgentCommand {
+    /** Ask for the master password and start the agent in the background */
+    Start,
+    /** Show whether the agent runs and when it locks */
+    Status,
+    /** Make the agent forget the key and stop */
+    Lock,
+    /** Run the agent in the foreground, reading the vault key from stdin */
+    #[command(hide = true)]
+    Serve,
+}
+
+#[derive(Debug, Args)]
+pub struct GetArgs {
+    /** The service to get the password of */
+    pub service: String,
+
+    /** Print the password instead of copying it to the clipboard */
+    #[arg(long)]
+    pub show: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /** The shell to print the completion script for */
+    pub shell: Shell,
+}
+
+#[derive(Debug, Args)]
+pub struct GitCredentialArgs {
+    /** What git asks for: get, store or erase, other operations are ignored */
+    pub operation: String,
+
+    /** The folder with the config and the vault, git runs the helper in the repository */
+    #[arg(short = 'C', long)]
+    pub directory: Option<PathBuf>,
+}
@@ -0,0 +1,147 @@
+use clap::{ Command, CommandFactory, ValueEnum };
+use clap_complete::{ generate, shells };
+
+use super::args::Cli;
+
+/** The hidden subcommand the completion scripts run to get the service names */
+pub const SERVICES_COMMAND: &str = "complete-services";
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/**
+ * The completion script for a shell. On top of the subcommands and options, the service
+ * argument of subcommands like `get` completes with the service names in the vault, which the
+ * script asks for with the `complete-services` subcommand.
+ *
+ * @param shell: Shell
+ * @return String
+ */
+pub fn script(shell: Shell) -> String {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    let subcommands = service_subcommands(&command);
+
+    let mut script = Vec::new();
+    match shell {
+        Shell::Bash => generate(shells::Bash, &mut command, &name, &mut script),
+        Shell::Zsh => generate(shells::Zsh, &mut command, &name, &mut script),
+        Shell::Fish => generate(shells::Fish, &mut command, &name, &mut script),
+    }
+    let script = String::from_utf8(script).expect("The completion script is UTF-8");
+
+    match shell {
+        Shell::Bash => complete_services_in_bash(script, &name, &subcommands),
+        Shell::Zsh => complete_services_in_zsh(script, &name),
+        Shell::Fish => complete_services_in_fish(script, &name, &subcommands),
+    }
+}
+
+/** The subcommands with a service argument */
+fn service_subcommands(command: &Command) -> Vec<String> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| subcommand.get_positionals().any(|arg| arg.get_id() == "service"))
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect()
+}
+
+/**
+ * Complete the first argument after a service subcommand that is not an option with the
+ * service names, anything else is left to the generated function.
+ */
+fn complete_services_in_bash(script: String, name: &str, subcommands: &[String]) -> String {
+    let function = format!(
+        r#"_{name}_services() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    case "${{COMP_WORDS[1]}}" in
+        {subcommands})
+            if [[ ${{COMP_CWORD}} -ge 2 && "${{cur}}" != -* ]]; then
+                local IFS=$'\n'
+                COMPREPLY=( $(compgen -W "$({name} {SERVICES_COMMAND} 2>/dev/null)" -- "${{cur}}") )
+                return 0
+            fi
+            ;;
+    esac
+    _{name} "$@"
+}}
+"#,
+        subcommands = subcommands.join("|")
+    );
+
+    let registration = "\nif [[ \"${BASH_VERSINFO[0]}\"";
+    script
+        .replacen(registration, &format!("\n{function}{registration}"), 1)
+        .replace(&format!("complete -F _{name} "), &format!("complete -F _{name}_services "))
+}
+
+/** The generated specs complete service arguments with files, they use the service names */
+fn complete_services_in_zsh(script: String, name: &str) -> String {
+    let function = format!(
+        r#"(( $+functions[_{name}_services] )) ||
+_{name}_services() {{
+    local -a services
+    services=(${{(f)"$({name} {SERVICES_COMMAND} 2>/dev/null)"}})
+    compadd -a services
+}}
+"#
+    );
+
+    let script: String = script
+        .lines()
+        .map(|line| {
+            match line.starts_with("':service -- ") {
+                true => line.replace(":_default'", &format!(":_{name}_services'")),
+                false => line.to_string(),
+            }
+        })
+        .map(|line| line + "\n")
+        .collect();
+
+    let registration = format!("\nif [ \"$funcstack[1]\" = \"_{name}\" ]");
+    script.replacen(&registration, &format!("\n{function}{registration}"), 1)
+}
+
+fn complete_services_in_fish(mut script: String, name: &str, subcommands: &[String]) -> String {
+    for subcommand in subcommands.iter() {
+        script.push_str(
+            &format!(
+                "complete -c {name} -n \"__fish_{name}_using_subcommand {subcommand}\" -f \
+                 -a \"({name} {SERVICES_COMMAND} 2>/dev/null)\"\n"
+            )
+        );
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_complete_service_names() {
+        assert_eq!(service_subcommands(&Cli::command()), vec!["get"]);
+
+        let bash = script(Shell::Bash);
+        assert!(bash.contains("        get)\n"));
+        assert!(bash.contains("complete -F _stashpass_services "));
+        assert!(!bash.contains("complete -F _stashpass "));
+
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.contains("password of:_stashpass_services'"));
+        assert!(zsh.contains("$(stashpass complete-services 2>/dev/null)"));
+
+        let fish = script(Shell::Fish);
+        assert!(
+            fish.contains(
+                "-n \"__fish_stashpass_using_subcommand get\" -f \
+                 -a \"(stashpass complete-services 2>/dev/null)\""
+            )
+        );
+    }
+}
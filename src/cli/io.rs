@@ -1,21 +1,142 @@
-use std::io::{ Write, BufRead, stdout };
+use std::io::{ self, Write, BufRead, stdout };
 
 pub fn print<W: Write + ?Sized>(writer: &mut W, message: &str) {
     writeln!(writer, "{message}").unwrap_or_else(|_| println!("{message}"));
 }
 
-pub fn read_terminal_input<R: BufRead, W: Write>(
+/**
+ * Read a line from the user, `None` when the input ended (Ctrl-D).
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param prompt: Option<&str>
+ * @return io::Result<Option<String>>
+ */
+pub fn try_read_terminal_input<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     prompt: Option<&str>
-) -> String {
+) -> io::Result<Option<String>> {
     if let Some(prompt) = prompt {
-        write!(writer, "{}", prompt).unwrap();
+        write!(writer, "{}", prompt)?;
     }
 
-    write!(writer, "{}", "").unwrap_or_else(|_| print!("{}", "👉 "));
-    stdout().flush().unwrap();
+    write!(writer, "").unwrap_or_else(|_| print!("👉 "));
+    writer.flush()?;
+    stdout().flush()?;
+
     let mut input = String::new();
-    reader.read_line(&mut input).unwrap();
-    input.trim().to_owned()
+    match reader.read_line(&mut input)? {
+        0 => Ok(None),
+        _ => Ok(Some(input.trim().to_owned())),
+    }
+}
+
+/**
+ * Read a line from the user in a dialog. `None` when the input ended or can not be read, so
+ * the dialog can be given up without saving anything. A read error is reported on stderr.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param prompt: Option<&str>
+ * @return Option<String>
+ */
+pub fn read_terminal_input<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt: Option<&str>
+) -> Option<String> {
+    try_read_terminal_input(reader, writer, prompt).unwrap_or_else(|err| {
+        eprintln!("Unable to read the input: {err}");
+        None
+    })
+}
+
+/**
+ * Ask the user a yes/no question.
+ * An empty answer falls back to the given default, anything else is asked again. `None` when
+ * the input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param prompt: &str
+ * @param default: bool
+ * @return Option<bool>
+ */
+pub fn read_confirmation<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt: &str,
+    default: bool
+) -> Option<bool> {
+    let options = if default { "[Y/n]" } else { "[y/N]" };
+
+    loop {
+        let input = read_terminal_input(reader, writer, Some(&format!("{prompt} {options}: ")))?;
+
+        match input.to_lowercase().as_str() {
+            "" => {
+                return Some(default);
+            }
+            "y" | "yes" => {
+                return Some(true);
+            }
+            "n" | "no" => {
+                return Some(false);
+            }
+            _ => print(writer, "Please answer yes or no"),
+        }
+    }
+}
+
+/**
+ * Ask the user for a positive number.
+ * An empty answer falls back to the given default, anything that is not a number is asked again.
+ * `None` when the input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param prompt: &str
+ * @param default: usize
+ * @return Option<usize>
+ */
+pub fn read_number<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    prompt: &str,
+    default: usize
+) -> Option<usize> {
+    loop {
+        let input = read_terminal_input(reader, writer, Some(&format!("{prompt} ({default}): ")))?;
+
+        if input.is_empty() {
+            return Some(default);
+        }
+
+        match input.parse::<usize>() {
+            Ok(number) => {
+                return Some(number);
+            }
+            Err(_) => print(writer, "Please enter a valid number"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_ends() {
+        let mut reader = "add\n".as_bytes();
+        let mut writer = Vec::new();
+
+        let input = try_read_terminal_input(&mut reader, &mut writer, Some("> ")).unwrap();
+        assert_eq!(input, Some("add".to_string()));
+        assert_eq!(try_read_terminal_input(&mut reader, &mut writer, None).unwrap(), None);
+        assert_eq!(read_terminal_input(&mut reader, &mut writer, None), None);
+        assert_eq!(read_confirmation(&mut reader, &mut writer, "Sure?", true), None);
+        assert_eq!(read_number(&mut reader, &mut writer, "Length", 20), None);
+        assert_eq!(writer, b"> Sure? [Y/n]: Length (20): ");
+    }
 }
@@ -1,66 +1,191 @@
+pub mod args;
+pub mod completion;
 pub mod io;
+pub mod session;
 
 use crate::cli::io::print;
-use std::io::{ Write, BufRead };
+use std::{
+    fs,
+    io::{ Write, BufRead },
+    path::Path,
+    sync::atomic::{ AtomicBool, Ordering },
+};
 
 use copypasta::{ ClipboardContext, ClipboardProvider };
 
-use crate::{ store::PasswordStore, password::{ Password, PasswordEntry } };
+#[cfg(unix)]
+use base64::{ engine::general_purpose::STANDARD, Engine };
+#[cfg(unix)]
+use zeroize::Zeroizing;
+#[cfg(unix)]
+use crate::{ agent::{ self, Agent, Request, Response }, crypto::KEY_LENGTH };
+#[cfg(unix)]
+use self::args::{ AgentArgs, AgentCommand };
 
-use self::io::read_terminal_input;
+use crate::{
+    audit::{ audit, AuditOptions },
+    backup::{ Backup, Backups },
+    breach::BreachIndex,
+    bundle::Bundle,
+    export::{ self, ExportFormat },
+    import::{ self, ImportFormat, ImportReport },
+    merge::{ self, Conflict, Resolution },
+    config::Config,
+    credential::Credential,
+    crypto::{ self, KdfParams },
+    otp::{ Hotp, Totp },
+    store::{
+        backend::{ StorageBackend, StorageKind },
+        directory::DirectoryBackend,
+        json::JsonFileBackend,
+        PasswordStore,
+    },
+    sync::PullOutcome,
+    tui,
+    password::{
+        Password,
+        PasswordEntry,
+        generator::PasswordPolicy,
+        passphrase::{ Passphrase, PassphrasePolicy },
+        strength::Strength,
+        unix_timestamp,
+    },
+};
 
-/** Get input from the user for the username */
-fn read_username<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> String {
+use self::{
+    args::{
+        AuditArgs,
+        BackupsArgs,
+        BackupsCommand,
+        BreachCheckArgs,
+        CompletionsArgs,
+        ExportArgs,
+        GenerateArgs,
+        GetArgs,
+        GitCredentialArgs,
+        ImportArgs,
+        MergeArgs,
+        PassphraseArgs,
+        SyncArgs,
+        SyncCommand,
+    },
+    io::{ read_terminal_input, read_confirmation, read_number },
+};
+
+/** Whether anything was copied to the clipboard, so it is cleared when the application stops */
+static COPIED_TO_CLIPBOARD: AtomicBool = AtomicBool::new(false);
+
+/** Get input from the user for the username, `None` when the input ended */
+fn read_username<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> Option<String> {
     read_terminal_input(reader, writer, Some("Enter username: "))
 }
 
-/** Get input from the user for the password */
-// fn read_password<R: BufRead, W: Write>(reader: &mut R, writer: &mut W) -> String {
-//     rpassword::prompt_password("Enter password: ").unwrap()
-// }
+/** Get a password from the user without showing it, `None` when it can not be read */
+fn read_password(prompt: &str) -> Option<String> {
+    rpassword
+        ::prompt_password(prompt)
+        .map_err(|err| eprintln!("Unable to read the password: {err}"))
+        .ok()
+}
 
 /**
  * Get input from the user for a service.
  * A service a website, app, or whatever you want to associate a password with.
+ * `None` when the input ended, so the dialog is given up.
  *
  * @param reader: &mut R
  * @param writer: &mut W
  * @param store: &mut PasswordStore
- * @return String
+ * @return Option<String>
  */
 fn read_service_name<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     store: &mut PasswordStore
-) -> String {
+) -> Option<String> {
     loop {
-        let service = read_terminal_input(reader, writer, Some("Enter service name: "));
+        let service = read_terminal_input(reader, writer, Some("Enter service name: "))?;
+        if service.is_empty() {
+            print(writer, "The service name can not be empty");
+            continue;
+        }
         if !store.check_for_duplicate_service_entry(&service) {
-            return service;
+            return Some(service);
         }
         print(writer, "This service already exists, please try again with a unique service name");
     }
 }
 
+/**
+ * Show the strength estimate for a password and check it against the configured minimum score.
+ *
+ * @param writer: &mut W
+ * @param password: &str
+ * @param user_inputs: &[&str]
+ * @param config: &Config
+ * @return bool
+ */
+fn check_password_strength<W: Write>(
+    writer: &mut W,
+    password: &str,
+    user_inputs: &[&str],
+    config: &Config
+) -> bool {
+    let strength = Strength::estimate(password, user_inputs);
+    print(writer, &strength.report());
+
+    if strength.score < config.min_password_score {
+        let message = format!(
+            "This password is too weak, the minimum strength is {}",
+            config.min_password_score
+        );
+        print(writer, &message);
+        return false;
+    }
+
+    true
+}
+
 /**
  * Get input from the user for a password and verify it.
- * This method will keep asking for a password until the user enters the same password twice.
+ * This method will keep asking for a password until the user enters the same password twice
+ * and the password meets the minimum strength from the config. `None` when a password can not
+ * be read or the user gives up by leaving it empty.
  *
  * It also uses the rpassword crate to hide the password input for the users privacy.
  *
  * @param writer: &mut W
+ * @param user_inputs: &[&str]
+ * @param config: &Config
  * @return Option<String>
  */
-fn read_and_confirm_password<W: Write>(writer: &mut W) -> Option<String> {
+fn read_and_confirm_password<W: Write>(
+    writer: &mut W,
+    user_inputs: &[&str],
+    config: &Config
+) -> Option<String> {
     loop {
-        let password = rpassword::prompt_password("Enter password: ").unwrap();
-        let verify_password = rpassword::prompt_password("Please verify password: ").unwrap();
+        let password = read_password("Enter password (leave empty to cancel): ")?;
+        if password.is_empty() {
+            return None;
+        }
+        let verify_password = read_password("Please verify password: ")?;
+
+        if password != verify_password {
+            print(writer, "Unfortunately the entered passwords did not match, please try again");
+            continue;
+        }
 
-        if password == verify_password {
+        if check_password_strength(writer, &password, user_inputs, config) {
             return Some(password);
         }
+    }
+}
 
-        print(writer, "Unfortunately the entered passwords did not match, please try again");
+/** Save an entry made in a dialog, a failure is shown instead of ending the dialog */
+fn save_new_entry<W: Write>(writer: &mut W, store: &mut PasswordStore, entry: PasswordEntry) {
+    if let Err(err) = store.add_and_save_entry(entry) {
+        print(writer, &format!("Error: {err}"));
     }
 }
 
@@ -71,22 +196,136 @@ fn read_and_confirm_password<W: Write>(writer: &mut W) -> Option<String> {
  * @param reader: &mut R
  * @param writer: &mut W
  * @param store: &mut PasswordStore
+ * @param config: &Config
  *
  */
 fn handle_enter_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    store: &mut PasswordStore
+    store: &mut PasswordStore,
+    config: &Config
 ) {
-    let service = read_service_name(reader, writer, store);
-    let username = read_username(reader, writer);
-    let password = read_and_confirm_password(writer);
+    let Some(service) = read_service_name(reader, writer, store) else {
+        return;
+    };
+    let Some(username) = read_username(reader, writer) else {
+        return;
+    };
+    let password = read_and_confirm_password(writer, &[&service, &username], config);
 
     if let Some(password) = password {
         let entry = PasswordEntry::new(service, username, password);
-        store.add_and_save_entry(entry).unwrap();
+        save_new_entry(writer, store, entry);
+    } else {
+        print(writer, "No password was entered, nothing was saved")
+    }
+}
+
+/**
+ * Get the rules for a generated password from the user.
+ * Every question has a default, so pressing enter throughout gives the default policy.
+ * `None` when the input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @return Option<PasswordPolicy>
+ */
+fn read_password_policy<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W
+) -> Option<PasswordPolicy> {
+    let default = PasswordPolicy::default();
+
+    let length = read_number(reader, writer, "Password length", default.length)?;
+    let uppercase = read_confirmation(reader, writer, "Include uppercase letters?", true)?;
+    let lowercase = read_confirmation(reader, writer, "Include lowercase letters?", true)?;
+    let digits = read_confirmation(reader, writer, "Include digits?", true)?;
+    let symbols = read_confirmation(reader, writer, "Include symbols?", false)?;
+    let custom = read_terminal_input(
+        reader,
+        writer,
+        Some("Extra characters to include (leave empty for none): ")
+    )?;
+
+    let mut min_count = |enabled: bool, prompt: &str| {
+        if enabled { read_number(reader, writer, prompt, 0) } else { Some(0) }
+    };
+    let min_uppercase = min_count(uppercase, "Minimum number of uppercase letters")?;
+    let min_lowercase = min_count(lowercase, "Minimum number of lowercase letters")?;
+    let min_digits = min_count(digits, "Minimum number of digits")?;
+    let min_symbols = min_count(symbols, "Minimum number of symbols")?;
+
+    let exclude_ambiguous = read_confirmation(
+        reader,
+        writer,
+        "Exclude ambiguous characters like 0/O and 1/l?",
+        false
+    )?;
+    let forbidden = read_terminal_input(
+        reader,
+        writer,
+        Some("Characters the service does not allow (leave empty for none): ")
+    )?;
+    let no_repeat = read_confirmation(reader, writer, "Avoid repeated characters?", false)?;
+
+    Some(PasswordPolicy {
+        length,
+        uppercase,
+        lowercase,
+        digits,
+        symbols,
+        custom,
+        min_uppercase,
+        min_lowercase,
+        min_digits,
+        min_symbols,
+        exclude_ambiguous,
+        forbidden,
+        no_repeat,
+    })
+}
+
+/**
+ * Get password rules from the user until they describe a password that can be generated.
+ * `None` when the input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @return Option<PasswordPolicy>
+ */
+fn read_valid_password_policy<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W
+) -> Option<PasswordPolicy> {
+    loop {
+        let policy = read_password_policy(reader, writer)?;
+
+        match policy.validate() {
+            Ok(_) => {
+                return Some(policy);
+            }
+            Err(err) => print(writer, &format!("{err}, please try again")),
+        }
+    }
+}
+
+/**
+ * Optionally let the user customize the rules for a generated password.
+ * Customized rules are saved with the entry so later regenerations follow them too.
+ * `None` when the input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @return Option<Option<PasswordPolicy>>
+ */
+fn read_optional_password_policy<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W
+) -> Option<Option<PasswordPolicy>> {
+    if read_confirmation(reader, writer, "Customize the password rules?", false)? {
+        read_valid_password_policy(reader, writer).map(Some)
     } else {
-        print(writer, "Unfortunately the entered passwords did not match, please try again")
+        Some(None)
     }
 }
 
@@ -103,40 +342,128 @@ fn handle_generate_password<R: BufRead, W: Write>(
     writer: &mut W,
     store: &mut PasswordStore
 ) {
-    let service = read_service_name(reader, writer, store);
-    let username = read_username(reader, writer);
-    let password = Password::generate();
-    let entry = PasswordEntry::new(service, username, password);
+    let Some(service) = read_service_name(reader, writer, store) else {
+        return;
+    };
+    let Some(username) = read_username(reader, writer) else {
+        return;
+    };
+    let Some(policy) = read_optional_password_policy(reader, writer) else {
+        return;
+    };
+    let mut entry = PasswordEntry::new(service, username, String::new());
+    entry.policy = policy;
+    entry.password = entry.generate_password().expect("The password policy was validated");
+    print(writer, &Strength::estimate(&entry.password, &[]).report());
 
-    store.add_and_save_entry(entry).unwrap();
+    save_new_entry(writer, store, entry);
+}
+
+/**
+ * Get the rules for a generated passphrase from the user and generate it.
+ * If the entered rules can not be satisfied the user is asked for new ones. `None` when the
+ * input ended.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @return Option<Passphrase>
+ */
+fn read_generated_passphrase<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W
+) -> Option<Passphrase> {
+    let default = PassphrasePolicy::default();
+
+    loop {
+        let word_count = read_number(reader, writer, "Number of words", default.word_count)?;
+        let separator = read_terminal_input(
+            reader,
+            writer,
+            Some(&format!("Separator between words ({}): ", default.separator))
+        )?;
+        let separator = if separator.is_empty() { default.separator.clone() } else { separator };
+        let capitalize = read_confirmation(reader, writer, "Capitalize the words?", false)?;
+        let include_number = read_confirmation(reader, writer, "Include a number?", false)?;
+        let include_symbol = read_confirmation(reader, writer, "Include a symbol?", false)?;
+
+        let policy = PassphrasePolicy {
+            word_count,
+            separator,
+            capitalize,
+            include_number,
+            include_symbol,
+        };
+
+        match Password::generate_passphrase(&policy) {
+            Ok(passphrase) => {
+                return Some(passphrase);
+            }
+            Err(err) => print(writer, &format!("{err}, please try again")),
+        }
+    }
+}
+
+/**
+ * Handle the user input for generating a passphrase.
+ * The method will generate a passphrase, report its entropy and add it to the password store.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ */
+fn handle_generate_passphrase<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore
+) {
+    let Some(service) = read_service_name(reader, writer, store) else {
+        return;
+    };
+    let Some(username) = read_username(reader, writer) else {
+        return;
+    };
+    let Some(passphrase) = read_generated_passphrase(reader, writer) else {
+        return;
+    };
+    let message = format!("Generated a passphrase with {:.1} bits of entropy", passphrase.entropy);
+    print(writer, &message);
+    let entry = PasswordEntry::new(service, username, passphrase.phrase);
+
+    save_new_entry(writer, store, entry);
 }
 
 /**
  * Starts the dialog to add a password.
- * The user can choose to generate a password or enter their own password.
+ * The user can choose to generate a password or passphrase, or enter their own password.
  * The method will then call the appropriate method to handle the user input.
  *
  * @param reader: &mut R
  * @param writer: &mut W
  * @param store: &mut PasswordStore
+ * @param config: &Config
  */
 pub fn handle_add_password<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    store: &mut PasswordStore
+    store: &mut PasswordStore,
+    config: &Config
 ) {
     let message = [
         format!("[{}] -> {} password\n", "1", "Generate"),
         format!("[{}] -> {} password\n", "2", "Enter"),
+        format!("[{}] -> {} passphrase\n", "3", "Generate"),
     ];
 
     let message = message.join("");
-    writeln!(writer, "\nOptions:\n{message}").unwrap();
-    let input = read_terminal_input(reader, writer, None);
+    print(writer, &format!("\nOptions:\n{message}"));
+    let Some(input) = read_terminal_input(reader, writer, None) else {
+        return;
+    };
 
     match input.as_str() {
         "1" | "generate" => handle_generate_password(reader, writer, store),
-        "2" | "enter" => handle_enter_password(reader, writer, store),
+        "2" | "enter" => handle_enter_password(reader, writer, store, config),
+        "3" | "passphrase" => handle_generate_passphrase(reader, writer, store),
         _ => print(writer, "Invalid command"),
     }
 }
@@ -145,6 +472,7 @@ pub fn handle_add_password<R: BufRead, W: Write>(
  * Starts the dialog to get a password.
  * The user can enter a service name and the method will then try to find the password for that service.
  * If the password is found it will be copied to the clipboard.
+ * If the entry has a TOTP or HOTP secret a one-time password is offered afterwards.
  *
  * @param reader: &mut R
  * @param writer: &mut W
@@ -155,18 +483,61 @@ pub fn handle_get_password<R: BufRead, W: Write>(
     writer: &mut W,
     store: &mut PasswordStore
 ) {
-    let service = read_terminal_input(reader, writer, Some("Enter service name: "));
+    let Some(service) = read_terminal_input(reader, writer, Some("Enter service name: ")) else {
+        return;
+    };
 
-    match store.get(&service) {
+    match store.get(&service).cloned() {
         Some(entry) => {
-            let mut ctx = ClipboardContext::new().unwrap();
-            println!("Found entry for {} - password was copied to clipboard!", &service);
-            ctx.set_contents(entry.password.to_owned()).unwrap();
+            match copy_to_clipboard(entry.password.to_owned()) {
+                Ok(()) => {
+                    println!("Found entry for {} - password was copied to clipboard!", &service);
+                }
+                Err(err) => print(writer, err),
+            }
+
+            if let Some(totp) = &entry.totp {
+                show_totp_code(reader, writer, totp);
+            }
+
+            if entry.hotp.is_some() {
+                show_hotp_code(reader, writer, store, &service);
+            }
         }
         None => println!("Could not find an entry for service: {}", &service),
     }
 }
 
+/**
+ * Show the current TOTP code and how long it stays valid, and offer to copy it.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param totp: &Totp
+ */
+fn show_totp_code<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, totp: &Totp) {
+    let now = unix_timestamp();
+
+    let generated = totp.generate(now).and_then(|code| Ok((code, totp.remaining_seconds(now)?)));
+    let (code, remaining) = match generated {
+        Ok(generated) => generated,
+        Err(err) => {
+            print(writer, &format!("Could not generate a TOTP code: {err}"));
+            return;
+        }
+    };
+
+    print(writer, &format!("TOTP code: {code} (valid for {remaining} seconds)"));
+
+    let copy = read_confirmation(reader, writer, "Copy the TOTP code to the clipboard?", true);
+    if copy == Some(true) {
+        match copy_to_clipboard(code) {
+            Ok(_) => print(writer, "The TOTP code was copied to the clipboard"),
+            Err(err) => print(writer, err),
+        }
+    }
+}
+
 /**
  * Handle updating a username for a service.
  * The method will ask the user for a new username and then update the entry in the store.
@@ -174,19 +545,21 @@ pub fn handle_get_password<R: BufRead, W: Write>(
  * @param reader: &mut R
  * @param writer: &mut W
  * @param store: &mut PasswordStore
- * @param service: &str
  * @param entry: PasswordEntry
  */
 fn update_username<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
     store: &mut PasswordStore,
-    service: &str,
     entry: PasswordEntry
 ) {
-    let username = read_terminal_input(reader, writer, Some("Enter new username: "));
-    let password = entry.password.to_owned();
-    let entry = PasswordEntry::new(service.to_owned(), username, password);
+    let mut entry = entry;
+    entry.username = match read_terminal_input(reader, writer, Some("Enter new username: ")) {
+        Some(username) => username,
+        None => {
+            return;
+        }
+    };
     let result = store.update_entry(entry);
 
     if let Err(err) = result {
@@ -198,17 +571,32 @@ fn update_username<R: BufRead, W: Write>(
  * Handle updating a password for a service.
  * The method will ask the user for a new password and then update the entry in the store.
  *
+ * @param writer: &mut W
  * @param store: &mut PasswordStore
- * @param service: &str
  * @param entry: PasswordEntry
+ * @param config: &Config
  */
-fn update_password(store: &mut PasswordStore, service: &str, entry: PasswordEntry) {
-    let username = entry.username.to_owned();
-    let password = rpassword::prompt_password("Enter new password: ").unwrap();
-    let verify_password = rpassword::prompt_password("Please verify password: ").unwrap();
+fn update_password<W: Write>(
+    writer: &mut W,
+    store: &mut PasswordStore,
+    entry: PasswordEntry,
+    config: &Config
+) {
+    let Some(password) = read_password("Enter new password: ") else {
+        return;
+    };
+    let Some(verify_password) = read_password("Please verify password: ") else {
+        return;
+    };
+    let user_inputs = [entry.service.as_str(), entry.username.as_str()];
 
     if password == verify_password {
-        let entry = PasswordEntry::new(service.to_owned(), username, password);
+        if !check_password_strength(writer, &password, &user_inputs, config) {
+            return;
+        }
+
+        let mut entry = entry;
+        entry.change_password(password);
         let result = store.update_entry(entry);
 
         if let Err(err) = result {
@@ -219,6 +607,247 @@ fn update_password(store: &mut PasswordStore, service: &str, entry: PasswordEntr
     }
 }
 
+/**
+ * Copy text to the system clipboard.
+ *
+ * @param contents: String
+ * @return Result<(), &'static str>
+ */
+pub fn copy_to_clipboard(contents: String) -> Result<(), &'static str> {
+    let mut ctx = ClipboardContext::new().map_err(|_| "Could not access the clipboard")?;
+    ctx.set_contents(contents).map_err(|_| "Could not copy to the clipboard")?;
+    COPIED_TO_CLIPBOARD.store(true, Ordering::SeqCst);
+
+    Ok(())
+}
+
+/**
+ * Empty the system clipboard when something was copied to it, so no password is left behind
+ * once the application stops.
+ */
+pub fn clear_clipboard() {
+    if !COPIED_TO_CLIPBOARD.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    let cleared = ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(String::new()));
+    if cleared.is_err() {
+        eprintln!("Could not clear the clipboard");
+    }
+}
+
+/**
+ * Handle rotating the password of a service to a generated one.
+ * The password follows the rules saved with the entry, so the service's restrictions do not have
+ * to be entered again. The new password is copied to the clipboard so it can be set on the
+ * service first, and it is only saved once the user confirms. The old password is kept in the
+ * entry's history.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param entry: PasswordEntry
+ */
+fn rotate_password<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    entry: PasswordEntry
+) {
+    let mut entry = entry;
+
+    let password = match entry.generate_password() {
+        Ok(password) => password,
+        Err(err) => {
+            print(writer, &format!("Error: {err}"));
+            return;
+        }
+    };
+
+    match copy_to_clipboard(password.clone()) {
+        Ok(_) => print(writer, "A new password was generated and copied to the clipboard"),
+        Err(err) => print(writer, &format!("A new password was generated, but: {err}")),
+    }
+
+    let confirmed = read_confirmation(
+        reader,
+        writer,
+        &format!("Save the new password for {}?", entry.service),
+        true
+    );
+
+    if confirmed != Some(true) {
+        print(writer, "The new password was discarded");
+        return;
+    }
+
+    entry.change_password(password);
+
+    if let Err(err) = store.update_entry(entry) {
+        println!("Error: {}", err);
+    }
+}
+
+/**
+ * Handle updating the password rules for a service.
+ * The rules are saved with the entry and used whenever its password is regenerated.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param entry: PasswordEntry
+ */
+fn update_password_policy<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    entry: PasswordEntry
+) {
+    let mut entry = entry;
+    entry.policy = match read_valid_password_policy(reader, writer) {
+        Some(policy) => Some(policy),
+        None => {
+            return;
+        }
+    };
+
+    if let Err(err) = store.update_entry(entry) {
+        println!("Error: {}", err);
+    }
+}
+
+/**
+ * Offer to produce the next HOTP code for a service.
+ * Every code advances the stored counter, so the code is only produced when the user asks for it.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param service: &str
+ */
+fn show_hotp_code<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    service: &str
+) {
+    if read_confirmation(reader, writer, "Generate the next HOTP code?", true) != Some(true) {
+        return;
+    }
+
+    let code = match store.next_hotp_code(service) {
+        Ok(code) => code,
+        Err(err) => {
+            print(writer, &format!("Could not generate an HOTP code: {err}"));
+            return;
+        }
+    };
+
+    print(writer, &format!("HOTP code: {code}"));
+
+    match copy_to_clipboard(code) {
+        Ok(_) => print(writer, "The HOTP code was copied to the clipboard"),
+        Err(err) => print(writer, err),
+    }
+}
+
+/**
+ * Handle setting up HOTP for a service.
+ * The user can paste either the base32 secret or the full `otpauth://` URI from the QR code.
+ * An empty answer removes HOTP from the entry.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param entry: PasswordEntry
+ */
+fn update_hotp<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    entry: PasswordEntry
+) {
+    let mut entry = entry;
+    let input = read_terminal_input(
+        reader,
+        writer,
+        Some("Enter the HOTP secret or otpauth:// URI (leave empty to remove): ")
+    );
+    let Some(input) = input else {
+        return;
+    };
+
+    let hotp = if input.is_empty() {
+        None
+    } else if input.starts_with("otpauth://") {
+        Some(Hotp::from_uri(&input))
+    } else {
+        let Some(counter) = read_number(reader, writer, "Current counter", 0) else {
+            return;
+        };
+        Some(Hotp::new(&input, counter as u64))
+    };
+
+    match hotp.transpose() {
+        Ok(hotp) => {
+            entry.hotp = hotp;
+        }
+        Err(err) => {
+            print(writer, &format!("Error: {err}"));
+            return;
+        }
+    }
+
+    if let Err(err) = store.update_entry(entry) {
+        println!("Error: {}", err);
+    }
+}
+
+/**
+ * Handle setting up TOTP for a service.
+ * The user can paste either the base32 secret or the full `otpauth://` URI from the QR code.
+ * An empty answer removes TOTP from the entry.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param entry: PasswordEntry
+ */
+fn update_totp<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    entry: PasswordEntry
+) {
+    let mut entry = entry;
+    let input = read_terminal_input(
+        reader,
+        writer,
+        Some("Enter the TOTP secret or otpauth:// URI (leave empty to remove): ")
+    );
+    let Some(input) = input else {
+        return;
+    };
+
+    if input.is_empty() {
+        entry.totp = None;
+    } else {
+        match Totp::parse(&input) {
+            Ok(totp) => {
+                entry.totp = Some(totp);
+            }
+            Err(err) => {
+                print(writer, &format!("Error: {err}"));
+                return;
+            }
+        }
+    }
+
+    if let Err(err) = store.update_entry(entry) {
+        println!("Error: {}", err);
+    }
+}
+
 /**
  * Starts the dialog to update a service.
  * The user can enter a service name and the method will ask the user if they want to update the username or password.
@@ -226,34 +855,49 @@ fn update_password(store: &mut PasswordStore, service: &str, entry: PasswordEntr
  * @param reader: &mut R
  * @param writer: &mut W
  * @param store: &mut PasswordStore
+ * @param config: &Config
  */
 pub fn handle_update_service<R: BufRead, W: Write>(
     reader: &mut R,
     writer: &mut W,
-    store: &mut PasswordStore
+    store: &mut PasswordStore,
+    config: &Config
 ) {
     let service = read_terminal_input(
         reader,
         writer,
         Some("Which service would you like to update?\n")
     );
+    let Some(service) = service else {
+        return;
+    };
 
     match store.get(&service) {
         Some(entry) => {
             let message = [
                 format!("[{}] -> {}\n", "1", "Update username"),
                 format!("[{}] -> {}\n", "2", "Update password"),
+                format!("[{}] -> {}\n", "3", "Rotate with generated password"),
+                format!("[{}] -> {}\n", "4", "Update password rules"),
+                format!("[{}] -> {}\n", "5", "Set up TOTP"),
+                format!("[{}] -> {}\n", "6", "Set up HOTP"),
             ];
 
             let message = message.join("");
             println!("\nUpdating service: {}. These are your options:\n{}", &service, message);
-            let input = read_terminal_input(reader, writer, None);
+            let Some(input) = read_terminal_input(reader, writer, None) else {
+                return;
+            };
 
             let entry_clone = entry.clone();
 
             match input.as_str() {
-                "1" | "username" => update_username(reader, writer, store, &service, entry_clone),
-                "2" | "password" => update_password(store, &service, entry_clone),
+                "1" | "username" => update_username(reader, writer, store, entry_clone),
+                "2" | "password" => update_password(writer, store, entry_clone, config),
+                "3" | "rotate" => rotate_password(reader, writer, store, entry_clone),
+                "4" | "rules" => update_password_policy(reader, writer, store, entry_clone),
+                "5" | "totp" => update_totp(reader, writer, store, entry_clone),
+                "6" | "hotp" => update_hotp(reader, writer, store, entry_clone),
                 _ => println!("Invalid command, please try again"),
             };
         }
@@ -263,7 +907,1205 @@ pub fn handle_update_service<R: BufRead, W: Write>(
     }
 }
 
-pub fn handle_list_services(store: &PasswordStore) {
-    println!("Listing all services:");
-    store.list_all();
+/**
+ * Handle delete service
+ * Removes the entry of a service after asking for confirmation.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ */
+pub fn handle_delete_service<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore
+) {
+    let service = read_terminal_input(
+        reader,
+        writer,
+        Some("Which service would you like to delete?\n")
+    );
+    let Some(service) = service else {
+        return;
+    };
+
+    if store.get(&service).is_none() {
+        print(writer, "Could not find an entry for service");
+        return;
+    }
+
+    let prompt = format!("Delete the entry for {service}? It can not be undone");
+    if read_confirmation(reader, writer, &prompt, false) != Some(true) {
+        print(writer, "Nothing was deleted");
+        return;
+    }
+
+    match store.delete_entry(&service) {
+        Ok(()) => print(writer, &format!("Deleted the entry for {service}")),
+        Err(err) => print(writer, err),
+    }
+}
+
+pub fn handle_list_services(store: &PasswordStore) {
+    println!("Listing all services:");
+    store.list_all();
+}
+
+/**
+ * Run a health check over every entry in the store and print the report.
+ *
+ * @param writer: &mut W
+ * @param store: &PasswordStore
+ * @param options: &AuditOptions
+ */
+pub fn handle_audit<W: Write>(writer: &mut W, store: &PasswordStore, options: &AuditOptions) {
+    let report = audit(&store.entries(), options, unix_timestamp());
+    print(writer, &report.report());
+}
+
+/**
+ * Handle the generate subcommand.
+ * Generates a password from the given command line options and prints it, optionally followed
+ * by its strength estimate.
+ *
+ * @param writer: &mut W
+ * @param args: &GenerateArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_generate_command<W: Write>(
+    writer: &mut W,
+    args: &GenerateArgs
+) -> Result<(), &'static str> {
+    let password = Password::generate_with_policy(&args.policy())?;
+    print(writer, &password);
+
+    if args.show_strength {
+        print(writer, &Strength::estimate(&password, &[]).report());
+    }
+
+    Ok(())
+}
+
+/**
+ * Handle the passphrase subcommand.
+ * Generates a passphrase from the given command line options and prints it with its entropy.
+ *
+ * @param writer: &mut W
+ * @param args: &PassphraseArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_passphrase_command<W: Write>(
+    writer: &mut W,
+    args: &PassphraseArgs
+) -> Result<(), &'static str> {
+    let passphrase = Password::generate_passphrase(&args.policy())?;
+    print(writer, &passphrase.phrase);
+    print(writer, &format!("Entropy: {:.1} bits", passphrase.entropy));
+
+    Ok(())
+}
+
+/**
+ * Handle the audit subcommand.
+ * Runs the vault health check with the thresholds from the command line.
+ *
+ * @param writer: &mut W
+ * @param store: &PasswordStore
+ * @param args: &AuditArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_audit_command<W: Write>(
+    writer: &mut W,
+    store: &PasswordStore,
+    args: &AuditArgs
+) -> Result<(), &'static str> {
+    handle_audit(writer, store, &args.options());
+
+    Ok(())
+}
+
+/**
+ * Handle the breach-check subcommand.
+ * Checks the password of every entry against the local hash list and lists the services whose
+ * password has been seen in a breach.
+ *
+ * @param writer: &mut W
+ * @param store: &PasswordStore
+ * @param args: &BreachCheckArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_breach_check_command<W: Write>(
+    writer: &mut W,
+    store: &PasswordStore,
+    args: &BreachCheckArgs
+) -> Result<(), &'static str> {
+    let index = BreachIndex::open(args.hashes.clone())?;
+    let compromised = index.check_entries(&store.entries())?;
+
+    if compromised.is_empty() {
+        print(writer, "None of your passwords were found in the breach list");
+        return Ok(());
+    }
+
+    print(writer, &format!("Compromised passwords: {}", compromised.len()));
+    for (service, count) in compromised.iter() {
+        print(writer, &format!("  - {service} (seen {count} times)"));
+    }
+
+    Ok(())
+}
+
+/**
+ * Print what an import will do: the entries to add, the skipped duplicates and the rows that
+ * could not be read. Passwords are never printed.
+ *
+ * @param writer: &mut W
+ * @param report: &ImportReport
+ */
+fn print_import_report<W: Write>(writer: &mut W, report: &ImportReport) {
+    print(writer, &format!("Entries to import: {}", report.entries.len()));
+    for entry in report.entries.iter() {
+        print(writer, &format!("  - {} ({})", entry.service, entry.username));
+    }
+
+    let skipped = report.duplicates.len();
+    print(writer, &format!("Skipped, service already exists or is repeated: {skipped}"));
+    for service in report.duplicates.iter() {
+        print(writer, &format!("  - {service}"));
+    }
+
+    print(writer, &format!("Rows with errors: {}", report.errors.len()));
+    for error in report.errors.iter() {
+        print(writer, &format!("  - row {}: {}", error.row, error.message));
+    }
+}
+
+/**
+ * Read the passphrase of a backup bundle, hidden from the terminal.
+ * When a new bundle is written the passphrase has to be entered twice.
+ *
+ * @param confirm: bool
+ * @return Result<String, &'static str>
+ */
+fn read_bundle_passphrase(confirm: bool) -> Result<String, &'static str> {
+    let passphrase = rpassword
+        ::prompt_password("Bundle passphrase: ")
+        .map_err(|_| "Unable to read the passphrase")?;
+
+    if passphrase.is_empty() {
+        return Err("The bundle passphrase can not be empty");
+    }
+
+    if confirm {
+        let verify_passphrase = rpassword
+            ::prompt_password("Please verify the bundle passphrase: ")
+            .map_err(|_| "Unable to read the passphrase")?;
+
+        if passphrase != verify_passphrase {
+            return Err("The entered passphrases did not match");
+        }
+    }
+
+    Ok(passphrase)
+}
+
+/**
+ * Read the entries to import from the file, or the directory for a pass store.
+ *
+ * @param args: &ImportArgs
+ * @return Result<ImportReport, String>
+ */
+fn read_import(args: &ImportArgs) -> Result<ImportReport, String> {
+    if args.format == ImportFormat::Pass {
+        return import::pass::read_store(&args.file, args.decrypt_command.as_deref());
+    }
+
+    let content = fs::read_to_string(&args.file).map_err(|_| "Unable to read the import file")?;
+
+    if args.format == ImportFormat::Bundle {
+        let bundle = Bundle::from_json(&content)?;
+        let entries = bundle.open(&read_bundle_passphrase(false)?)?;
+
+        return Ok(ImportReport { entries, ..ImportReport::default() });
+    }
+
+    import::parse(&content, args.format, &args.mapping())
+}
+
+/**
+ * Handle the import subcommand.
+ * Reads the export, skips services that already exist and saves the rest, or only shows the
+ * preview when it is a dry run. With `--replace` the vault is replaced by the imported entries
+ * instead, once the user confirmed how many entries are dropped.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param args: &ImportArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_import_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    args: &ImportArgs
+) -> Result<(), &'static str> {
+    let report = match read_import(args) {
+        Ok(report) if args.replace => import::skip_repeated(report),
+        Ok(report) => import::skip_duplicates(report, store),
+        Err(err) => {
+            print(writer, &err);
+            return Err("The file could not be imported");
+        }
+    };
+
+    print_import_report(writer, &report);
+
+    let removed = store.entries().len();
+    if args.replace {
+        print(writer, &format!("Replacing the vault, {removed} existing entries are removed"));
+    }
+
+    if args.dry_run {
+        print(writer, "Dry run, nothing was saved");
+        return Ok(());
+    }
+
+    if args.replace {
+        let prompt = format!(
+            "Drop all {removed} entries of the vault for the {} imported ones?",
+            report.entries.len()
+        );
+        if read_confirmation(reader, writer, &prompt, false) != Some(true) {
+            print(writer, "Nothing was imported");
+            return Ok(());
+        }
+
+        return store.replace_all(report.entries);
+    }
+
+    // The vault is written once, so the import takes one backup and one commit
+    let imported = report.entries.len();
+    let mut entries: Vec<PasswordEntry> = store.entries().into_iter().cloned().collect();
+    entries.extend(report.entries);
+    store.replace_all(entries)?;
+    print(writer, &format!("Imported {imported} entries"));
+
+    Ok(())
+}
+
+/**
+ * Ask for the master password and check it against the hash in the config.
+ *
+ * @param config: &Config
+ * @return Result<String, &'static str> the master password
+ */
+fn verify_master_password(config: &Config) -> Result<String, &'static str> {
+    let hash = config.master_password_hash
+        .as_deref()
+        .ok_or("No master password is set, set one with the master-password command")?;
+    let password = rpassword
+        ::prompt_password("Master password: ")
+        .map_err(|_| "Unable to read the master password")?;
+
+    if !crypto::verify_password(&password, hash) {
+        return Err("Wrong master password");
+    }
+
+    Ok(password)
+}
+
+/**
+ * Open the store chosen in the config. The encrypted directory vault is unlocked with the
+ * master password, and created with it when it does not exist yet.
+ *
+ * @param config: &Config
+ * @return Result<PasswordStore, &'static str>
+ */
+pub fn open_store(config: &Config) -> Result<PasswordStore, &'static str> {
+    if config.storage != StorageKind::Directory {
+        return PasswordStore::open(config);
+    }
+
+    let directory = config.storage.default_path();
+
+    let key = if DirectoryBackend::exists(&directory) {
+        let password = rpassword
+            ::prompt_password("Master password: ")
+            .map_err(|_| "Unable to read the master password")?;
+        DirectoryBackend::unlock(&directory, &password)?
+    } else {
+        let password = verify_master_password(config)?;
+        DirectoryBackend::create(directory.clone(), &password, KdfParams::default())?;
+        DirectoryBackend::unlock(&directory, &password)?
+    };
+
+    PasswordStore::open_with_key(config, Some(key))
+}
+
+/** What has to be typed to confirm a plaintext export */
+const PLAINTEXT_CONFIRMATION: &str = "export unencrypted";
+
+/**
+ * Make sure a plaintext export is intended: show why the location may be unsafe, ask for the
+ * master password and have the user type a confirmation.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param file: &Path
+ * @return Result<(), &'static str>
+ */
+fn confirm_plaintext_export<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    config: &Config,
+    file: &Path
+) -> Result<(), &'static str> {
+    print(writer, "This export is NOT encrypted, anyone who can read the file sees every password");
+    for warning in export::location_warnings(file).iter() {
+        print(writer, &format!("Warning: {warning}"));
+    }
+
+    verify_master_password(config)?;
+
+    let prompt = format!("Type \"{PLAINTEXT_CONFIRMATION}\" to continue");
+    let confirmation = read_terminal_input(reader, writer, Some(&prompt));
+
+    if confirmation.as_deref() != Some(PLAINTEXT_CONFIRMATION) {
+        return Err("The export was cancelled");
+    }
+
+    Ok(())
+}
+
+/**
+ * Handle the export subcommand.
+ * Writes every entry to the file in the chosen format, readable only by its owner. A bundle
+ * asks for a new passphrase that is only used for that bundle, an unencrypted export has to
+ * be confirmed with the master password first.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &PasswordStore
+ * @param config: &Config
+ * @param args: &ExportArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_export_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &PasswordStore,
+    config: &Config,
+    args: &ExportArgs
+) -> Result<(), &'static str> {
+    if args.format.is_plaintext() {
+        confirm_plaintext_export(reader, writer, config, &args.file)?;
+    }
+
+    let entries = store.entries();
+    let passphrase = match args.format {
+        ExportFormat::Bundle => Some(read_bundle_passphrase(true)?),
+        _ => None,
+    };
+    let content = export::export(&entries, args.format, passphrase.as_deref())?;
+
+    export
+        ::write_private_file(&args.file, &content)
+        .map_err(|_| "Unable to write the export file")?;
+    print(writer, &format!("Exported {} entries to {}", entries.len(), args.file.display()));
+
+    Ok(())
+}
+
+/**
+ * Handle the master-password subcommand.
+ * The current master password has to be entered before it can be changed.
+ *
+ * @param writer: &mut W
+ * @param config: &mut Config
+ * @param config_path: &Path
+ * @return Result<(), &'static str>
+ */
+pub fn handle_master_password_command<W: Write>(
+    writer: &mut W,
+    config: &mut Config,
+    config_path: &Path
+) -> Result<(), &'static str> {
+    let current_password = match config.master_password_hash {
+        Some(_) => Some(verify_master_password(config)?),
+        None => None,
+    };
+
+    let password = rpassword
+        ::prompt_password("New master password: ")
+        .map_err(|_| "Unable to read the master password")?;
+    let verify_password = rpassword
+        ::prompt_password("Please verify the master password: ")
+        .map_err(|_| "Unable to read the master password")?;
+
+    if password != verify_password {
+        return Err("The entered passwords did not match");
+    }
+
+    if password.is_empty() || !check_password_strength(writer, &password, &[], config) {
+        return Err("The master password was not changed");
+    }
+
+    // The directory vault is encrypted with the master password, so its key is re-encrypted
+    let directory = config.storage.default_path();
+    if config.storage == StorageKind::Directory && DirectoryBackend::exists(&directory) {
+        let current_password = current_password.ok_or("The vault has no master password")?;
+        DirectoryBackend::change_master_password(
+            &directory,
+            &current_password,
+            &password,
+            KdfParams::default()
+        )?;
+    }
+
+    config.master_password_hash = Some(crypto::hash_password(&password)?);
+    config.save(config_path)?;
+    print(writer, "The master password was saved");
+
+    Ok(())
+}
+
+/**
+ * Find a backup by its number in the list or by its file name.
+ *
+ * @param backups: &[Backup]
+ * @param name: &str
+ * @return Option<&Backup>
+ */
+fn find_backup<'a>(backups: &'a [Backup], name: &str) -> Option<&'a Backup> {
+    if let Ok(number) = name.parse::<usize>() {
+        return number.checked_sub(1).and_then(|index| backups.get(index));
+    }
+
+    backups.iter().find(|backup| {
+        backup.path.file_name().is_some_and(|file_name| file_name.to_string_lossy() == name)
+    })
+}
+
+/**
+ * Handle the backups subcommand.
+ * Lists the backups with the number of entries in each, or restores one after checking it can
+ * be read and asking for confirmation. Backups are plain copies of the JSON file or the
+ * database, the encrypted directory vault has none.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param args: &BackupsArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_backups_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    args: &BackupsArgs
+) -> Result<(), &'static str> {
+    let backups = store.list_backups()?;
+
+    match &args.command {
+        BackupsCommand::List => {
+            if backups.is_empty() {
+                print(writer, "There are no backups yet");
+            }
+
+            for (index, backup) in backups.iter().enumerate() {
+                let name = backup.path.file_name().unwrap_or_default().to_string_lossy();
+                let entries = match store.verify_backup(&backup.path) {
+                    Ok(count) => format!("{count} entries"),
+                    Err(err) => err.to_string(),
+                };
+                print(writer, &format!("[{}] {name} ({entries})", index + 1));
+            }
+
+            Ok(())
+        }
+        BackupsCommand::Restore { backup } => {
+            let backup = find_backup(&backups, backup).ok_or("Could not find that backup")?;
+            let count = store.verify_backup(&backup.path)?;
+            let prompt = format!(
+                "Replace the vault with {} ({count} entries)?",
+                backup.path.display()
+            );
+
+            if read_confirmation(reader, writer, &prompt, false) != Some(true) {
+                print(writer, "Nothing was restored");
+                return Ok(());
+            }
+
+            let restored = store.restore_backup(&backup.path)?;
+            print(writer, &format!("Restored {restored} entries"));
+
+            Ok(())
+        }
+    }
+}
+
+/**
+ * Open a copy of a vault for a merge. A folder is an encrypted directory vault, it is unlocked
+ * with the master password; anything else is read as the JSON file. The copies of a vault share
+ * their master password, so it is asked for once and only again when it does not unlock a copy.
+ *
+ * @param path: &Path
+ * @param master_password: &mut Option<Zeroizing<String>>
+ * @return Result<Box<dyn StorageBackend>, &'static str>
+ */
+fn open_vault_copy(
+    path: &Path,
+    master_password: &mut Option<Zeroizing<String>>
+) -> Result<Box<dyn StorageBackend>, &'static str> {
+    if !DirectoryBackend::exists(path) {
+        return Ok(Box::new(JsonFileBackend::new(path.to_path_buf())?));
+    }
+
+    if let Some(password) = master_password.as_deref() {
+        if let Ok(key) = DirectoryBackend::unlock(path, password) {
+            return Ok(Box::new(DirectoryBackend::open(path.to_path_buf(), key)?));
+        }
+    }
+
+    let password = Zeroizing::new(
+        rpassword
+            ::prompt_password(format!("Master password of {}: ", path.display()))
+            .map_err(|_| "Unable to read the master password")?
+    );
+    let key = DirectoryBackend::unlock(path, &password)?;
+    *master_password = Some(password);
+
+    Ok(Box::new(DirectoryBackend::open(path.to_path_buf(), key)?))
+}
+
+/**
+ * Read the entries of a copy of a vault, without creating it when it does not exist.
+ *
+ * @param path: &Path
+ * @param master_password: &mut Option<Zeroizing<String>>
+ * @return Result<Vec<PasswordEntry>, &'static str>
+ */
+fn read_vault(
+    path: &Path,
+    master_password: &mut Option<Zeroizing<String>>
+) -> Result<Vec<PasswordEntry>, &'static str> {
+    if !path.exists() {
+        return Err("The vault file does not exist");
+    }
+
+    let mut entries = open_vault_copy(path, master_password)?.load()?;
+    entries.sort_by(|a, b| a.service.cmp(&b.service));
+
+    Ok(entries)
+}
+
+/**
+ * Describe one side of a conflict without showing any secrets.
+ *
+ * @param side: &Option<PasswordEntry>
+ * @param other: &Option<PasswordEntry>
+ * @return String
+ */
+fn describe_conflict_side(side: &Option<PasswordEntry>, other: &Option<PasswordEntry>) -> String {
+    let entry = match side {
+        Some(entry) => entry,
+        None => {
+            return "deleted".to_string();
+        }
+    };
+    let modified = match entry.modified_at {
+        0 => "modified at an unknown time".to_string(),
+        modified_at => {
+            let age_days = unix_timestamp().saturating_sub(modified_at) / 86_400;
+            format!("modified {age_days} days ago")
+        }
+    };
+    let changed = match other {
+        Some(other) => merge::changed_fields(entry, other).join(", "),
+        None => "kept".to_string(),
+    };
+
+    format!("{modified}, {changed}")
+}
+
+/**
+ * Ask which side of a conflict to keep, the side modified last is the default.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param conflict: &Conflict
+ * @return Resolution
+ */
+fn read_resolution<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    conflict: &Conflict
+) -> Resolution {
+    print(writer, &format!("\nConflict in {}", conflict.service()));
+    let ours = describe_conflict_side(&conflict.ours, &conflict.theirs);
+    let theirs = describe_conflict_side(&conflict.theirs, &conflict.ours);
+    print(writer, &format!("[1] Keep ours: {ours}"));
+    print(writer, &format!("[2] Keep theirs: {theirs}"));
+
+    let default = match conflict.newest() {
+        Resolution::Ours => 1,
+        Resolution::Theirs => 2,
+    };
+
+    loop {
+        let choice = read_number(reader, writer, "Which version do you want to keep?", default);
+
+        match choice.unwrap_or(default) {
+            1 => {
+                return Resolution::Ours;
+            }
+            2 => {
+                return Resolution::Theirs;
+            }
+            _ => print(writer, "Please choose 1 or 2"),
+        }
+    }
+}
+
+/**
+ * Handle the merge subcommand.
+ * Merges the two copies, asks how to resolve each conflict and writes the merged vault. The
+ * copies may be JSON files or encrypted directory vaults. When the output is a JSON file that
+ * already exists it is backed up first. Encrypted copies are only merged into an encrypted
+ * vault, so their entries are never written out in plaintext.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param args: &MergeArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_merge_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    config: &Config,
+    args: &MergeArgs
+) -> Result<(), &'static str> {
+    let copies = [Some(&args.ours), Some(&args.theirs), args.base.as_ref()];
+    let encrypted = copies.into_iter().flatten().any(|copy| DirectoryBackend::exists(copy));
+    if encrypted && !DirectoryBackend::exists(&args.output) {
+        return Err("Encrypted vaults are only merged into an encrypted vault, like one of them");
+    }
+
+    let mut master_password = None;
+    let base = match &args.base {
+        Some(base) => Some(read_vault(base, &mut master_password)?),
+        None => None,
+    };
+    let ours = read_vault(&args.ours, &mut master_password)?;
+    let theirs = read_vault(&args.theirs, &mut master_password)?;
+    let result = merge::merge(base.as_deref(), &ours, &theirs);
+
+    print(writer, &format!("Taken from their copy: {}", result.from_theirs));
+    print(writer, &format!("Deleted in one copy: {}", result.deleted));
+    print(writer, &format!("Conflicts: {}", result.conflicts.len()));
+
+    let mut entries = result.entries;
+    for conflict in result.conflicts.into_iter() {
+        let resolution = read_resolution(reader, writer, &conflict);
+        entries.extend(conflict.resolve(resolution));
+    }
+
+    let mut output = match DirectoryBackend::exists(&args.output) {
+        true => open_vault_copy(&args.output, &mut master_password)?,
+        false => {
+            let mut output = JsonFileBackend::new(args.output.clone())?;
+            output.set_backups(Backups::from_config(config, &args.output));
+            Box::new(output)
+        }
+    };
+    output.replace_all(&entries)?;
+    print(writer, &format!("Wrote {} entries to {}", entries.len(), args.output.display()));
+
+    Ok(())
+}
+
+/**
+ * Handle the sync subcommand.
+ * Pushes the commits of the vault to the remote, or pulls the remote into the vault and asks
+ * how to resolve each entry that was changed on both sides.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param store: &mut PasswordStore
+ * @param config: &Config
+ * @param args: &SyncArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_sync_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    config: &Config,
+    args: &SyncArgs
+) -> Result<(), &'static str> {
+    let remote = args.remote.as_deref().unwrap_or(&config.git_remote);
+    let branch = args.branch.as_deref().unwrap_or(&config.git_branch);
+
+    match args.command {
+        SyncCommand::Push => {
+            store.push(remote, branch)?;
+            print(writer, &format!("Pushed the vault to {remote}/{branch}"));
+        }
+        SyncCommand::Pull => {
+            let outcome = store.pull(remote, branch, &mut |conflict| {
+                read_resolution(reader, writer, conflict)
+            })?;
+
+            match outcome {
+                PullOutcome::UpToDate => print(writer, "The vault is up to date"),
+                PullOutcome::FastForward => {
+                    print(writer, &format!("Took the changes from {remote}/{branch}"));
+                }
+                PullOutcome::Merged { from_theirs, deleted, conflicts } => {
+                    print(writer, &format!("Taken from {remote}/{branch}: {from_theirs}"));
+                    print(writer, &format!("Deleted in one copy: {deleted}"));
+                    print(writer, &format!("Conflicts resolved: {conflicts}"));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Send a request to the agent when it runs.
+ *
+ * @param config: &Config
+ * @param request: &Request
+ * @return Result<Option<Response>, &'static str> `None` when no agent runs
+ */
+#[cfg(unix)]
+fn ask_agent(config: &Config, request: &Request) -> Result<Option<Response>, &'static str> {
+    match agent::send(&agent::socket_path(config), request) {
+        Ok(Response { error: Some(error), .. }) => {
+            eprintln!("{error}");
+            Err("The agent could not answer the request")
+        }
+        Ok(response) => Ok(Some(response)),
+        Err(_) => Ok(None),
+    }
+}
+
+/**
+ * Find the entry of a service through the agent, or by opening the vault when no agent runs.
+ *
+ * @param config: &Config
+ * @param service: &str
+ * @return Result<Option<PasswordEntry>, &'static str>
+ */
+fn find_entry(config: &Config, service: &str) -> Result<Option<PasswordEntry>, &'static str> {
+    #[cfg(unix)]
+    if let Some(response) = ask_agent(config, &(Request::Get { service: service.to_string() }))? {
+        return Ok(response.entry);
+    }
+
+    Ok(open_store(config)?.get(service).cloned())
+}
+
+/**
+ * The name of every service, through the agent or by opening the vault when no agent runs.
+ *
+ * @param config: &Config
+ * @return Result<Vec<String>, &'static str>
+ */
+pub fn service_names(config: &Config) -> Result<Vec<String>, &'static str> {
+    #[cfg(unix)]
+    if let Some(response) = ask_agent(config, &Request::List)? {
+        return Ok(response.services);
+    }
+
+    let store = open_store(config)?;
+
+    Ok(store.entries().into_iter().map(|entry| entry.service.clone()).collect())
+}
+
+/**
+ * Handle the get subcommand.
+ * Copies the password of a service to the clipboard, or prints it when asked to.
+ *
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param args: &GetArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_get_command<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    args: &GetArgs
+) -> Result<(), &'static str> {
+    let entry = find_entry(config, &args.service)?.ok_or("Could not find an entry for service")?;
+
+    if args.show {
+        print(writer, &entry.password);
+        return Ok(());
+    }
+
+    copy_to_clipboard(entry.password)?;
+    let message = format!("Found entry for {} - password was copied to clipboard!", entry.service);
+    print(writer, &message);
+
+    Ok(())
+}
+
+/**
+ * Handle the list subcommand, prints the name of every service.
+ *
+ * @param writer: &mut W
+ * @param config: &Config
+ * @return Result<(), &'static str>
+ */
+pub fn handle_list_command<W: Write>(writer: &mut W, config: &Config) -> Result<(), &'static str> {
+    for service in service_names(config)?.iter() {
+        print(writer, service);
+    }
+
+    Ok(())
+}
+
+/**
+ * Handle the completions subcommand, prints the completion script for a shell.
+ *
+ * @param writer: &mut W
+ * @param args: &CompletionsArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_completions_command<W: Write>(
+    writer: &mut W,
+    args: &CompletionsArgs
+) -> Result<(), &'static str> {
+    write!(writer, "{}", completion::script(args.shell))
+        .map_err(|_| "Unable to write the completion script")
+}
+
+/**
+ * Handle the hidden complete-services subcommand the completion scripts run.
+ * Only service names are printed and nothing is asked for: an encrypted vault is read through
+ * a running agent only, and a vault that can not be read completes nothing. Completing never
+ * writes anything, a missing vault is not created and git is left alone.
+ *
+ * @param writer: &mut W
+ * @param config: &Config
+ * @return Result<(), &'static str>
+ */
+pub fn handle_complete_services_command<W: Write>(
+    writer: &mut W,
+    config: &Config
+) -> Result<(), &'static str> {
+    #[cfg(unix)]
+    if let Ok(Some(response)) = ask_agent(config, &Request::List) {
+        response.services.iter().for_each(|service| print(writer, service));
+        return Ok(());
+    }
+
+    if !config.storage.default_path().exists() {
+        return Ok(());
+    }
+
+    // Opening an encrypted vault without its key fails instead of asking for the password
+    let config = Config { git_sync: false, ..config.clone() };
+    if let Ok(store) = PasswordStore::open(&config) {
+        store.entries().iter().for_each(|entry| print(writer, &entry.service));
+    }
+
+    Ok(())
+}
+
+/**
+ * Find the entry for a git credential through the agent, or by opening the vault when no agent
+ * runs.
+ *
+ * @param config: &Config
+ * @param credential: &Credential
+ * @return Result<Option<PasswordEntry>, &'static str>
+ */
+fn find_credential(
+    config: &Config,
+    credential: &Credential
+) -> Result<Option<PasswordEntry>, &'static str> {
+    #[cfg(unix)]
+    if let Some(response) = ask_agent(config, &Request::Credential(credential.clone()))? {
+        return Ok(response.entry);
+    }
+
+    Ok(credential.find(open_store(config)?.entries()).cloned())
+}
+
+/**
+ * Save a credential git used successfully. The matching entry with the same username gets the
+ * password, its old one is kept in the history, and without one a new entry is added that is
+ * marked as saved by the helper.
+ *
+ * @param config: &Config
+ * @param credential: &Credential
+ * @return Result<(), &'static str>
+ */
+fn store_credential(config: &Config, credential: &Credential) -> Result<(), &'static str> {
+    let (Some(username), Some(password)) = (&credential.username, &credential.password) else {
+        return Ok(());
+    };
+
+    let mut store = open_store(config)?;
+
+    match credential.find(store.entries()).cloned() {
+        Some(entry) if entry.password == *password => Ok(()),
+        Some(mut entry) => {
+            entry.change_password(password.clone());
+            store.update_entry(entry)
+        }
+        None => {
+            let service = [credential.service(), format!("{username}@{}", credential.service())]
+                .into_iter()
+                .find(|service| !store.check_for_duplicate_service_entry(service))
+                .ok_or("There is already an entry for this service")?;
+
+            let entry = credential.new_entry(service).ok_or("The credential is incomplete")?;
+            store.add_and_save_entry(entry).map(|_| ())
+        }
+    }
+}
+
+/**
+ * Remove the entries matching a credential git had rejected. Only entries this helper saved
+ * that hold nothing but what git stored and still have the rejected password are removed,
+ * entries the user made or added to are never touched.
+ *
+ * @param config: &Config
+ * @param credential: &Credential
+ * @return Result<(), &'static str>
+ */
+fn erase_credential(config: &Config, credential: &Credential) -> Result<(), &'static str> {
+    let Some(password) = &credential.password else {
+        return Ok(());
+    };
+
+    let mut store = open_store(config)?;
+    let services: Vec<String> = store
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.password == *password && credential.stored_by_git(entry))
+        .map(|entry| entry.service.clone())
+        .collect();
+
+    services.iter().try_for_each(|service| store.delete_entry(service))
+}
+
+/**
+ * Handle the git-credential subcommand, git's credential helper protocol.
+ * `get` answers with the username and password of the entry that matches the credential most
+ * closely, `store` saves a credential that worked and `erase` removes an entry it saved that
+ * was rejected.
+ * Other operations are ignored, as the protocol asks. Nothing but the answer to `get` is
+ * written, git reads it.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param args: &GitCredentialArgs
+ * @return Result<(), &'static str>
+ */
+pub fn handle_git_credential_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    config: &Config,
+    args: &GitCredentialArgs
+) -> Result<(), &'static str> {
+    let operation = args.operation.as_str();
+    if !["get", "store", "erase"].contains(&operation) {
+        return Ok(());
+    }
+
+    // Never start a new vault in the repository git runs the helper in
+    if config.storage != StorageKind::Memory && !config.storage.default_path().exists() {
+        return Err("There is no vault here, pass the folder of the vault with --directory");
+    }
+
+    let credential = Credential::read(reader)?;
+
+    match operation {
+        "get" => {
+            if let Some(entry) = find_credential(config, &credential)? {
+                Credential::write_entry(writer, &entry)?;
+            }
+            Ok(())
+        }
+        "store" => store_credential(config, &credential),
+        _ => erase_credential(config, &credential),
+    }
+}
+
+/**
+ * Handle the tui subcommand, runs the full-screen interface. When it ends the decrypted entries
+ * are wiped and the clipboard is cleared, like when the dialog ends.
+ *
+ * @param store: &mut PasswordStore
+ * @param config: &Config
+ * @return Result<(), &'static str>
+ */
+pub fn handle_tui_command(store: &mut PasswordStore, config: &Config) -> Result<(), &'static str> {
+    let result = tui::run(store, config);
+    session::end(store);
+
+    result
+}
+
+/**
+ * Unlock the vault and start the agent as a background process. The key is handed to the
+ * agent through a pipe, never as an argument or in the environment where other processes
+ * could read it.
+ *
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param socket: &Path
+ * @return Result<(), &'static str>
+ */
+#[cfg(unix)]
+fn start_agent<W: Write>(
+    writer: &mut W,
+    config: &Config,
+    socket: &Path
+) -> Result<(), &'static str> {
+    use std::{
+        os::unix::process::CommandExt,
+        process::{ Command, Stdio },
+        thread,
+        time::{ Duration, Instant },
+    };
+
+    if config.storage != StorageKind::Directory {
+        return Err("The agent only works with the directory storage");
+    }
+
+    if agent::send(socket, &Request::Status).is_ok() {
+        return Err("The agent is already running");
+    }
+
+    let password = Zeroizing::new(
+        rpassword
+            ::prompt_password("Master password: ")
+            .map_err(|_| "Unable to read the master password")?
+    );
+    let directory = config.storage.default_path();
+    let key = Zeroizing::new(DirectoryBackend::unlock(&directory, &password)?);
+    let encoded_key = Zeroizing::new(STANDARD.encode(*key));
+
+    let executable = std::env::current_exe().map_err(|_| "Unable to find the stashpass program")?;
+    let mut child = Command::new(executable)
+        .args(["agent", "serve"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Its own process group, so closing the terminal or Ctrl-C do not stop the agent
+        .process_group(0)
+        .spawn()
+        .map_err(|_| "Unable to start the agent")?;
+
+    let mut stdin = child.stdin.take().ok_or("Unable to start the agent")?;
+    stdin
+        .write_all(encoded_key.as_bytes())
+        .and_then(|_| stdin.write_all(b"\n"))
+        .map_err(|_| "Unable to hand the key to the agent")?;
+    drop(stdin);
+
+    let started = Instant::now();
+    while agent::send(socket, &Request::Status).is_err() {
+        if matches!(child.try_wait(), Ok(Some(_))) {
+            return Err("The agent stopped right after it was started");
+        }
+
+        if started.elapsed() > Duration::from_secs(5) {
+            return Err("The agent did not start in time");
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    print(writer, &format!("The agent is running at {}", socket.display()));
+
+    Ok(())
+}
+
+/**
+ * Run the agent in the foreground with the key read from the reader, as started by
+ * `start_agent`.
+ *
+ * @param reader: &mut R
+ * @param config: &Config
+ * @param socket: &Path
+ * @return Result<(), &'static str>
+ */
+#[cfg(unix)]
+fn serve_agent<R: BufRead>(
+    reader: &mut R,
+    config: &Config,
+    socket: &Path
+) -> Result<(), &'static str> {
+    use zeroize::Zeroize;
+
+    let mut line = Zeroizing::new(String::new());
+    reader.read_line(&mut line).map_err(|_| "Unable to read the key")?;
+    let decoded = Zeroizing::new(
+        STANDARD.decode(line.trim()).map_err(|_| "The key is not valid")?
+    );
+
+    let mut key = [0u8; KEY_LENGTH];
+    if decoded.len() != KEY_LENGTH {
+        return Err("The key is not valid");
+    }
+    key.copy_from_slice(&decoded);
+
+    let idle_timeout = std::time::Duration::from_secs(config.agent_idle_timeout);
+    let agent = Agent::new(config.storage.default_path(), key, idle_timeout);
+    key.zeroize();
+
+    agent?.serve(socket).map_err(|err| {
+        eprintln!("{err}");
+        "The agent stopped with an error"
+    })
+}
+
+/**
+ * Handle the agent subcommand.
+ *
+ * @param reader: &mut R
+ * @param writer: &mut W
+ * @param config: &Config
+ * @param args: &AgentArgs
+ * @return Result<(), &'static str>
+ */
+#[cfg(unix)]
+pub fn handle_agent_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    config: &Config,
+    args: &AgentArgs
+) -> Result<(), &'static str> {
+    let socket = agent::socket_path(config);
+
+    match args.command {
+        AgentCommand::Start => start_agent(writer, config, &socket),
+        AgentCommand::Serve => serve_agent(reader, config, &socket),
+        AgentCommand::Status => {
+            let response = agent::send(&socket, &Request::Status)?;
+            let locks_in = response.locks_in.unwrap_or_default();
+            let status = format!("The agent is running, it locks in {locks_in} seconds if unused");
+            print(writer, &status);
+
+            Ok(())
+        }
+        AgentCommand::Lock => {
+            agent::send(&socket, &Request::Lock)?;
+            print(writer, "The agent was locked");
+
+            Ok(())
+        }
+    }
 }
@@ -0,0 +1,174 @@
+use std::{ sync::{ Condvar, Mutex }, time::{ Duration, Instant } };
+
+use crate::{ config::Config, crypto, store::{ backend::StorageKind, PasswordStore } };
+
+/** How many times the master password may be entered wrong before the session ends */
+const UNLOCK_ATTEMPTS: usize = 3;
+
+#[derive(Debug)]
+struct State {
+    last_used: Instant,
+    finished: bool,
+}
+
+/**
+ * Session
+ * Tracks when the interactive session was last used, so the store can be locked once it sat
+ * idle for too long. `watch` runs in its own thread next to the dialog, which calls `touch`
+ * for every command and `finish` when it ends.
+ */
+#[derive(Debug)]
+pub struct Session {
+    state: Mutex<State>,
+    wake: Condvar,
+    idle_timeout: Duration,
+}
+
+impl Session {
+    pub fn new(idle_timeout: Duration) -> Session {
+        Session {
+            state: Mutex::new(State { last_used: Instant::now(), finished: false }),
+            wake: Condvar::new(),
+            idle_timeout,
+        }
+    }
+
+    /**
+     * Whether the session can lock with the config: it needs an idle timeout and a master
+     * password to unlock it again.
+     *
+     * @param config: &Config
+     * @return bool
+     */
+    pub fn enabled(config: &Config) -> bool {
+        let has_master_password =
+            config.master_password_hash.is_some() || config.storage == StorageKind::Directory;
+
+        config.session_idle_timeout > 0 && has_master_password
+    }
+
+    /** Mark the session as used now */
+    pub fn touch(&self) {
+        self.state.lock().unwrap().last_used = Instant::now();
+        self.wake.notify_all();
+    }
+
+    /** Stop `watch`, the session is over */
+    pub fn finish(&self) {
+        self.state.lock().unwrap().finished = true;
+        self.wake.notify_all();
+    }
+
+    /**
+     * Lock the store whenever the session was not used for the idle timeout, until the session
+     * is finished. The store is only locked between commands, while the dialog does not hold
+     * it.
+     *
+     * @param store: &Mutex<&mut PasswordStore>
+     */
+    pub fn watch(&self, store: &Mutex<&mut PasswordStore>) {
+        let mut state = self.state.lock().unwrap();
+
+        while !state.finished {
+            let idle = state.last_used.elapsed();
+
+            if idle < self.idle_timeout {
+                state = self.wake.wait_timeout(state, self.idle_timeout - idle).unwrap().0;
+                continue;
+            }
+
+            // The store is taken before the state, the same order in which the dialog uses them
+            drop(state);
+            let mut store = store.lock().unwrap();
+            state = self.state.lock().unwrap();
+
+            if !state.finished && state.last_used.elapsed() >= self.idle_timeout {
+                if !store.is_locked() {
+                    store.lock();
+                }
+                drop(store);
+
+                // Nothing to do until the session is used again
+                let last_used = state.last_used;
+                while !state.finished && state.last_used == last_used {
+                    state = self.wake.wait(state).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/**
+ * Ask for the master password until it unlocks the store, a few attempts are allowed.
+ * For a vault that is not encrypted the password is checked against the hash in the config.
+ *
+ * @param config: &Config
+ * @param store: &mut PasswordStore
+ * @return Result<(), &'static str>
+ */
+pub fn unlock(config: &Config, store: &mut PasswordStore) -> Result<(), &'static str> {
+    for _ in 0..UNLOCK_ATTEMPTS {
+        let password = rpassword
+            ::prompt_password("Master password: ")
+            .map_err(|_| "Unable to read the master password")?;
+
+        let verified = match &config.master_password_hash {
+            Some(hash) if config.storage != StorageKind::Directory => {
+                crypto::verify_password(&password, hash)
+            }
+            _ => true,
+        };
+
+        match verified {
+            true => match store.unlock(&password) {
+                Ok(()) => {
+                    return Ok(());
+                }
+                Err(err) => eprintln!("{err}"),
+            }
+            false => eprintln!("Wrong master password"),
+        }
+    }
+
+    Err("The session stays locked")
+}
+
+/**
+ * End the session: the decrypted entries are wiped and the clipboard is cleared if a password
+ * was copied to it.
+ *
+ * @param store: &mut PasswordStore
+ */
+pub fn end(store: &mut PasswordStore) {
+    store.lock();
+    super::clear_clipboard();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ password::PasswordEntry, store::memory::MemoryBackend };
+
+    #[test]
+    fn test_store_locks_when_idle() {
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "pw".to_string());
+        let backend = MemoryBackend::new(vec![entry]);
+        let mut store = PasswordStore::with_backend(Box::new(backend)).unwrap();
+        let session = Session::new(Duration::from_millis(100));
+        let shared = Mutex::new(&mut store);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| session.watch(&shared));
+
+            std::thread::sleep(Duration::from_millis(50));
+            session.touch();
+            assert!(!shared.lock().unwrap().is_locked());
+
+            std::thread::sleep(Duration::from_millis(250));
+            assert!(shared.lock().unwrap().is_locked());
+            assert!(shared.lock().unwrap().entries().is_empty());
+
+            session.finish();
+        });
+    }
+}
@@ -0,0 +1,182 @@
+use std::{ fs::File, io::BufReader, path::{ Path, PathBuf } };
+
+use serde::{ Deserialize, Serialize };
+
+use crate::{ export, password::strength::MAX_SCORE, store::backend::StorageKind };
+
+/**
+ * Config
+ * Settings for the password manager, read from a JSON file next to the password file.
+ * Every setting has a default, so the file and any of its keys may be left out.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /**
+     * Lowest strength score (0-4) accepted for passwords entered by hand.
+     * The default of 0 accepts every password but still shows the strength estimate.
+     */
+    pub min_password_score: u8,
+
+    /**
+     * Argon2 hash of the master password, asked for before sensitive operations such as a
+     * plaintext export. Set with the `master-password` command.
+     */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub master_password_hash: Option<String>,
+
+    /** How many backups of the vault file to keep, 0 turns them off */
+    pub backup_count: usize,
+
+    /** Folder for the backups, a `backups` folder next to the vault file when not set */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_dir: Option<PathBuf>,
+
+    /** Where the vault is kept: `json` (the default), `sqlite`, `memory` or `directory` */
+    pub storage: StorageKind,
+
+    /**
+     * Keep the folder of the vault as a git repository and commit every change, so the vault
+     * can be synced with the `sync` command. Only works with the encrypted `directory` storage.
+     */
+    pub git_sync: bool,
+
+    /** The remote to sync with */
+    pub git_remote: String,
+
+    /** The branch of the remote to sync with */
+    pub git_branch: String,
+
+    /** Socket of the unlock agent, a socket in the user's runtime folder when not set */
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub agent_socket: Option<PathBuf>,
+
+    /** Seconds the agent stays unlocked without being used */
+    pub agent_idle_timeout: u64,
+
+    /**
+     * Seconds without a command before the interactive session locks and asks for the master
+     * password again, 0 turns it off. Only used when there is a master password.
+     */
+    pub session_idle_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            min_password_score: 0,
+            master_password_hash: None,
+            backup_count: 10,
+            backup_dir: None,
+            storage: StorageKind::Json,
+            git_sync: false,
+            git_remote: "origin".to_string(),
+            git_branch: "main".to_string(),
+            agent_socket: None,
+            agent_idle_timeout: 15 * 60,
+            session_idle_timeout: 5 * 60,
+        }
+    }
+}
+
+impl Config {
+    /**
+     * Load the config from file
+     * A missing file gives the default config, a file that can not be parsed is an error so
+     * a typo does not silently turn off a setting. So is a minimum password score no password
+     * can reach.
+     *
+     * @param file_path: PathBuf
+     * @return Result<Config, &'static str>
+     */
+    pub fn load(file_path: PathBuf) -> Result<Config, &'static str> {
+        if !file_path.exists() {
+            return Ok(Config::default());
+        }
+
+        let file = File::open(&file_path).map_err(|_| "Unable to open config file")?;
+        let reader = BufReader::new(file);
+        let config: Config = serde_json
+            ::from_reader(reader)
+            .map_err(|_| "Unable to parse config file")?;
+
+        if config.min_password_score > MAX_SCORE {
+            return Err("The min_password_score in the config can be at most 4");
+        }
+
+        Ok(config)
+    }
+
+    /**
+     * Save the config to file, readable only by its owner since it holds the hash of the
+     * master password.
+     *
+     * @param file_path: &Path
+     * @return Result<(), &'static str>
+     */
+    pub fn save(&self, file_path: &Path) -> Result<(), &'static str> {
+        let content = serde_json::to_string_pretty(self).unwrap();
+
+        export::write_private_file(file_path, &content).map_err(|_| "Unable to write config file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_config_file() {
+        let config = Config::load(PathBuf::from("does_not_exist.json")).unwrap();
+
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_partial_config() {
+        let config: Config = serde_json::from_str(r#"{ "min_password_score": 3 }"#).unwrap();
+
+        assert_eq!(config.min_password_score, 3);
+        assert_eq!(config.backup_count, Config::default().backup_count);
+    }
+
+    #[test]
+    fn test_unreachable_min_password_score() {
+        let file_path = std::env::temp_dir().join("stashpass_test_config_score.json");
+        std::fs::write(&file_path, r#"{ "min_password_score": 5 }"#).unwrap();
+
+        assert!(Config::load(file_path.clone()).is_err());
+        std::fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let file_path = std::env::temp_dir().join("stashpass_test_config.json");
+        let config = Config {
+            min_password_score: 2,
+            master_password_hash: Some("$argon2id$hash".to_string()),
+            backup_count: 3,
+            backup_dir: Some(PathBuf::from("/backups")),
+            storage: StorageKind::Sqlite,
+            git_sync: true,
+            git_remote: "backup".to_string(),
+            git_branch: "vault".to_string(),
+            agent_socket: Some(PathBuf::from("/run/agent.sock")),
+            agent_idle_timeout: 60,
+            session_idle_timeout: 0,
+        };
+
+        config.save(&file_path).unwrap();
+        let loaded = Config::load(file_path.clone()).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&file_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(loaded, config);
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::{ BufRead, Write };
+
+use serde::{ Deserialize, Serialize };
+use url::Url;
+
+use crate::password::PasswordEntry;
+
+/**
+ * Credential
+ * What git asks a credential helper about, or hands it to store or erase. Git sends it as
+ * `key=value` lines ending with an empty line, and reads the answer to `get` in the same format.
+ * The host includes the port, and the path is only sent when git is configured with
+ * `credential.useHttpPath`.
+ */
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Credential {
+    pub protocol: String,
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+/** The note of the entries the helper adds, so it only ever erases its own entries */
+pub const STORED_NOTE: &str = "Saved by the git credential helper";
+
+/** Where a URL points, split up like git splits it */
+#[derive(Debug, PartialEq)]
+struct Location {
+    protocol: String,
+    host: String,
+    path: String,
+    username: Option<String>,
+}
+
+/** A path without slashes around it and without the `.git` of a repository */
+fn normalize_path(path: &str) -> String {
+    let path = path.trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path).trim_end_matches('/').to_string()
+}
+
+/**
+ * Split a URL into its protocol, host with port and path. URLs without a protocol, like
+ * `github.com/org`, are read as https.
+ */
+fn location(url: &str) -> Option<Location> {
+    let url = match Url::parse(url) {
+        Ok(url) if url.has_host() => url,
+        _ => Url::parse(&format!("https://{url}")).ok()?,
+    };
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str()?),
+        None => url.host_str()?.to_string(),
+    };
+
+    Some(Location {
+        protocol: url.scheme().to_string(),
+        host,
+        path: normalize_path(url.path()),
+        username: Some(url.username().to_string()).filter(|username| !username.is_empty()),
+    })
+}
+
+impl Credential {
+    /**
+     * Read a credential from git. Attributes the helper does not use are skipped.
+     *
+     * @param reader: &mut R
+     * @return Result<Credential, &'static str>
+     */
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Credential, &'static str> {
+        let mut credential = Credential::default();
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(|_| "Unable to read the credential")? == 0 {
+                break;
+            }
+
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                break;
+            }
+
+            let (key, value) = line.split_once('=').ok_or("A credential line is not key=value")?;
+            let value = value.to_string();
+
+            match key {
+                "protocol" => {
+                    credential.protocol = value;
+                }
+                "host" => {
+                    credential.host = value;
+                }
+                "path" => {
+                    credential.path = Some(value);
+                }
+                "username" => {
+                    credential.username = Some(value);
+                }
+                "password" => {
+                    credential.password = Some(value);
+                }
+                "url" => {
+                    let location = location(&value).ok_or("The credential URL can not be read")?;
+                    credential.protocol = location.protocol;
+                    credential.host = location.host;
+                    credential.path = Some(location.path).filter(|path| !path.is_empty());
+                    credential.username = location.username.or(credential.username);
+                }
+                _ => {}
+            }
+        }
+
+        if credential.protocol.is_empty() || credential.host.is_empty() {
+            return Err("The credential has no protocol or host");
+        }
+
+        Ok(credential)
+    }
+
+    /**
+     * Answer git with the username and password of an entry. A value with a line break or a NUL
+     * would end the line and add lines of its own to the answer, so nothing is written then.
+     *
+     * @param writer: &mut W
+     * @param entry: &PasswordEntry
+     * @return Result<(), &'static str>
+     */
+    pub fn write_entry<W: Write>(
+        writer: &mut W,
+        entry: &PasswordEntry
+    ) -> Result<(), &'static str> {
+        let unsafe_value = |value: &str| value.contains(['\n', '\0']);
+        if unsafe_value(&entry.username) || unsafe_value(&entry.password) {
+            return Err("The entry contains a line break and can not be given to git");
+        }
+
+        let result = match entry.username.is_empty() {
+            true => Ok(()),
+            false => writeln!(writer, "username={}", entry.username),
+        };
+
+        result
+            .and_then(|_| writeln!(writer, "password={}", entry.password))
+            .map_err(|_| "Unable to answer git")
+    }
+
+    /**
+     * The name of an entry made for the credential: the host, with the path when git sent one.
+     *
+     * @return String
+     */
+    pub fn service(&self) -> String {
+        match self.path.as_deref().map(normalize_path) {
+            Some(path) if !path.is_empty() => format!("{}/{path}", self.host),
+            _ => self.host.clone(),
+        }
+    }
+
+    /**
+     * The URL the credential is for, saved with entries made for it.
+     *
+     * @return String
+     */
+    pub fn url(&self) -> String {
+        format!("{}://{}", self.protocol, self.service())
+    }
+
+    /**
+     * How closely an entry matches the credential, `None` when it does not match.
+     * The protocol and host of the entry's URL have to be the same, and a path in the URL
+     * has to be the path of the credential or a parent of it, so an entry for a whole host
+     * matches every repository on it. An entry without a URL matches when its service is the
+     * host and git asks for https, so its password is never sent unencrypted. When the
+     * credential has a username the entry needs the same one.
+     *
+     * @param entry: &PasswordEntry
+     * @return Option<usize> a higher score is a closer match
+     */
+    pub fn score(&self, entry: &PasswordEntry) -> Option<usize> {
+        if self.username.as_ref().is_some_and(|username| *username != entry.username) {
+            return None;
+        }
+
+        let Some(location) = entry.url.as_deref().and_then(location) else {
+            let https = self.protocol == "https";
+            return (https && entry.service.eq_ignore_ascii_case(&self.host)).then_some(0);
+        };
+
+        if location.protocol != self.protocol || !location.host.eq_ignore_ascii_case(&self.host) {
+            return None;
+        }
+        if location.path.is_empty() {
+            return Some(1);
+        }
+
+        let path = normalize_path(self.path.as_deref().unwrap_or_default());
+        let within = path == location.path || path.starts_with(&format!("{}/", location.path));
+
+        within.then(|| 1 + location.path.split('/').count())
+    }
+
+    /**
+     * A new entry for a credential git stored, marked with `STORED_NOTE`. Its service is taken
+     * by the caller, since it has to be unique in the vault.
+     *
+     * @param service: String
+     * @return Option<PasswordEntry> `None` without a username and password
+     */
+    pub fn new_entry(&self, service: String) -> Option<PasswordEntry> {
+        let username = self.username.clone()?;
+        let mut entry = PasswordEntry::new(service, username, self.password.clone()?);
+        entry.url = Some(self.url());
+        entry.notes = Some(STORED_NOTE.to_string());
+
+        Some(entry)
+    }
+
+    /**
+     * Whether an entry holds nothing but what git stored for the credential: the helper added
+     * it for the credential's URL and no notes, folder, custom fields or one-time passwords were
+     * added to it since.
+     *
+     * @param entry: &PasswordEntry
+     * @return bool
+     */
+    pub fn stored_by_git(&self, entry: &PasswordEntry) -> bool {
+        entry.url.as_deref() == Some(self.url().as_str()) &&
+            entry.notes.as_deref() == Some(STORED_NOTE) &&
+            entry.folder.is_none() &&
+            entry.fields.is_empty() &&
+            entry.totp.is_none() &&
+            entry.hotp.is_none()
+    }
+
+    /**
+     * The entry that matches the credential most closely, the first one by service when
+     * several match equally well.
+     *
+     * @param entries: I
+     * @return Option<&'a PasswordEntry>
+     */
+    pub fn find<'a, I>(&self, entries: I) -> Option<&'a PasswordEntry>
+        where I: IntoIterator<Item = &'a PasswordEntry>
+    {
+        let mut entries: Vec<&PasswordEntry> = entries.into_iter().collect();
+        entries.sort_by(|a, b| a.service.cmp(&b.service));
+
+        let mut best: Option<(usize, &PasswordEntry)> = None;
+        for entry in entries {
+            if let Some(score) = self.score(entry) {
+                if best.is_none_or(|(best_score, _)| score > best_score) {
+                    best = Some((score, entry));
+                }
+            }
+        }
+
+        best.map(|(_, entry)| entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(service: &str, url: Option<&str>, username: &str) -> PasswordEntry {
+        let mut entry = PasswordEntry::new(
+            service.to_string(),
+            username.to_string(),
+            format!("{service}-token")
+        );
+        entry.url = url.map(|url| url.to_string());
+
+        entry
+    }
+
+    #[test]
+    fn test_read_credential() {
+        let input = "protocol=https\r\nhost=git.example.com:8443\ncapability[]=authtype\n\nx=y\n";
+        let credential = Credential::read(&mut input.as_bytes()).unwrap();
+
+        assert_eq!(credential.protocol, "https");
+        assert_eq!(credential.host, "git.example.com:8443");
+        assert_eq!(credential.path, None);
+        assert_eq!(credential.url(), "https://git.example.com:8443");
+
+        let input = "url=https://me@github.com/org/repo.git\n";
+        let credential = Credential::read(&mut input.as_bytes()).unwrap();
+        assert_eq!(credential.host, "github.com");
+        assert_eq!(credential.path.as_deref(), Some("org/repo"));
+        assert_eq!(credential.username.as_deref(), Some("me"));
+        assert_eq!(credential.service(), "github.com/org/repo");
+
+        assert!(Credential::read(&mut "host=github.com\n".as_bytes()).is_err());
+        assert!(Credential::read(&mut "protocol\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_find_closest_entry() {
+        let entries = vec![
+            entry("github", Some("https://github.com"), "me"),
+            entry("work", Some("https://github.com/work/"), "me"),
+            entry("api", Some("https://github.com/work/api.git"), "bot"),
+            entry("gitlab.com", None, "me"),
+            entry("plain", Some("http://github.com"), "me"),
+        ];
+        let find = |input: &str| {
+            let credential = Credential::read(&mut input.as_bytes()).unwrap();
+            credential.find(&entries).map(|entry| entry.service.as_str())
+        };
+
+        assert_eq!(find("protocol=https\nhost=github.com\n"), Some("github"));
+        assert_eq!(find("protocol=https\nhost=github.com\npath=work/site.git\n"), Some("work"));
+        assert_eq!(find("protocol=https\nhost=github.com\npath=work/api.git\n"), Some("api"));
+        assert_eq!(find("protocol=https\nhost=github.com\npath=workshop\n"), Some("github"));
+        assert_eq!(
+            find("protocol=https\nhost=github.com\npath=work/api\nusername=me\n"),
+            Some("work")
+        );
+        assert_eq!(find("protocol=http\nhost=GitHub.com\n"), Some("plain"));
+        assert_eq!(find("protocol=https\nhost=gitlab.com\n"), Some("gitlab.com"));
+        assert_eq!(find("protocol=http\nhost=gitlab.com\n"), None);
+        assert_eq!(find("protocol=https\nhost=bitbucket.org\n"), None);
+        assert_eq!(find("protocol=https\nhost=github.com\nusername=other\n"), None);
+    }
+
+    #[test]
+    fn test_stored_by_git() {
+        let input = "protocol=https\nhost=github.com\npath=org/repo.git\nusername=me\npassword=x\n";
+        let credential = Credential::read(&mut input.as_bytes()).unwrap();
+
+        let mut stored = credential.new_entry(credential.service()).unwrap();
+        assert_eq!(stored.url.as_deref(), Some("https://github.com/org/repo"));
+        assert!(credential.stored_by_git(&stored));
+
+        stored.notes = Some("Recovery codes in the safe".to_string());
+        assert!(!credential.stored_by_git(&stored));
+        let imported = entry("github.com/org/repo", Some("https://github.com/org/repo"), "me");
+        assert!(!credential.stored_by_git(&imported));
+    }
+
+    #[test]
+    fn test_values_with_line_breaks_are_not_written() {
+        let mut written = vec![];
+        let stored = entry("github.com", None, "me");
+        Credential::write_entry(&mut written, &stored).unwrap();
+        let answer = String::from_utf8(written).unwrap();
+        assert_eq!(answer, "username=me\npassword=github.com-token\n");
+
+        let mut written = vec![];
+        let injected = entry("github.com", None, "me\nhost=evil.example");
+        assert!(Credential::write_entry(&mut written, &injected).is_err());
+        let mut nul = entry("github.com", None, "me");
+        nul.password = "pw\0".to_string();
+        assert!(Credential::write_entry(&mut written, &nul).is_err());
+        assert!(written.is_empty());
+    }
+}
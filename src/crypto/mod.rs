@@ -0,0 +1,202 @@
+use argon2::{
+    password_hash::{ PasswordHash, PasswordHasher, PasswordVerifier, SaltString },
+    Algorithm,
+    Argon2,
+    Params,
+    Version,
+};
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use chacha20poly1305::{ aead::{ Aead, KeyInit, Payload }, XChaCha20Poly1305, XNonce };
+use serde::{ Deserialize, Serialize };
+
+/** Length of the derived key in bytes */
+pub const KEY_LENGTH: usize = 32;
+/** XChaCha20-Poly1305 takes a 24 byte nonce, long enough to be picked at random */
+const NONCE_LENGTH: usize = 24;
+const SALT_LENGTH: usize = 16;
+
+/**
+ * Key Derivation Parameters
+ * Everything needed to derive the same key from a passphrase again: Argon2id with its cost
+ * settings and a random salt. They are stored next to the encrypted data, only the passphrase
+ * is secret.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub algorithm: String,
+    /** Memory cost in KiB */
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /** Base64 encoded salt */
+    pub salt: String,
+}
+
+impl Default for KdfParams {
+    /** Argon2id with 64 MiB of memory and 3 passes, and a fresh random salt */
+    fn default() -> Self {
+        KdfParams {
+            algorithm: "argon2id".to_string(),
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+            salt: STANDARD.encode(rand::random::<[u8; SALT_LENGTH]>()),
+        }
+    }
+}
+
+impl KdfParams {
+    /** Cheap parameters for tests, the defaults take too long there */
+    #[cfg(test)]
+    pub(crate) fn fast() -> KdfParams {
+        KdfParams { memory_kib: 64, iterations: 1, ..KdfParams::default() }
+    }
+
+    /**
+     * Derive the encryption key from a passphrase.
+     *
+     * @param passphrase: &str
+     * @return Result<[u8; KEY_LENGTH], &'static str>
+     */
+    pub fn derive_key(&self, passphrase: &str) -> Result<[u8; KEY_LENGTH], &'static str> {
+        if self.algorithm != "argon2id" {
+            return Err("Unsupported key derivation algorithm");
+        }
+
+        let salt = STANDARD.decode(&self.salt).map_err(|_| "The salt is not valid base64")?;
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH)
+        ).map_err(|_| "Invalid key derivation parameters")?;
+
+        let mut key = [0u8; KEY_LENGTH];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|_| "Unable to derive the key")?;
+
+        Ok(key)
+    }
+}
+
+/**
+ * Encrypt data with XChaCha20-Poly1305 under a random nonce.
+ * The associated data is not encrypted but is authenticated, so it can not be changed without
+ * decryption failing.
+ *
+ * @param key: &[u8; KEY_LENGTH]
+ * @param plaintext: &[u8]
+ * @param associated_data: &[u8]
+ * @return Result<(Vec<u8>, Vec<u8>), &'static str> the nonce and the ciphertext
+ */
+pub fn encrypt(
+    key: &[u8; KEY_LENGTH],
+    plaintext: &[u8],
+    associated_data: &[u8]
+) -> Result<(Vec<u8>, Vec<u8>), &'static str> {
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = rand::random::<[u8; NONCE_LENGTH]>();
+    let payload = Payload { msg: plaintext, aad: associated_data };
+
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| "Unable to encrypt the data")?;
+
+    Ok((nonce.to_vec(), ciphertext))
+}
+
+/**
+ * Decrypt data encrypted with `encrypt`.
+ * A wrong key, a changed ciphertext or changed associated data all give the same error.
+ *
+ * @param key: &[u8; KEY_LENGTH]
+ * @param nonce: &[u8]
+ * @param ciphertext: &[u8]
+ * @param associated_data: &[u8]
+ * @return Result<Vec<u8>, &'static str>
+ */
+pub fn decrypt(
+    key: &[u8; KEY_LENGTH],
+    nonce: &[u8],
+    ciphertext: &[u8],
+    associated_data: &[u8]
+) -> Result<Vec<u8>, &'static str> {
+    if nonce.len() != NONCE_LENGTH {
+        return Err("The nonce has the wrong length");
+    }
+
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let payload = Payload { msg: ciphertext, aad: associated_data };
+
+    cipher
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| "Decryption failed, the passphrase is wrong or the data was changed")
+}
+
+/**
+ * Hash a password for storage, as an Argon2id PHC string that includes its salt and cost.
+ * Only used to check a password later, never to derive a key.
+ *
+ * @param password: &str
+ * @return Result<String, &'static str>
+ */
+pub fn hash_password(password: &str) -> Result<String, &'static str> {
+    let salt = SaltString
+        ::encode_b64(&rand::random::<[u8; SALT_LENGTH]>())
+        .map_err(|_| "Unable to encode the salt")?;
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| "Unable to hash the password")
+}
+
+/**
+ * Check a password against a hash from `hash_password`.
+ *
+ * @param password: &str
+ * @param hash: &str
+ * @return bool
+ */
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default().verify_password(password.as_bytes(), &hash).is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_key_is_deterministic() {
+        let params = KdfParams::fast();
+
+        assert_eq!(params.derive_key("correct horse"), params.derive_key("correct horse"));
+        assert_ne!(params.derive_key("correct horse"), params.derive_key("battery staple"));
+        let other_salt = KdfParams::fast();
+        assert_ne!(params.derive_key("correct horse"), other_salt.derive_key("correct horse"));
+    }
+
+    #[test]
+    fn test_encrypt_and_decrypt() {
+        let key = KdfParams::fast().derive_key("passphrase").unwrap();
+        let other_key = KdfParams::fast().derive_key("passphrase").unwrap();
+        let (nonce, ciphertext) = encrypt(&key, b"secret", b"header").unwrap();
+
+        assert_eq!(decrypt(&key, &nonce, &ciphertext, b"header"), Ok(b"secret".to_vec()));
+        assert!(decrypt(&other_key, &nonce, &ciphertext, b"header").is_err());
+        assert!(decrypt(&key, &nonce, &ciphertext, b"changed").is_err());
+    }
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        let hash = hash_password("master password").unwrap();
+
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("master password", &hash));
+        assert!(!verify_password("wrong password", &hash));
+        assert!(!verify_password("master password", "not a hash"));
+    }
+}
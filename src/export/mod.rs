@@ -0,0 +1,244 @@
+use std::{ collections::BTreeSet, env, fs::OpenOptions, io::Write, path::Path };
+
+use crate::{ bundle::Bundle, crypto::KdfParams, keepass, password::PasswordEntry };
+
+/** File formats the vault can be exported to */
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /** KeePass 2 XML, folders become groups */
+    Keepass,
+    /** Encrypted Stashpass backup bundle with its own passphrase */
+    Bundle,
+    /** Unencrypted CSV, one column per field */
+    Csv,
+    /** Unencrypted JSON with every entry in full, including the password history */
+    Json,
+}
+
+impl ExportFormat {
+    /** Whether the export holds the passwords unencrypted */
+    pub fn is_plaintext(&self) -> bool {
+        *self != ExportFormat::Bundle
+    }
+}
+
+/** Folder names of file sync clients, a plaintext export inside one leaves the machine */
+const SYNCED_FOLDERS: [&str; 10] = [
+    "dropbox",
+    "onedrive",
+    "google drive",
+    "googledrive",
+    "icloud",
+    "mobile documents",
+    "nextcloud",
+    "owncloud",
+    "syncthing",
+    "pcloud",
+];
+
+/**
+ * Write entries as CSV with a header row.
+ * The standard fields come first, followed by one column for every custom field name.
+ *
+ * @param entries: &[&PasswordEntry]
+ * @return Result<String, &'static str>
+ */
+fn export_csv(entries: &[&PasswordEntry]) -> Result<String, &'static str> {
+    let field_names: BTreeSet<&String> = entries
+        .iter()
+        .flat_map(|entry| entry.fields.keys())
+        .collect();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    let mut header = vec!["service", "username", "password", "url", "notes", "folder"];
+    header.extend(["totp", "hotp"]);
+    header.extend(field_names.iter().map(|name| name.as_str()));
+    writer.write_record(&header).map_err(|_| "Unable to write the CSV header")?;
+
+    for entry in entries.iter() {
+        let mut record = vec![
+            entry.service.clone(),
+            entry.username.clone(),
+            entry.password.clone(),
+            entry.url.clone().unwrap_or_default(),
+            entry.notes.clone().unwrap_or_default(),
+            entry.folder.clone().unwrap_or_default(),
+            entry.totp.as_ref().map(|totp| totp.to_uri(&entry.service)).unwrap_or_default(),
+            entry.hotp.as_ref().map(|hotp| hotp.to_uri(&entry.service)).unwrap_or_default()
+        ];
+        record.extend(
+            field_names.iter().map(|name| entry.fields.get(*name).cloned().unwrap_or_default())
+        );
+        writer.write_record(&record).map_err(|_| "Unable to write a CSV row")?;
+    }
+
+    let content = writer.into_inner().map_err(|_| "Unable to write the CSV file")?;
+
+    String::from_utf8(content).map_err(|_| "The CSV file is not UTF-8")
+}
+
+/**
+ * Write entries in an export format.
+ * Encrypted formats need a passphrase, the others ignore it.
+ *
+ * @param entries: &[&PasswordEntry]
+ * @param format: ExportFormat
+ * @param passphrase: Option<&str>
+ * @return Result<String, &'static str>
+ */
+pub fn export(
+    entries: &[&PasswordEntry],
+    format: ExportFormat,
+    passphrase: Option<&str>
+) -> Result<String, &'static str> {
+    match format {
+        ExportFormat::Keepass => Ok(keepass::export(entries)),
+        ExportFormat::Bundle => {
+            let passphrase = passphrase.ok_or("A bundle needs a passphrase")?;
+
+            Ok(Bundle::seal(entries, passphrase, KdfParams::default())?.to_json())
+        }
+        ExportFormat::Csv => export_csv(entries),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(entries).unwrap()),
+    }
+}
+
+/**
+ * Reasons why a file should not hold unencrypted passwords: it is inside a folder that is
+ * synced to the cloud, or its folder can be read by other users.
+ *
+ * @param path: &Path
+ * @return Vec<String>
+ */
+pub fn location_warnings(path: &Path) -> Vec<String> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    let folder = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let folder = folder.canonicalize().unwrap_or(folder);
+    let mut warnings = vec![];
+
+    let synced = folder.components().find_map(|component| {
+        let name = component.as_os_str().to_string_lossy().to_lowercase();
+        SYNCED_FOLDERS.iter().find(|synced| name.contains(*synced))
+    });
+
+    if let Some(synced) = synced {
+        let folder = folder.display();
+        warnings.push(format!("{folder} looks like a {synced} folder that is synced"));
+    }
+
+    if is_world_readable(&folder) {
+        warnings.push(format!("{} can be read by other users", folder.display()));
+    }
+
+    warnings
+}
+
+#[cfg(unix)]
+fn is_world_readable(folder: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    folder.metadata().is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_world_readable(_folder: &Path) -> bool {
+    false
+}
+
+/**
+ * Write a file that only its owner can read and write.
+ * An existing file is truncated and has its permissions tightened before anything is written.
+ *
+ * @param path: &Path
+ * @param content: &str
+ * @return std::io::Result<()>
+ */
+pub fn write_private_file(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+
+    #[cfg(unix)]
+    {
+        use std::{ fs::Permissions, os::unix::fs::PermissionsExt };
+        file.set_permissions(Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(content.as_bytes())?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{ self, ColumnMapping, ImportFormat };
+
+    #[test]
+    fn test_csv_export_can_be_imported() {
+        let mut entry = PasswordEntry::new(
+            "github".to_string(),
+            "octocat".to_string(),
+            "pa,ss\"word".to_string()
+        );
+        entry.notes = Some("two\nlines".to_string());
+        entry.fields.insert("PIN".to_string(), "1234".to_string());
+        let other = PasswordEntry::new("mail".to_string(), "me".to_string(), "pw".to_string());
+
+        let content = export(&[&entry, &other], ExportFormat::Csv, None).unwrap();
+
+        let header = "service,username,password,url,notes,folder,totp,hotp,PIN\n";
+        assert!(content.starts_with(header));
+
+        let mapping = ColumnMapping::for_format(ImportFormat::Csv);
+        let report = import::parse(&content, ImportFormat::Csv, &mapping).unwrap();
+
+        assert_eq!(report.entries[0].password, entry.password);
+        assert_eq!(report.entries[0].notes, entry.notes);
+        assert_eq!(report.entries[1].service, "mail");
+    }
+
+    #[test]
+    fn test_json_export() {
+        let entry = PasswordEntry::new("github".to_string(), "me".to_string(), "pw".to_string());
+        let content = export(&[&entry], ExportFormat::Json, None).unwrap();
+        let entries: Vec<PasswordEntry> = serde_json::from_str(&content).unwrap();
+
+        assert_eq!(entries, vec![entry]);
+    }
+
+    #[test]
+    fn test_location_warnings() {
+        let synced = location_warnings(Path::new("/home/me/Dropbox/export.csv"));
+
+        assert!(synced[0].contains("dropbox folder that is synced"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_private_file() {
+        use std::{ fs, os::unix::fs::PermissionsExt };
+
+        let file_path = env::temp_dir().join("stashpass_test_private_export.csv");
+        fs::write(&file_path, "old").unwrap();
+        fs::set_permissions(&file_path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private_file(&file_path, "new").unwrap();
+        let mode = fs::metadata(&file_path).unwrap().permissions().mode();
+        let content = fs::read_to_string(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(content, "new");
+    }
+}
@@ -0,0 +1,523 @@
+pub mod pass;
+
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::{ keepass, otp::Totp, password::PasswordEntry, store::PasswordStore };
+
+/** Export formats of other password managers that can be imported */
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum ImportFormat {
+    Chrome,
+    Firefox,
+    BitwardenCsv,
+    BitwardenJson,
+    #[value(name = "1password")]
+    OnePassword,
+    Lastpass,
+    /** KeePass 2 XML export, groups become folders */
+    Keepass,
+    /** A pass password store directory, entries keep their path as name and folder */
+    Pass,
+    /** An encrypted Stashpass backup bundle */
+    Bundle,
+    /** Any other CSV file, the columns have to be given with a column mapping */
+    Csv,
+}
+
+/**
+ * Column Mapping
+ * Which CSV column holds which part of an entry. Column names are matched case-insensitively.
+ * Every format has its own defaults, any of which can be overridden.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColumnMapping {
+    pub service: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub url: Option<String>,
+    pub notes: Option<String>,
+    pub totp: Option<String>,
+}
+
+impl ColumnMapping {
+    /**
+     * The columns used by the CSV export of a format.
+     *
+     * @param format: ImportFormat
+     * @return ColumnMapping
+     */
+    pub fn for_format(format: ImportFormat) -> ColumnMapping {
+        let columns = match format {
+            ImportFormat::Chrome => ["name", "username", "password", "url", "note", ""],
+            ImportFormat::Firefox => ["", "username", "password", "url", "", ""],
+            ImportFormat::BitwardenCsv | ImportFormat::BitwardenJson =>
+                ["name", "login_username", "login_password", "login_uri", "notes", "login_totp"],
+            ImportFormat::OnePassword =>
+                ["title", "username", "password", "url", "notes", "otpauth"],
+            ImportFormat::Lastpass => ["name", "username", "password", "url", "extra", "totp"],
+            ImportFormat::Csv => ["service", "username", "password", "url", "notes", "totp"],
+            ImportFormat::Keepass | ImportFormat::Pass | ImportFormat::Bundle =>
+                ["", "", "", "", "", ""],
+        };
+        let column = |name: &str| if name.is_empty() { None } else { Some(name.to_string()) };
+
+        ColumnMapping {
+            service: column(columns[0]),
+            username: column(columns[1]),
+            password: column(columns[2]),
+            url: column(columns[3]),
+            notes: column(columns[4]),
+            totp: column(columns[5]),
+        }
+    }
+
+    /**
+     * Replace the default columns with the ones that were given explicitly.
+     *
+     * @param overrides: ColumnMapping
+     * @return ColumnMapping
+     */
+    pub fn with_overrides(self, overrides: ColumnMapping) -> ColumnMapping {
+        ColumnMapping {
+            service: overrides.service.or(self.service),
+            username: overrides.username.or(self.username),
+            password: overrides.password.or(self.password),
+            url: overrides.url.or(self.url),
+            notes: overrides.notes.or(self.notes),
+            totp: overrides.totp.or(self.totp),
+        }
+    }
+}
+
+/** A row that could not be imported, `row` is the 1-based record number in the export */
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+/**
+ * Import Report
+ * The outcome of reading an export: the entries that would be added, the services that were
+ * skipped because they already exist, and the rows that could not be read.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ImportReport {
+    pub entries: Vec<PasswordEntry>,
+    pub duplicates: Vec<String>,
+    pub errors: Vec<RowError>,
+}
+
+/** The fields of a single exported login, before it is turned into an entry */
+#[derive(Debug, Default)]
+struct ImportRow {
+    service: String,
+    username: String,
+    password: String,
+    url: String,
+    notes: String,
+    totp: String,
+}
+
+/**
+ * Derive a service name from a URL, e.g. `https://www.github.com/login` becomes `github.com`.
+ *
+ * @param url: &str
+ * @return Option<String>
+ */
+pub fn service_from_url(url: &str) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_lowercase();
+
+    Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+/** A row read from an export, or why it could not be read */
+type ImportResult = Result<ImportRow, String>;
+
+impl ImportRow {
+    fn into_entry(self) -> Result<PasswordEntry, String> {
+        let service = if self.service.trim().is_empty() {
+            service_from_url(&self.url).ok_or("The row has no name or URL to use as service")?
+        } else {
+            self.service.trim().to_string()
+        };
+
+        if self.password.is_empty() {
+            return Err(format!("The entry for {service} has no password"));
+        }
+
+        let mut entry = PasswordEntry::new(service, self.username, self.password);
+        entry.url = Some(self.url).filter(|url| !url.is_empty());
+        entry.notes = Some(self.notes).filter(|notes| !notes.is_empty());
+
+        if !self.totp.is_empty() {
+            entry.totp = Some(
+                Totp::parse(&self.totp).map_err(|err| format!("Invalid TOTP secret: {err}"))?
+            );
+        }
+
+        Ok(entry)
+    }
+}
+
+/**
+ * Read the rows of a CSV export using the given column mapping.
+ *
+ * @param content: &str
+ * @param mapping: &ColumnMapping
+ * @return Result<Vec<ImportResult>, String>
+ */
+fn read_csv(content: &str, mapping: &ColumnMapping) -> Result<Vec<ImportResult>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(content.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|err| format!("Unable to read the CSV header: {err}"))?
+        .iter()
+        .map(|header| header.trim().to_lowercase())
+        .collect();
+
+    let find = |column: &Option<String>| -> Result<Option<usize>, String> {
+        match column {
+            Some(name) =>
+                headers
+                    .iter()
+                    .position(|header| header == &name.to_lowercase())
+                    .map(Some)
+                    .ok_or(format!("The CSV file has no column named {name}")),
+            None => Ok(None),
+        }
+    };
+
+    let service = find(&mapping.service)?;
+    let username = find(&mapping.username)?;
+    let password = find(&mapping.password)?.ok_or("A password column is required")?;
+    let url = find(&mapping.url)?;
+    let notes = find(&mapping.notes)?;
+    let totp = find(&mapping.totp)?;
+
+    if service.is_none() && url.is_none() {
+        return Err("A service or URL column is required".to_string());
+    }
+
+    let rows = reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|err| format!("Unable to read the row: {err}"))?;
+            let field = |index: Option<usize>| {
+                index.and_then(|index| record.get(index)).unwrap_or("").to_string()
+            };
+
+            Ok(ImportRow {
+                service: field(service),
+                username: field(username),
+                password: field(Some(password)),
+                url: field(url),
+                notes: field(notes),
+                totp: field(totp),
+            })
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenItem {
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    login: Option<BitwardenLogin>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenLogin {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Option<Vec<BitwardenUri>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BitwardenUri {
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+/** Bitwarden item type for logins, other types are cards, identities and secure notes */
+const BITWARDEN_LOGIN: u8 = 1;
+
+/**
+ * Read the items of an unencrypted Bitwarden JSON export.
+ *
+ * @param content: &str
+ * @return Result<Vec<ImportResult>, String>
+ */
+fn read_bitwarden_json(content: &str) -> Result<Vec<ImportResult>, String> {
+    let export: BitwardenExport = serde_json
+        ::from_str(content)
+        .map_err(|err| format!("Unable to parse the Bitwarden export: {err}"))?;
+
+    let rows = export.items
+        .into_iter()
+        .map(|item| {
+            let login = match item.login {
+                Some(login) if item.kind == BITWARDEN_LOGIN => login,
+                _ => {
+                    return Err(format!("{} is not a login and was skipped", item.name));
+                }
+            };
+            let url = login.uris
+                .unwrap_or_default()
+                .into_iter()
+                .find_map(|uri| uri.uri)
+                .unwrap_or_default();
+
+            Ok(ImportRow {
+                service: item.name,
+                username: login.username.unwrap_or_default(),
+                password: login.password.unwrap_or_default(),
+                url,
+                notes: item.notes.unwrap_or_default(),
+                totp: login.totp.unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/**
+ * Read an export of another password manager into entries.
+ * Rows that can not be read are collected as errors instead of failing the whole import, only
+ * a file that can not be read at all is an error.
+ *
+ * @param content: &str
+ * @param format: ImportFormat
+ * @param mapping: &ColumnMapping
+ * @return Result<ImportReport, String>
+ */
+pub fn parse(
+    content: &str,
+    format: ImportFormat,
+    mapping: &ColumnMapping
+) -> Result<ImportReport, String> {
+    let rows = match format {
+        ImportFormat::BitwardenJson => read_bitwarden_json(content)?,
+        ImportFormat::Keepass => return keepass::parse(content),
+        ImportFormat::Pass => {
+            return Err("A pass store is a directory, use pass::read_store".to_string());
+        }
+        ImportFormat::Bundle => {
+            return Err("A bundle is encrypted, use Bundle::open".to_string());
+        }
+        _ => read_csv(content, mapping)?,
+    };
+
+    let mut report = ImportReport::default();
+
+    for (index, row) in rows.into_iter().enumerate() {
+        match row.and_then(ImportRow::into_entry) {
+            Ok(entry) => report.entries.push(entry),
+            Err(message) => report.errors.push(RowError { row: index + 1, message }),
+        }
+    }
+
+    Ok(report)
+}
+
+/**
+ * Move entries whose service already exists in the store, or earlier in the same import, to the
+ * duplicates so they are not overwritten.
+ *
+ * @param report: ImportReport
+ * @param store: &PasswordStore
+ * @return ImportReport
+ */
+pub fn skip_duplicates(report: ImportReport, store: &PasswordStore) -> ImportReport {
+    skip_services(report, |service| store.check_for_duplicate_service_entry(service))
+}
+
+/**
+ * Move entries whose service appeared earlier in the same import to the duplicates, for an
+ * import that replaces the whole vault.
+ *
+ * @param report: ImportReport
+ * @return ImportReport
+ */
+pub fn skip_repeated(report: ImportReport) -> ImportReport {
+    skip_services(report, |_| false)
+}
+
+fn skip_services(report: ImportReport, exists: impl Fn(&str) -> bool) -> ImportReport {
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    let mut duplicates = report.duplicates;
+
+    for entry in report.entries.into_iter() {
+        if exists(&entry.service) || !seen.insert(entry.service.clone()) {
+            duplicates.push(entry.service);
+        } else {
+            entries.push(entry);
+        }
+    }
+
+    ImportReport {
+        entries,
+        duplicates,
+        errors: report.errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_default(content: &str, format: ImportFormat) -> ImportReport {
+        parse(content, format, &ColumnMapping::for_format(format)).unwrap()
+    }
+
+    #[test]
+    fn test_chrome_csv() {
+        let content = "name,url,username,password,note\n\
+            github.com,https://github.com/login,alice,secret,\n\
+            ,https://www.example.com/,bob,hunter2,work account\n";
+
+        let report = parse_default(content, ImportFormat::Chrome);
+
+        assert_eq!(report.errors, vec![]);
+        assert_eq!(report.entries[0].service, "github.com");
+        assert_eq!(report.entries[0].url.as_deref(), Some("https://github.com/login"));
+        assert_eq!(report.entries[1].service, "example.com");
+        assert_eq!(report.entries[1].notes.as_deref(), Some("work account"));
+    }
+
+    #[test]
+    fn test_firefox_csv() {
+        let content = "\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\"\n\
+            \"https://accounts.example.org\",\"carol\",\"pa,ss\",,\"\",\"{1}\"\n";
+
+        let report = parse_default(content, ImportFormat::Firefox);
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].service, "accounts.example.org");
+        assert_eq!(report.entries[0].password, "pa,ss");
+    }
+
+    #[test]
+    fn test_bitwarden_csv_with_totp() {
+        let content = "folder,favorite,type,name,notes,fields,reprompt,login_uri,login_username,login_password,login_totp\n\
+            ,,login,GitLab,,,0,https://gitlab.com,dave,pw1,GEZDGNBVGY3TQOJQ\n\
+            ,,login,Broken,,,0,,erin,pw2,not base32!\n";
+
+        let report = parse_default(content, ImportFormat::BitwardenCsv);
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].totp.as_ref().unwrap().secret, "GEZDGNBVGY3TQOJQ");
+        assert_eq!(report.errors[0].row, 2);
+    }
+
+    #[test]
+    fn test_bitwarden_json() {
+        let content = r#"{
+            "folders": [],
+            "items": [
+                {
+                    "type": 1,
+                    "name": "Mastodon",
+                    "notes": null,
+                    "login": {
+                        "username": "frank",
+                        "password": "toot",
+                        "totp": null,
+                        "uris": [{ "match": null, "uri": "https://mastodon.social" }]
+                    }
+                },
+                { "type": 2, "name": "A secure note", "secureNote": { "type": 0 } }
+            ]
+        }"#;
+
+        let report = parse_default(content, ImportFormat::BitwardenJson);
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].url.as_deref(), Some("https://mastodon.social"));
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_1password_and_lastpass_csv() {
+        let one_password = "Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\n\
+            Bank,https://bank.example,grace,money,,false,false,,\n";
+        let lastpass = "url,username,password,totp,extra,name,grouping,fav\n\
+            https://news.example,heidi,read,,,News,Media,0\n\
+            https://empty.example,ivan,,,,Empty,,0\n";
+
+        let one_password = parse_default(one_password, ImportFormat::OnePassword);
+        let lastpass = parse_default(lastpass, ImportFormat::Lastpass);
+
+        assert_eq!(one_password.entries[0].service, "Bank");
+        assert_eq!(lastpass.entries[0].service, "News");
+        assert_eq!(lastpass.errors[0].message, "The entry for Empty has no password");
+    }
+
+    #[test]
+    fn test_custom_column_mapping() {
+        let content = "Site,Login,Secret\nwiki,judy,pw\n";
+        let mapping = ColumnMapping {
+            service: Some("Site".to_string()),
+            username: Some("Login".to_string()),
+            password: Some("Secret".to_string()),
+            ..ColumnMapping::default()
+        };
+
+        let report = parse(content, ImportFormat::Csv, &mapping).unwrap();
+        let default_mapping = ColumnMapping::for_format(ImportFormat::Csv);
+        let missing = parse(content, ImportFormat::Csv, &default_mapping);
+
+        assert_eq!(report.entries[0].service, "wiki");
+        assert_eq!(report.entries[0].username, "judy");
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_skip_duplicates() {
+        let file_path = std::env::temp_dir().join("stashpass_test_import_duplicates.json");
+        let _ = std::fs::remove_file(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+        store
+            .add_and_save_entry(
+                PasswordEntry::new("github.com".to_string(), "me".to_string(), "pw".to_string())
+            )
+            .unwrap();
+
+        let content = "name,url,username,password,note\n\
+            github.com,,alice,secret,\n\
+            gitlab.com,,alice,secret,\n\
+            gitlab.com,,alice,other,\n";
+        let report = skip_duplicates(parse_default(content, ImportFormat::Chrome), &store);
+        std::fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.duplicates, vec!["github.com".to_string(), "gitlab.com".to_string()]);
+
+        let report = skip_repeated(parse_default(content, ImportFormat::Chrome));
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.duplicates, vec!["gitlab.com".to_string()]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{ fs, path::{ Path, PathBuf }, process::Command };
+
+use crate::{
+    import::{ ImportReport, RowError },
+    otp::{ Hotp, Totp },
+    password::PasswordEntry,
+};
+
+/** Keys that hold the username in a pass entry, the first one found is used */
+const USERNAME_KEYS: [&str; 4] = ["login", "username", "user", "email"];
+const URL_KEYS: [&str; 3] = ["url", "website", "site"];
+const TOTP_KEYS: [&str; 2] = ["totp", "otp"];
+const NOTES_KEYS: [&str; 2] = ["notes", "comment"];
+
+/** Whether a file is a pass entry: encrypted with `.gpg`, or decrypted already with `.txt` */
+fn is_entry_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "gpg" || extension == "txt")
+}
+
+/**
+ * Collect the entry files of a password store, sorted by path.
+ * Hidden files and directories like `.git` and `.gpg-id` are skipped, and so is every file
+ * that is not an entry, like a README.
+ *
+ * @param directory: &Path
+ * @param files: &mut Vec<PathBuf>
+ * @return Result<(), String>
+ */
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let read_error = |err| format!("Unable to read {}: {err}", directory.display());
+    let mut paths: Vec<PathBuf> = fs
+        ::read_dir(directory)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .map_err(read_error)?;
+    paths.sort();
+
+    for path in paths.into_iter() {
+        let hidden = path
+            .file_name()
+            .map(|name| name.to_string_lossy().starts_with('.'))
+            .unwrap_or(true);
+
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if is_entry_file(&path) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * Read the decrypted content of an entry file.
+ * Files ending in `.gpg` are passed as the last argument to the decryption command, which has to
+ * print the plaintext. Any other file is taken to be decrypted already.
+ *
+ * @param path: &Path
+ * @param decrypt_command: Option<&str>
+ * @return Result<String, String>
+ */
+fn read_content(path: &Path, decrypt_command: Option<&str>) -> Result<String, String> {
+    let encrypted = path.extension().is_some_and(|extension| extension == "gpg");
+
+    if !encrypted {
+        return fs::read_to_string(path).map_err(|err| format!("Unable to read the file: {err}"));
+    }
+
+    let mut words = decrypt_command
+        .ok_or("The file is encrypted and no decryption command was given")?
+        .split_whitespace();
+    let program = words.next().ok_or("The decryption command is empty")?;
+
+    let output = Command::new(program)
+        .args(words)
+        .arg(path)
+        .output()
+        .map_err(|err| format!("Unable to run {program}: {err}"))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Decryption failed: {}", stderr.trim()));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| "The decrypted content is not UTF-8".to_string())
+}
+
+/**
+ * Turn the content of a pass entry into a password entry.
+ * The first line is the password. The remaining `key: value` lines fill in the username, URL,
+ * TOTP and notes when the key is a common name for them, any other key becomes a field.
+ * Lines that are not `key: value` pairs are kept as notes.
+ *
+ * @param service: String
+ * @param content: &str
+ * @return Result<PasswordEntry, String>
+ */
+pub fn parse_entry(service: String, content: &str) -> Result<PasswordEntry, String> {
+    let mut lines = content.lines();
+    let password = lines.next().unwrap_or("").to_string();
+
+    if password.is_empty() {
+        return Err(format!("The entry for {service} has no password"));
+    }
+
+    let mut entry = PasswordEntry::new(service, String::new(), password);
+    let mut notes = vec![];
+
+    for line in lines.map(str::trim).filter(|line| !line.is_empty()) {
+        if line.starts_with("otpauth://hotp") {
+            entry.hotp = Some(
+                Hotp::from_uri(line).map_err(|err| format!("Invalid HOTP URI: {err}"))?
+            );
+            continue;
+        }
+
+        let bare_key = if line.starts_with("otpauth://") {
+            Some("totp")
+        } else if line.starts_with("http://") || line.starts_with("https://") {
+            Some("url")
+        } else {
+            None
+        };
+
+        if let Some(key) = bare_key {
+            notes.extend(apply_field(&mut entry, key, line)?);
+            continue;
+        }
+
+        match line.split_once(':') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                notes.extend(apply_field(&mut entry, key.trim(), value.trim())?);
+            }
+            _ => notes.push(line.to_string()),
+        }
+    }
+
+    if !notes.is_empty() {
+        entry.notes = Some(notes.join("\n"));
+    }
+
+    Ok(entry)
+}
+
+/**
+ * Store a `key: value` pair on the entry. Returns the value when it belongs in the notes.
+ *
+ * @param entry: &mut PasswordEntry
+ * @param key: &str
+ * @param value: &str
+ * @return Result<Option<String>, String>
+ */
+fn apply_field(
+    entry: &mut PasswordEntry,
+    key: &str,
+    value: &str
+) -> Result<Option<String>, String> {
+    let name = key.to_lowercase();
+
+    if USERNAME_KEYS.contains(&name.as_str()) && entry.username.is_empty() {
+        entry.username = value.to_string();
+    } else if URL_KEYS.contains(&name.as_str()) && entry.url.is_none() {
+        entry.url = Some(value.to_string());
+    } else if TOTP_KEYS.contains(&name.as_str()) && entry.totp.is_none() {
+        entry.totp = Some(Totp::parse(value).map_err(|err| format!("Invalid TOTP secret: {err}"))?);
+    } else if NOTES_KEYS.contains(&name.as_str()) {
+        return Ok(Some(value.to_string()));
+    } else {
+        entry.fields.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(None)
+}
+
+/**
+ * Read every entry of a pass password store.
+ * An entry is named by its path below the store, like pass names it, and the path of its
+ * folder becomes the folder. So `Work/github.com.gpg` is imported as `Work/github.com` in the
+ * `Work` folder, and entries with the same file name in different folders stay apart.
+ *
+ * @param directory: &Path
+ * @param decrypt_command: Option<&str>
+ * @return Result<ImportReport, String>
+ */
+pub fn read_store(directory: &Path, decrypt_command: Option<&str>) -> Result<ImportReport, String> {
+    if !directory.is_dir() {
+        return Err(format!("{} is not a password store directory", directory.display()));
+    }
+
+    let mut files = vec![];
+    collect_files(directory, &mut files)?;
+
+    let mut report = ImportReport::default();
+
+    for (index, path) in files.iter().enumerate() {
+        let mut relative = path.strip_prefix(directory).unwrap_or(path).to_path_buf();
+        relative.set_extension("");
+        let service = relative.to_string_lossy().replace('\\', "/");
+        let folder = relative
+            .parent()
+            .map(|parent| parent.to_string_lossy().replace('\\', "/"))
+            .filter(|parent| !parent.is_empty());
+
+        let entry = read_content(path, decrypt_command).and_then(|content|
+            parse_entry(service, &content)
+        );
+
+        match entry {
+            Ok(mut entry) => {
+                entry.folder = folder;
+                report.entries.push(entry);
+            }
+            Err(message) => {
+                let message = format!("{}: {message}", relative.display());
+                report.errors.push(RowError { row: index + 1, message });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        let content = "hunter2\nlogin: alice\nurl: https://github.com\n\
+            otpauth://totp/GitHub?secret=GEZDGNBVGY3TQOJQ\nPIN: 1234\nrecovery codes in the safe\n";
+        let entry = parse_entry("github.com".to_string(), content).unwrap();
+
+        assert_eq!(entry.password, "hunter2");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.url.as_deref(), Some("https://github.com"));
+        assert_eq!(entry.totp.unwrap().secret, "GEZDGNBVGY3TQOJQ");
+        assert_eq!(entry.fields.get("PIN").map(String::as_str), Some("1234"));
+        assert_eq!(entry.notes.as_deref(), Some("recovery codes in the safe"));
+
+        assert!(parse_entry("empty".to_string(), "\nlogin: alice").is_err());
+    }
+
+    #[test]
+    fn test_read_store() {
+        let directory = std::env::temp_dir().join("stashpass_test_pass_store");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("Work/Servers")).unwrap();
+        fs::create_dir_all(directory.join(".git")).unwrap();
+        fs::write(directory.join(".gpg-id"), "ABCDEF").unwrap();
+        fs::write(directory.join(".git/config"), "[core]").unwrap();
+        fs::write(directory.join("README.md"), "My passwords").unwrap();
+        fs::write(directory.join("email.txt"), "secret\nuser: me@example.com").unwrap();
+        fs::write(directory.join("Work/email.txt"), "work-secret").unwrap();
+        let database = "pg-password\nusername: postgres";
+        fs::write(directory.join("Work/Servers/db.gpg"), database).unwrap();
+
+        let plain = read_store(&directory, None).unwrap();
+        let services: Vec<&str> = plain.entries
+            .iter()
+            .map(|entry| entry.service.as_str())
+            .collect();
+        assert_eq!(services, vec!["Work/email", "email"]);
+        assert_eq!(plain.entries[0].folder.as_deref(), Some("Work"));
+        assert_eq!(plain.entries[1].username, "me@example.com");
+        assert_eq!(plain.errors.len(), 1);
+        assert!(plain.errors[0].message.starts_with("Work/Servers/db:"));
+
+        // `cat` stands in for `gpg --decrypt`, the test file is not actually encrypted
+        let decrypted = read_store(&directory, Some("cat")).unwrap();
+        assert_eq!(decrypted.errors, vec![]);
+        assert_eq!(decrypted.entries[0].service, "Work/Servers/db");
+        assert_eq!(decrypted.entries[0].password, "pg-password");
+        assert_eq!(decrypted.entries[0].username, "postgres");
+        assert_eq!(decrypted.entries[0].folder.as_deref(), Some("Work/Servers"));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use roxmltree::{ Document, Node };
+
+use crate::{
+    import::{ service_from_url, ImportReport, RowError },
+    otp::{ Hotp, Totp },
+    password::PasswordEntry,
+};
+
+/** Name of the top-level group in exported files */
+const ROOT_GROUP: &str = "Stashpass";
+/** KeePass keeps deleted entries in this group, they are not imported */
+const RECYCLE_BIN: &str = "Recycle Bin";
+/** KeePassXC stores TOTP and HOTP secrets as an `otpauth://` URI under this key */
+const OTP_KEY: &str = "otp";
+/** The standard KeePass fields, every other string of an entry is a custom field */
+const STANDARD_KEYS: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+
+fn child<'a>(node: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> String {
+    child(node, name)
+        .and_then(|child| child.text())
+        .unwrap_or("")
+        .to_string()
+}
+
+/**
+ * Read the strings of a KeePass entry into a password entry.
+ * Groups are mapped to the folder path, custom strings to fields.
+ *
+ * @param node: Node
+ * @param folder: Option<String>
+ * @return Result<PasswordEntry, String>
+ */
+fn read_entry(node: Node, folder: Option<String>) -> Result<PasswordEntry, String> {
+    let mut strings = BTreeMap::new();
+
+    for string in node.children().filter(|child| child.has_tag_name("String")) {
+        strings.insert(child_text(string, "Key"), child_text(string, "Value"));
+    }
+
+    let mut take = |key: &str| strings.remove(key).unwrap_or_default();
+    let title = take("Title");
+    let username = take("UserName");
+    let password = take("Password");
+    let url = take("URL");
+    let notes = take("Notes");
+    let otp = take(OTP_KEY);
+
+    let service = if title.trim().is_empty() {
+        service_from_url(&url).ok_or("The entry has no title or URL to use as service")?
+    } else {
+        title.trim().to_string()
+    };
+
+    let mut entry = PasswordEntry::new(service, username, password);
+    entry.url = Some(url).filter(|url| !url.is_empty());
+    entry.notes = Some(notes).filter(|notes| !notes.is_empty());
+    entry.folder = folder;
+    entry.fields = strings;
+
+    if otp.starts_with("otpauth://hotp") {
+        entry.hotp = Some(Hotp::from_uri(&otp).map_err(|err| format!("Invalid HOTP URI: {err}"))?);
+    } else if !otp.is_empty() {
+        entry.totp = Some(Totp::parse(&otp).map_err(|err| format!("Invalid TOTP secret: {err}"))?);
+    }
+
+    Ok(entry)
+}
+
+/**
+ * Read the entries of a group and all of its subgroups.
+ *
+ * @param group: Node
+ * @param folder: Option<String>
+ * @param report: &mut ImportReport
+ * @param row: &mut usize
+ */
+fn read_group(group: Node, folder: Option<String>, report: &mut ImportReport, row: &mut usize) {
+    for node in group.children().filter(|node| node.is_element()) {
+        if node.has_tag_name("Entry") {
+            *row += 1;
+
+            match read_entry(node, folder.clone()) {
+                Ok(entry) => report.entries.push(entry),
+                Err(message) => report.errors.push(RowError { row: *row, message }),
+            }
+        } else if node.has_tag_name("Group") {
+            let name = child_text(node, "Name");
+
+            if name == RECYCLE_BIN {
+                continue;
+            }
+
+            let subfolder = match &folder {
+                Some(folder) => format!("{folder}/{name}"),
+                None => name,
+            };
+            read_group(node, Some(subfolder), report, row);
+        }
+    }
+}
+
+/**
+ * Read a KeePass 2 XML export into entries.
+ * The top-level group is the database itself, so only the groups below it become folders.
+ *
+ * @param content: &str
+ * @return Result<ImportReport, String>
+ */
+pub fn parse(content: &str) -> Result<ImportReport, String> {
+    let document = Document::parse(content).map_err(|err| format!("Unable to parse the XML: {err}"))?;
+    let keepass = document.root_element();
+
+    if !keepass.has_tag_name("KeePassFile") {
+        return Err("The file is not a KeePass XML export".to_string());
+    }
+
+    let root_group = child(keepass, "Root")
+        .and_then(|root| child(root, "Group"))
+        .ok_or("The KeePass export has no root group")?;
+
+    let mut report = ImportReport::default();
+    read_group(root_group, None, &mut report, &mut 0);
+
+    Ok(report)
+}
+
+/**
+ * Escape text for use in XML element content.
+ *
+ * @param text: &str
+ * @return String
+ */
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/** A random KeePass UUID: 16 bytes, base64 encoded */
+fn uuid() -> String {
+    STANDARD.encode(rand::random::<[u8; 16]>())
+}
+
+/** Entries grouped by folder, mirroring the KeePass group tree */
+#[derive(Default)]
+struct Group<'a> {
+    entries: Vec<&'a PasswordEntry>,
+    groups: BTreeMap<String, Group<'a>>,
+}
+
+impl<'a> Group<'a> {
+    fn insert(&mut self, entry: &'a PasswordEntry) {
+        let mut group = self;
+
+        if let Some(folder) = &entry.folder {
+            for name in folder.split('/').filter(|name| !name.is_empty()) {
+                group = group.groups.entry(name.to_string()).or_default();
+            }
+        }
+
+        group.entries.push(entry);
+    }
+
+    fn write(&self, name: &str, depth: usize, xml: &mut Vec<String>) {
+        let indent = "\t".repeat(depth);
+
+        xml.push(format!("{indent}<Group>"));
+        xml.push(format!("{indent}\t<UUID>{}</UUID>", uuid()));
+        xml.push(format!("{indent}\t<Name>{}</Name>", escape(name)));
+
+        for entry in self.entries.iter() {
+            write_entry(entry, depth + 1, xml);
+        }
+
+        for (name, group) in self.groups.iter() {
+            group.write(name, depth + 1, xml);
+        }
+
+        xml.push(format!("{indent}</Group>"));
+    }
+}
+
+fn write_entry(entry: &PasswordEntry, depth: usize, xml: &mut Vec<String>) {
+    let indent = "\t".repeat(depth);
+
+    let mut strings = vec![
+        ("Title", entry.service.clone()),
+        ("UserName", entry.username.clone()),
+        ("Password", entry.password.clone()),
+        ("URL", entry.url.clone().unwrap_or_default()),
+        ("Notes", entry.notes.clone().unwrap_or_default())
+    ];
+
+    if let Some(totp) = &entry.totp {
+        strings.push((OTP_KEY, totp.to_uri(&entry.service)));
+    } else if let Some(hotp) = &entry.hotp {
+        strings.push((OTP_KEY, hotp.to_uri(&entry.service)));
+    }
+
+    for (key, value) in entry.fields.iter() {
+        if !STANDARD_KEYS.contains(&key.as_str()) && key != OTP_KEY {
+            strings.push((key, value.clone()));
+        }
+    }
+
+    xml.push(format!("{indent}<Entry>"));
+    xml.push(format!("{indent}\t<UUID>{}</UUID>", uuid()));
+
+    for (key, value) in strings.iter() {
+        let protect = if *key == "Password" { " ProtectInMemory=\"True\"" } else { "" };
+
+        xml.push(format!("{indent}\t<String>"));
+        xml.push(format!("{indent}\t\t<Key>{}</Key>", escape(key)));
+        xml.push(format!("{indent}\t\t<Value{protect}>{}</Value>", escape(value)));
+        xml.push(format!("{indent}\t</String>"));
+    }
+
+    xml.push(format!("{indent}</Entry>"));
+}
+
+/**
+ * Write entries as a KeePass 2 XML file.
+ * Folders become groups below a single root group and fields become custom strings, so the
+ * file can be imported into KeePass and read back without losing anything.
+ *
+ * @param entries: &[&PasswordEntry]
+ * @return String
+ */
+pub fn export(entries: &[&PasswordEntry]) -> String {
+    let mut root = Group::default();
+    for entry in entries.iter() {
+        root.insert(entry);
+    }
+
+    let mut xml = vec![
+        "<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>".to_string(),
+        "<KeePassFile>".to_string(),
+        "\t<Meta>".to_string(),
+        format!("\t\t<Generator>{ROOT_GROUP}</Generator>"),
+        "\t</Meta>".to_string(),
+        "\t<Root>".to_string()
+    ];
+    root.write(ROOT_GROUP, 2, &mut xml);
+    xml.push("\t</Root>".to_string());
+    xml.push("</KeePassFile>".to_string());
+
+    xml.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEEPASS_XML: &str = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+    <Meta><Generator>KeePass</Generator></Meta>
+    <Root>
+        <Group>
+            <UUID>AAAAAAAAAAAAAAAAAAAAAA==</UUID>
+            <Name>Database</Name>
+            <Entry>
+                <String><Key>Title</Key><Value>Router</Value></String>
+                <String><Key>UserName</Key><Value>admin</Value></String>
+                <String><Key>Password</Key><Value ProtectInMemory="True">a&amp;b&lt;c</Value></String>
+                <String><Key>PIN</Key><Value>1234</Value></String>
+            </Entry>
+            <Group>
+                <Name>Work</Name>
+                <Group>
+                    <Name>Servers</Name>
+                    <Entry>
+                        <String><Key>Title</Key><Value></Value></String>
+                        <String><Key>URL</Key><Value>https://www.db.example.com</Value></String>
+                        <String><Key>Password</Key><Value>pg</Value></String>
+                        <String><Key>otp</Key><Value>otpauth://totp/db?secret=GEZDGNBVGY3TQOJQ</Value></String>
+                        <History>
+                            <Entry>
+                                <String><Key>Title</Key><Value>Old</Value></String>
+                                <String><Key>Password</Key><Value>old</Value></String>
+                            </Entry>
+                        </History>
+                    </Entry>
+                </Group>
+            </Group>
+            <Group>
+                <Name>Recycle Bin</Name>
+                <Entry>
+                    <String><Key>Title</Key><Value>Deleted</Value></String>
+                    <String><Key>Password</Key><Value>gone</Value></String>
+                </Entry>
+            </Group>
+        </Group>
+    </Root>
+</KeePassFile>"#;
+
+    #[test]
+    fn test_parse() {
+        let report = parse(KEEPASS_XML).unwrap();
+
+        assert_eq!(report.errors, vec![]);
+        assert_eq!(report.entries.len(), 2);
+
+        let router = &report.entries[0];
+        assert_eq!(router.service, "Router");
+        assert_eq!(router.password, "a&b<c");
+        assert_eq!(router.folder, None);
+        assert_eq!(router.fields.get("PIN").map(String::as_str), Some("1234"));
+
+        let database = &report.entries[1];
+        assert_eq!(database.service, "db.example.com");
+        assert_eq!(database.folder.as_deref(), Some("Work/Servers"));
+        assert_eq!(database.totp.as_ref().unwrap().secret, "GEZDGNBVGY3TQOJQ");
+        assert!(database.fields.is_empty());
+    }
+
+    #[test]
+    fn test_not_keepass() {
+        assert!(parse("<html></html>").is_err());
+        assert!(parse("not xml").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut top = PasswordEntry::new(
+            "Top <level>".to_string(),
+            "me".to_string(),
+            "\"quoted\" & 'apostrophes'".to_string()
+        );
+        top.notes = Some("line one\nline two".to_string());
+        top.hotp = Some(Hotp::new("GEZDGNBVGY3TQOJQ", 3).unwrap());
+
+        let mut nested = PasswordEntry::new(
+            "Nested".to_string(),
+            "you".to_string(),
+            "pw".to_string()
+        );
+        nested.url = Some("https://nested.example".to_string());
+        nested.folder = Some("Work/Servers".to_string());
+        nested.fields.insert("Security question".to_string(), "Blue".to_string());
+        nested.totp = Some(Totp::new("GEZDGNBVGY3TQOJQ").unwrap());
+
+        let xml = export(&[&top, &nested]);
+        let report = parse(&xml).unwrap();
+
+        assert_eq!(report.errors, vec![]);
+        for (original, imported) in [&top, &nested].iter().zip(report.entries.iter()) {
+            assert_eq!(imported.service, original.service);
+            assert_eq!(imported.username, original.username);
+            assert_eq!(imported.password, original.password);
+            assert_eq!(imported.url, original.url);
+            assert_eq!(imported.notes, original.notes);
+            assert_eq!(imported.folder, original.folder);
+            assert_eq!(imported.fields, original.fields);
+            assert_eq!(imported.totp, original.totp);
+            assert_eq!(imported.hotp, original.hotp);
+        }
+    }
+}
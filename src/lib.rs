@@ -1,3 +1,19 @@
+pub mod backup;
+pub mod bundle;
+pub mod config;
+pub mod credential;
+pub mod crypto;
+pub mod import;
+pub mod keepass;
+pub mod merge;
+pub mod export;
+pub mod otp;
 pub mod password;
 pub mod store;
-pub mod cli;
\ No newline at end of file
+pub mod sync;
+pub mod audit;
+pub mod breach;
+pub mod cli;
+pub mod tui;
+#[cfg(unix)]
+pub mod agent;
\ No newline at end of file
@@ -1,17 +1,46 @@
-use password_manager::cli::{ io::print, handle_list_services };
-use std::{ io::{ Write, BufRead }, path::PathBuf };
+use clap::Parser;
+use password_manager::{ audit::AuditOptions, cli::{ self, io::print, handle_list_services } };
+use std::{
+    io::{ Write, BufRead },
+    path::PathBuf,
+    process::{ self, ExitCode },
+    sync::{ mpsc, Mutex },
+    thread,
+    time::Duration,
+};
 
 use password_manager::{
+    config::Config,
     store::PasswordStore,
     cli::{
-        io::read_terminal_input,
+        args::{ Cli, Command },
+        io::try_read_terminal_input,
+        session::{ self, Session },
         handle_add_password,
         handle_get_password,
         handle_update_service,
+        handle_generate_command,
+        handle_passphrase_command,
+        handle_audit,
+        handle_audit_command,
+        handle_breach_check_command,
+        handle_import_command,
+        handle_export_command,
+        handle_master_password_command,
+        handle_backups_command,
+        handle_merge_command,
+        handle_sync_command,
+        handle_delete_service,
+        handle_get_command,
+        handle_list_command,
+        handle_tui_command,
+        handle_completions_command,
+        handle_complete_services_command,
+        handle_git_credential_command,
     },
 };
 
-/**
+/*
  * Password manager written in Rust
  *
  * MVP Features:
@@ -23,42 +52,149 @@ use password_manager::{
  *
  */
 
+/** What the Ctrl-C watcher of the dialog is told */
+enum Signal {
+    Interrupted,
+    Finished,
+}
+
+/** The inputs that end the dialog */
+const QUIT_COMMANDS: [&str; 4] = ["7", "q", "quit", "exit"];
+
 /**
  * Loops over the basic commands of the application:
  * Add, Get, Update, Delete and List
  *
  * Wait for user input to get direction on which commands to run
  * Each command will fan out to it's sub-dialogs that encapsulates feature specific logic
+ *
+ * When the session is not used for the configured idle time the store is locked in the
+ * background, wiping the decrypted entries, and the master password is asked for before the
+ * next command runs.
+ *
+ * The dialog ends on quit, Ctrl-D or Ctrl-C. Either way the decrypted entries are wiped and
+ * the clipboard is cleared if a password was copied to it.
  */
-fn run_dialog<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, store: &mut PasswordStore) {
-    loop {
-        let message = [
-            format!("[{}] -> {} password\n", "1", "Add"),
-            format!("[{}] -> {} password\n", "2", "Get"),
-            format!("[{}] -> {} service\n", "3", "Update"),
-            format!("[{}] -> {} all services\n", "4", "List"),
-        ];
-
-        let message = message.join("");
-        writeln!(writer, "\nCommands:\n{message}").unwrap();
-        let input = read_terminal_input(reader, writer, None);
-
-        match input.as_str() {
-            "1" | "add" => {
-                handle_add_password(reader, writer, store);
-            }
-            "2" | "get" => {
-                handle_get_password(reader, writer, store);
-            }
-            "3" | "update" => {
-                handle_update_service(reader, writer, store);
+fn run_dialog<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    config: &Config
+) {
+    let session = Session::new(Duration::from_secs(config.session_idle_timeout));
+    let store = Mutex::new(store);
+    let (sender, signals) = mpsc::channel();
+
+    let interrupts = sender.clone();
+    if ctrlc::set_handler(move || interrupts.send(Signal::Interrupted).unwrap_or(())).is_err() {
+        eprintln!("Unable to handle Ctrl-C, the clipboard is not cleared when it is pressed");
+    }
+
+    thread::scope(|scope| {
+        if Session::enabled(config) {
+            scope.spawn(|| session.watch(&store));
+        }
+
+        let shared = &store;
+        scope.spawn(move || {
+            if let Ok(Signal::Interrupted) = signals.recv() {
+                // A command in progress holds the store, its entries go away with the process
+                if let Ok(mut store) = shared.try_lock() {
+                    store.lock();
+                }
+                cli::clear_clipboard();
+                eprintln!("\nInterrupted, goodbye 👋");
+                process::exit(130);
             }
-            "4" | "list" => {
-                handle_list_services(store);
+        });
+
+        loop {
+            let message = [
+                format!("[{}] -> {} password\n", "1", "Add"),
+                format!("[{}] -> {} password\n", "2", "Get"),
+                format!("[{}] -> {} service\n", "3", "Update"),
+                format!("[{}] -> {} all services\n", "4", "List"),
+                format!("[{}] -> {} vault health\n", "5", "Audit"),
+                format!("[{}] -> {} service\n", "6", "Delete"),
+                format!("[{}] -> {}\n", "7", "Quit"),
+            ];
+
+            let message = message.join("");
+            print(writer, &format!("\nCommands:\n{message}"));
+
+            let input = match try_read_terminal_input(reader, writer, None) {
+                Ok(Some(input)) => input,
+                Ok(None) => {
+                    // Ctrl-D, the prompt is still on the line
+                    print(writer, "");
+                    break;
+                }
+                Err(err) => {
+                    print(writer, &format!("Unable to read the input: {err}"));
+                    break;
+                }
+            };
+            if QUIT_COMMANDS.contains(&input.as_str()) {
+                break;
             }
-            _ => {
-                print(writer, "Invalid command");
+            session.touch();
+
+            let mut guard = store.lock().unwrap();
+            let store: &mut PasswordStore = &mut guard;
+
+            if store.is_locked() {
+                print(writer, "The session was locked because it was not used for a while");
+
+                if let Err(err) = session::unlock(config, store) {
+                    print(writer, err);
+                    break;
+                }
             }
+
+            run_dialog_command(reader, writer, store, config, &input);
+            drop(guard);
+            session.touch();
+        }
+
+        session.finish();
+        sender.send(Signal::Finished).unwrap_or(());
+    });
+
+    session::end(store.into_inner().unwrap());
+    print(writer, "Goodbye 👋");
+}
+
+/**
+ * Run one command of the dialog.
+ */
+fn run_dialog_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    store: &mut PasswordStore,
+    config: &Config,
+    input: &str
+) {
+    match input {
+        "1" | "add" => {
+            handle_add_password(reader, writer, store, config);
+        }
+        "2" | "get" => {
+            handle_get_password(reader, writer, store);
+        }
+        "3" | "update" => {
+            handle_update_service(reader, writer, store, config);
+        }
+        "4" | "list" => {
+            handle_list_services(store);
+        }
+        "5" | "audit" => {
+            handle_audit(writer, store, &AuditOptions::default());
+        }
+        "6" | "delete" => {
+            handle_delete_service(reader, writer, store);
+        }
+        _ => {
+            print(writer, "Invalid command");
         }
     }
 }
@@ -66,9 +202,17 @@ fn run_dialog<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, store: &mut
 fn initialize_application<R: BufRead, W: Write>(read: &mut R, write: &mut W) {
     print(write, "Welcome to the password manager! 👋");
 
+    // Load the config, falling back to the defaults if there is no config file
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            print(write, err);
+            return;
+        }
+    };
+
     // Initialize the password store
-    let file_path = PathBuf::from("passwords.json");
-    let mut store = match PasswordStore::new(file_path) {
+    let mut store = match open_store() {
         Ok(store) => store,
         Err(err) => {
             print(write, err);
@@ -76,12 +220,75 @@ fn initialize_application<R: BufRead, W: Write>(read: &mut R, write: &mut W) {
         }
     };
 
-    run_dialog(read, write, &mut store)
+    run_dialog(read, write, &mut store, &config)
+}
+
+fn open_store() -> Result<PasswordStore, &'static str> {
+    cli::open_store(&load_config()?)
+}
+
+fn config_path() -> PathBuf {
+    PathBuf::from("config.json")
+}
+
+fn load_config() -> Result<Config, &'static str> {
+    Config::load(config_path())
+}
+
+/**
+ * Runs a single subcommand and exits, without starting the interactive dialog.
+ */
+fn run_command<R: BufRead, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    command: Command
+) -> Result<(), &'static str> {
+    match command {
+        Command::Generate(args) => handle_generate_command(writer, &args),
+        Command::Passphrase(args) => handle_passphrase_command(writer, &args),
+        Command::Audit(args) => handle_audit_command(writer, &open_store()?, &args),
+        Command::BreachCheck(args) => handle_breach_check_command(writer, &open_store()?, &args),
+        Command::Import(args) => handle_import_command(reader, writer, &mut open_store()?, &args),
+        Command::Export(args) =>
+            handle_export_command(reader, writer, &open_store()?, &load_config()?, &args),
+        Command::MasterPassword =>
+            handle_master_password_command(writer, &mut load_config()?, &config_path()),
+        Command::Backups(args) => handle_backups_command(reader, writer, &mut open_store()?, &args),
+        Command::Merge(args) => handle_merge_command(reader, writer, &load_config()?, &args),
+        Command::Sync(args) =>
+            handle_sync_command(reader, writer, &mut open_store()?, &load_config()?, &args),
+        #[cfg(unix)]
+        Command::Agent(args) => cli::handle_agent_command(reader, writer, &load_config()?, &args),
+        Command::Get(args) => handle_get_command(writer, &load_config()?, &args),
+        Command::List => handle_list_command(writer, &load_config()?),
+        Command::Tui => handle_tui_command(&mut open_store()?, &load_config()?),
+        Command::Completions(args) => handle_completions_command(writer, &args),
+        Command::CompleteServices => handle_complete_services_command(writer, &load_config()?),
+        Command::GitCredential(args) => {
+            // Git runs the helper in the repository, the vault is found from its own folder
+            if let Some(directory) = &args.directory {
+                std::env::set_current_dir(directory)
+                    .map_err(|_| "Unable to open the vault folder")?;
+            }
+            handle_git_credential_command(reader, writer, &load_config()?, &args)
+        }
+    }
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let cli = Cli::parse();
     let mut input = std::io::stdin().lock();
     let mut output = std::io::stdout().lock();
 
-    initialize_application(&mut input, &mut output);
+    match cli.command {
+        Some(command) => {
+            if let Err(err) = run_command(&mut input, &mut output, command) {
+                eprintln!("Error: {}", err);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => initialize_application(&mut input, &mut output),
+    }
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,397 @@
+use crate::password::PasswordEntry;
+
+/**
+ * Conflict
+ * An entry that was changed differently in both copies, or changed in one copy and deleted in
+ * the other. `None` means the entry does not exist in that copy.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub base: Option<PasswordEntry>,
+    pub ours: Option<PasswordEntry>,
+    pub theirs: Option<PasswordEntry>,
+}
+
+/** Which side of a conflict to keep */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+}
+
+impl Conflict {
+    /** The service the conflict is about */
+    pub fn service(&self) -> &str {
+        [&self.ours, &self.theirs, &self.base]
+            .into_iter()
+            .flatten()
+            .map(|entry| entry.service.as_str())
+            .next()
+            .unwrap_or("")
+    }
+
+    /**
+     * The side that was modified last, used as the suggested resolution.
+     * A deleted entry has no modified time, so a change wins over a deletion.
+     *
+     * @return Resolution
+     */
+    pub fn newest(&self) -> Resolution {
+        let modified = |entry: &Option<PasswordEntry>| {
+            entry.as_ref().map(|entry| entry.modified_at)
+        };
+
+        if modified(&self.theirs) > modified(&self.ours) {
+            Resolution::Theirs
+        } else {
+            Resolution::Ours
+        }
+    }
+
+    /**
+     * The entry to keep for a resolution, `None` when the entry stays deleted.
+     *
+     * @param resolution: Resolution
+     * @return Option<PasswordEntry>
+     */
+    pub fn resolve(self, resolution: Resolution) -> Option<PasswordEntry> {
+        match resolution {
+            Resolution::Ours => self.ours,
+            Resolution::Theirs => self.theirs,
+        }
+    }
+}
+
+/**
+ * The names of the fields that differ between two versions of an entry, without their values.
+ *
+ * @param a: &PasswordEntry
+ * @param b: &PasswordEntry
+ * @return Vec<&'static str>
+ */
+pub fn changed_fields(a: &PasswordEntry, b: &PasswordEntry) -> Vec<&'static str> {
+    let fields = [
+        ("service", a.service != b.service),
+        ("username", a.username != b.username),
+        ("password", a.password != b.password),
+        ("url", a.url != b.url),
+        ("notes", a.notes != b.notes),
+        ("folder", a.folder != b.folder),
+        ("custom fields", a.fields != b.fields),
+        ("password rules", a.policy != b.policy),
+        ("password history", a.history != b.history),
+        ("TOTP", a.totp != b.totp),
+        ("HOTP", a.hotp != b.hotp),
+    ];
+
+    fields.into_iter().filter(|(_, changed)| *changed).map(|(name, _)| name).collect()
+}
+
+/**
+ * Merge Result
+ * The entries both copies agree on after the merge, and the conflicts that still have to be
+ * resolved before the merged vault can be written.
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MergeResult {
+    pub entries: Vec<PasswordEntry>,
+    pub conflicts: Vec<Conflict>,
+    /** Entries taken from their copy because only they changed or added it */
+    pub from_theirs: usize,
+    /** Entries dropped because one copy deleted them and the other left them unchanged */
+    pub deleted: usize,
+}
+
+/**
+ * Whether two entries are the same entry in different copies: the same id, or else the same
+ * service. Entries with different ids still match by service, since a vault holds one entry per
+ * service and keeping both would lose one of them when the merged vault is written. `pair`
+ * matches by id first, so a service only decides for entries whose id has no match.
+ */
+fn same_entry(a: &PasswordEntry, b: &PasswordEntry) -> bool {
+    if !a.id.is_empty() && !b.id.is_empty() && a.id == b.id {
+        return true;
+    }
+
+    a.service == b.service
+}
+
+/** Remove and return the first entry that matches */
+fn take<'a>(
+    entries: &mut Vec<&'a PasswordEntry>,
+    matches: impl Fn(&PasswordEntry) -> bool
+) -> Option<&'a PasswordEntry> {
+    let index = entries.iter().position(|entry| matches(entry))?;
+
+    Some(entries.remove(index))
+}
+
+/**
+ * Pair the entries of two copies, entries without a counterpart are paired with `None`.
+ * Ids are matched before services, so a service that was renamed in one copy is still found.
+ *
+ * @param ours: &'a [PasswordEntry]
+ * @param theirs: &'a [PasswordEntry]
+ * @return Vec<(Option<&'a PasswordEntry>, Option<&'a PasswordEntry>)>
+ */
+fn pair<'a>(
+    ours: &'a [PasswordEntry],
+    theirs: &'a [PasswordEntry]
+) -> Vec<(Option<&'a PasswordEntry>, Option<&'a PasswordEntry>)> {
+    let mut unmatched: Vec<&PasswordEntry> = theirs.iter().collect();
+    let mut pairs = vec![];
+
+    let matched: Vec<Option<&PasswordEntry>> = ours
+        .iter()
+        .map(|entry| take(&mut unmatched, |other| !entry.id.is_empty() && entry.id == other.id))
+        .collect();
+
+    for (entry, matched) in ours.iter().zip(matched) {
+        let other = matched.or_else(|| take(&mut unmatched, |other| same_entry(entry, other)));
+        pairs.push((Some(entry), other));
+    }
+
+    pairs.extend(unmatched.into_iter().map(|entry| (None, Some(entry))));
+
+    pairs
+}
+
+/**
+ * Merge two copies of a vault.
+ * With a common ancestor this is a three-way merge: a side that still equals the ancestor
+ * takes the other side's change or deletion, and only entries changed on both sides conflict.
+ * Without an ancestor deletions can not be told apart from additions, so every entry is kept
+ * and the entry that was modified last wins, unless both were modified at the same time.
+ * Two entries that end up with the same service are a conflict as well.
+ *
+ * @param base: Option<&[PasswordEntry]>
+ * @param ours: &[PasswordEntry]
+ * @param theirs: &[PasswordEntry]
+ * @return MergeResult
+ */
+pub fn merge(
+    base: Option<&[PasswordEntry]>,
+    ours: &[PasswordEntry],
+    theirs: &[PasswordEntry]
+) -> MergeResult {
+    let mut result = MergeResult::default();
+
+    for (our_entry, their_entry) in pair(ours, theirs).into_iter() {
+        let base_entry = base.and_then(|base| {
+            base.iter().find(|entry| {
+                [our_entry, their_entry].into_iter().flatten().any(|other| same_entry(entry, other))
+            })
+        });
+
+        let conflict = Conflict {
+            base: base_entry.cloned(),
+            ours: our_entry.cloned(),
+            theirs: their_entry.cloned(),
+        };
+
+        match (base_entry, our_entry, their_entry) {
+            (_, Some(ours), Some(theirs)) if ours == theirs => {
+                result.entries.push(ours.clone());
+            }
+            (Some(base), Some(ours), Some(theirs)) if ours == base => {
+                result.entries.push(theirs.clone());
+                result.from_theirs += 1;
+            }
+            (Some(base), Some(ours), Some(theirs)) if theirs == base => {
+                result.entries.push(ours.clone());
+            }
+            (Some(_), Some(_), Some(_)) => result.conflicts.push(conflict),
+            (None, Some(ours), Some(theirs)) => {
+                if ours.modified_at == theirs.modified_at {
+                    result.conflicts.push(conflict);
+                } else if conflict.newest() == Resolution::Theirs {
+                    result.entries.push(theirs.clone());
+                    result.from_theirs += 1;
+                } else {
+                    result.entries.push(ours.clone());
+                }
+            }
+            // Deleted on one side: drop it if the other side did not change it
+            (Some(base), Some(entry), None) | (Some(base), None, Some(entry)) => {
+                if entry == base {
+                    result.deleted += 1;
+                } else {
+                    result.conflicts.push(conflict);
+                }
+            }
+            (_, Some(ours), None) => result.entries.push(ours.clone()),
+            (_, None, Some(theirs)) => {
+                result.entries.push(theirs.clone());
+                result.from_theirs += 1;
+            }
+            (_, None, None) => {}
+        }
+    }
+
+    conflict_on_same_service(&mut result, ours);
+
+    result
+}
+
+/**
+ * Turn merged entries that ended up with the same service into a conflict, since a vault holds
+ * one entry per service. Two entries with different ids reach the same service when one copy
+ * renamed an entry to a service the other copy added.
+ *
+ * @param result: &mut MergeResult
+ * @param ours: &[PasswordEntry]
+ */
+fn conflict_on_same_service(result: &mut MergeResult, ours: &[PasswordEntry]) {
+    let mut entries: Vec<PasswordEntry> = vec![];
+
+    for entry in std::mem::take(&mut result.entries).into_iter() {
+        let Some(index) = entries.iter().position(|other| other.service == entry.service) else {
+            entries.push(entry);
+            continue;
+        };
+
+        let other = entries.remove(index);
+        let (our_entry, their_entry) = match ours.contains(&other) {
+            true => (other, entry),
+            false => (entry, other),
+        };
+        let taken_from_theirs = [&our_entry, &their_entry]
+            .into_iter()
+            .filter(|entry| !ours.contains(entry))
+            .count();
+        result.from_theirs -= taken_from_theirs;
+
+        result.conflicts.push(Conflict {
+            base: None,
+            ours: Some(our_entry),
+            theirs: Some(their_entry),
+        });
+    }
+
+    result.entries = entries;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(service: &str, password: &str, modified_at: u64) -> PasswordEntry {
+        let mut entry = PasswordEntry::new(
+            service.to_string(),
+            "me".to_string(),
+            password.to_string()
+        );
+        entry.id = format!("id-{service}");
+        entry.modified_at = modified_at;
+
+        entry
+    }
+
+    #[test]
+    fn test_three_way_merge() {
+        let base = vec![
+            entry("unchanged", "pw", 1),
+            entry("ours-changed", "pw", 1),
+            entry("theirs-changed", "pw", 1),
+            entry("both-changed", "pw", 1),
+            entry("theirs-deleted", "pw", 1),
+            entry("deleted-and-changed", "pw", 1)
+        ];
+        let ours = vec![
+            entry("unchanged", "pw", 1),
+            entry("ours-changed", "new", 2),
+            entry("theirs-changed", "pw", 1),
+            entry("both-changed", "ours", 2),
+            entry("theirs-deleted", "pw", 1),
+            entry("deleted-and-changed", "new", 2),
+            entry("ours-added", "pw", 2)
+        ];
+        let theirs = vec![
+            entry("unchanged", "pw", 1),
+            entry("ours-changed", "pw", 1),
+            entry("theirs-changed", "new", 3),
+            entry("both-changed", "theirs", 3),
+            entry("theirs-added", "pw", 3)
+        ];
+
+        let result = merge(Some(&base), &ours, &theirs);
+        let passwords: Vec<(&str, &str)> = result.entries
+            .iter()
+            .map(|entry| (entry.service.as_str(), entry.password.as_str()))
+            .collect();
+
+        assert_eq!(passwords, vec![
+            ("unchanged", "pw"),
+            ("ours-changed", "new"),
+            ("theirs-changed", "new"),
+            ("ours-added", "pw"),
+            ("theirs-added", "pw")
+        ]);
+        assert_eq!(result.deleted, 1);
+        assert_eq!(result.from_theirs, 2);
+
+        let conflicts: Vec<&str> = result.conflicts.iter().map(Conflict::service).collect();
+        assert_eq!(conflicts, vec!["both-changed", "deleted-and-changed"]);
+        assert_eq!(result.conflicts[0].newest(), Resolution::Theirs);
+        assert_eq!(result.conflicts[1].newest(), Resolution::Ours);
+        assert_eq!(result.conflicts[1].clone().resolve(Resolution::Theirs), None);
+
+        let ours = result.conflicts[0].ours.as_ref().unwrap();
+        let theirs = result.conflicts[0].theirs.as_ref().unwrap();
+        assert_eq!(changed_fields(ours, theirs), vec!["password"]);
+    }
+
+    #[test]
+    fn test_two_way_merge_keeps_newest() {
+        let ours = vec![entry("github", "old", 1), entry("same-time", "a", 5)];
+        let mut renamed = entry("github", "new", 2);
+        renamed.service = "GitHub".to_string();
+        let theirs = vec![renamed.clone(), entry("same-time", "b", 5)];
+
+        let result = merge(None, &ours, &theirs);
+
+        assert_eq!(result.entries, vec![renamed]);
+        assert_eq!(result.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_entries_added_in_both_copies_match_by_service() {
+        let ours = entry("github", "ours", 1);
+        let mut theirs = entry("github", "theirs", 2);
+        theirs.id = "id-added-there".to_string();
+
+        let result = merge(None, &[ours], &[theirs.clone()]);
+
+        assert_eq!(result.entries, vec![theirs]);
+        assert!(result.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_rename_to_a_service_added_in_the_other_copy_conflicts() {
+        let base = vec![entry("gh", "one", 1)];
+        let mut renamed = base[0].clone();
+        renamed.service = "github".to_string();
+        let added = entry("github", "two", 2);
+
+        let result = merge(Some(&base), &[renamed.clone()], &[base[0].clone(), added.clone()]);
+
+        assert!(result.entries.is_empty());
+        assert_eq!(result.from_theirs, 0);
+        assert_eq!(result.conflicts, vec![Conflict {
+            base: None,
+            ours: Some(renamed),
+            theirs: Some(added),
+        }]);
+    }
+
+    #[test]
+    fn test_entries_without_id_match_by_service() {
+        let mut ours = entry("github", "old", 1);
+        ours.id = String::new();
+        let theirs = vec![entry("github", "new", 2)];
+
+        let result = merge(None, &[ours], &theirs);
+
+        assert_eq!(result.entries, theirs);
+    }
+}
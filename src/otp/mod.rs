@@ -0,0 +1,542 @@
+use hmac::{ Hmac, Mac };
+use serde::{ Deserialize, Serialize };
+use sha1::Sha1;
+use sha2::{ Sha256, Sha512 };
+use url::Url;
+
+const BASE32_ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/** Hash function used for the HMAC of a one-time password */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Algorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Algorithm, &'static str> {
+        match name.to_uppercase().as_str() {
+            "SHA1" => Ok(Algorithm::Sha1),
+            "SHA256" => Ok(Algorithm::Sha256),
+            "SHA512" => Ok(Algorithm::Sha512),
+            _ => Err("Unsupported OTP algorithm, use SHA1, SHA256 or SHA512"),
+        }
+    }
+}
+
+/**
+ * Decode a base32 (RFC 4648) string, as used for OTP secrets.
+ * Padding, spaces and dashes are ignored and lowercase letters are accepted, since secrets are
+ * often shown in groups to make them easier to type.
+ *
+ * @param input: &str
+ * @return Result<Vec<u8>, &'static str>
+ */
+pub fn decode_base32(input: &str) -> Result<Vec<u8>, &'static str> {
+    let mut bytes = Vec::new();
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !matches!(c, '=' | ' ' | '-')) {
+        let value = BASE32_ALPHABET
+            .find(c.to_ascii_uppercase())
+            .ok_or("The secret is not valid base32")?;
+
+        buffer = (buffer << 5) | (value as u64);
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(bytes)
+}
+
+/**
+ * Compute an HOTP value (RFC 4226) for a key and counter.
+ * This is the building block for both counter and time based one-time passwords.
+ *
+ * @param key: &[u8]
+ * @param counter: u64
+ * @param digits: u32
+ * @param algorithm: Algorithm
+ * @return String the code, left padded with zeros
+ */
+pub fn hotp(key: &[u8], counter: u64, digits: u32, algorithm: Algorithm) -> String {
+    let message = counter.to_be_bytes();
+
+    let hash = match algorithm {
+        Algorithm::Sha1 => hmac::<Hmac<Sha1>>(key, &message),
+        Algorithm::Sha256 => hmac::<Hmac<Sha256>>(key, &message),
+        Algorithm::Sha512 => hmac::<Hmac<Sha512>>(key, &message),
+    };
+
+    // Dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    let code = (binary as u64) % 10_u64.pow(digits);
+
+    format!("{:0width$}", code, width = digits as usize)
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/** An `otpauth://` URI split into its type (`totp` or `hotp`) and query parameters */
+struct OtpauthUri {
+    kind: String,
+    params: Vec<(String, String)>,
+}
+
+impl OtpauthUri {
+    fn parse(uri: &str) -> Result<OtpauthUri, &'static str> {
+        let url = Url::parse(uri).map_err(|_| "The otpauth URI could not be parsed")?;
+
+        if url.scheme() != "otpauth" {
+            return Err("The URI must start with otpauth://");
+        }
+
+        let kind = url.host_str().ok_or("The otpauth URI is missing its type")?.to_lowercase();
+        let params = url
+            .query_pairs()
+            .map(|(key, value)| (key.to_lowercase(), value.into_owned()))
+            .collect();
+
+        Ok(OtpauthUri { kind, params })
+    }
+
+    /**
+     * Build an `otpauth://` URI from its parts.
+     *
+     * @param kind: &str `totp` or `hotp`
+     * @param label: &str usually the service name
+     * @param params: &[(&str, String)]
+     * @return String
+     */
+    fn build(kind: &str, label: &str, params: &[(&str, String)]) -> String {
+        let mut url = Url::parse(&format!("otpauth://{kind}/")).expect("The base URI is valid");
+        url.set_path(label);
+        url.query_pairs_mut().extend_pairs(params.iter());
+
+        url.to_string()
+    }
+
+    fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/**
+ * TOTP
+ * A time-based one-time password configuration (RFC 6238), stored with a password entry.
+ * The secret is kept in base32, the way services hand it out.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Totp {
+    pub secret: String,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    #[serde(default = "default_period")]
+    pub period: u64,
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+impl Totp {
+    /**
+     * Create a TOTP configuration with the common defaults: SHA1, 6 digits and 30 seconds.
+     *
+     * @param secret: &str base32 encoded secret
+     * @return Result<Totp, &'static str>
+     */
+    pub fn new(secret: &str) -> Result<Totp, &'static str> {
+        let totp = Totp {
+            secret: secret.replace([' ', '-'], "").to_uppercase(),
+            algorithm: Algorithm::default(),
+            digits: default_digits(),
+            period: default_period(),
+        };
+        totp.validate()?;
+
+        Ok(totp)
+    }
+
+    /**
+     * Create a TOTP configuration from an `otpauth://totp/...` URI, as encoded in QR codes.
+     *
+     * @param uri: &str
+     * @return Result<Totp, &'static str>
+     */
+    pub fn from_uri(uri: &str) -> Result<Totp, &'static str> {
+        let uri = OtpauthUri::parse(uri)?;
+
+        if uri.kind != "totp" {
+            return Err("The otpauth URI is not for a time-based one-time password");
+        }
+
+        let secret = uri.param("secret").ok_or("The otpauth URI is missing its secret")?;
+        let mut totp = Totp::new(secret)?;
+
+        if let Some(algorithm) = uri.param("algorithm") {
+            totp.algorithm = Algorithm::parse(algorithm)?;
+        }
+        if let Some(digits) = uri.param("digits") {
+            totp.digits = digits.parse().map_err(|_| "The digits are not a number")?;
+        }
+        if let Some(period) = uri.param("period") {
+            totp.period = period.parse().map_err(|_| "The period is not a number")?;
+        }
+        totp.validate()?;
+
+        Ok(totp)
+    }
+
+    /**
+     * Create a TOTP configuration from either an `otpauth://` URI or a plain base32 secret.
+     *
+     * @param input: &str
+     * @return Result<Totp, &'static str>
+     */
+    pub fn parse(input: &str) -> Result<Totp, &'static str> {
+        if input.trim().starts_with("otpauth://") {
+            Totp::from_uri(input.trim())
+        } else {
+            Totp::new(input.trim())
+        }
+    }
+
+    /**
+     * The `otpauth://totp/...` URI for this configuration, so it can be moved to other apps.
+     *
+     * @param label: &str
+     * @return String
+     */
+    pub fn to_uri(&self, label: &str) -> String {
+        OtpauthUri::build("totp", label, &[
+            ("secret", self.secret.clone()),
+            ("algorithm", self.algorithm.name().to_string()),
+            ("digits", self.digits.to_string()),
+            ("period", self.period.to_string()),
+        ])
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if decode_base32(&self.secret)?.is_empty() {
+            return Err("The secret is empty");
+        }
+        if !(6..=10).contains(&self.digits) {
+            return Err("The number of digits must be between 6 and 10");
+        }
+        if self.period == 0 {
+            return Err("The period must be greater than zero");
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Generate the code for a point in time. A configuration read from a vault file is checked
+     * first, it may have been edited by hand.
+     *
+     * @param timestamp: u64 seconds since the unix epoch
+     * @return Result<String, &'static str>
+     */
+    pub fn generate(&self, timestamp: u64) -> Result<String, &'static str> {
+        self.validate()?;
+        let key = decode_base32(&self.secret)?;
+
+        Ok(hotp(&key, timestamp / self.period, self.digits, self.algorithm))
+    }
+
+    /**
+     * Number of seconds the code for a point in time stays valid.
+     *
+     * @param timestamp: u64 seconds since the unix epoch
+     * @return Result<u64, &'static str>
+     */
+    pub fn remaining_seconds(&self, timestamp: u64) -> Result<u64, &'static str> {
+        if self.period == 0 {
+            return Err("The period must be greater than zero");
+        }
+
+        Ok(self.period - (timestamp % self.period))
+    }
+}
+
+/**
+ * HOTP
+ * A counter-based one-time password configuration (RFC 4226), stored with a password entry.
+ * `counter` is the counter for the next code; it has to be saved every time a code is used so
+ * the vault stays in sync with the service, like a hardware token.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hotp {
+    pub secret: String,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default = "default_digits")]
+    pub digits: u32,
+    #[serde(default)]
+    pub counter: u64,
+}
+
+impl Hotp {
+    /**
+     * Create an HOTP configuration with the common defaults: SHA1 and 6 digits.
+     *
+     * @param secret: &str base32 encoded secret
+     * @param counter: u64
+     * @return Result<Hotp, &'static str>
+     */
+    pub fn new(secret: &str, counter: u64) -> Result<Hotp, &'static str> {
+        let hotp = Hotp {
+            secret: secret.replace([' ', '-'], "").to_uppercase(),
+            algorithm: Algorithm::default(),
+            digits: default_digits(),
+            counter,
+        };
+        hotp.validate()?;
+
+        Ok(hotp)
+    }
+
+    /**
+     * Create an HOTP configuration from an `otpauth://hotp/...` URI, as encoded in QR codes.
+     *
+     * @param uri: &str
+     * @return Result<Hotp, &'static str>
+     */
+    pub fn from_uri(uri: &str) -> Result<Hotp, &'static str> {
+        let uri = OtpauthUri::parse(uri)?;
+
+        if uri.kind != "hotp" {
+            return Err("The otpauth URI is not for a counter-based one-time password");
+        }
+
+        let secret = uri.param("secret").ok_or("The otpauth URI is missing its secret")?;
+        let counter = match uri.param("counter") {
+            Some(counter) => counter.parse().map_err(|_| "The counter is not a number")?,
+            None => 0,
+        };
+        let mut hotp = Hotp::new(secret, counter)?;
+
+        if let Some(algorithm) = uri.param("algorithm") {
+            hotp.algorithm = Algorithm::parse(algorithm)?;
+        }
+        if let Some(digits) = uri.param("digits") {
+            hotp.digits = digits.parse().map_err(|_| "The digits are not a number")?;
+        }
+        hotp.validate()?;
+
+        Ok(hotp)
+    }
+
+    /**
+     * The `otpauth://hotp/...` URI for this configuration, including the current counter.
+     *
+     * @param label: &str
+     * @return String
+     */
+    pub fn to_uri(&self, label: &str) -> String {
+        OtpauthUri::build("hotp", label, &[
+            ("secret", self.secret.clone()),
+            ("algorithm", self.algorithm.name().to_string()),
+            ("digits", self.digits.to_string()),
+            ("counter", self.counter.to_string()),
+        ])
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if decode_base32(&self.secret)?.is_empty() {
+            return Err("The secret is empty");
+        }
+        if !(6..=10).contains(&self.digits) {
+            return Err("The number of digits must be between 6 and 10");
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Produce the code for the current counter and advance the counter. A configuration read
+     * from a vault file is checked first, it may have been edited by hand.
+     * The caller is responsible for saving the entry before the code is shown.
+     *
+     * @return Result<String, &'static str>
+     */
+    pub fn next_code(&mut self) -> Result<String, &'static str> {
+        self.validate()?;
+        let key = decode_base32(&self.secret)?;
+        let code = hotp(&key, self.counter, self.digits, self.algorithm);
+        self.counter = self.counter.checked_add(1).ok_or("The counter can not be advanced")?;
+
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const SHA256_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA====";
+    const SHA512_SECRET: &str = concat!(
+        "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ",
+        "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA="
+    );
+
+    fn totp(secret: &str, algorithm: Algorithm) -> Totp {
+        Totp {
+            secret: secret.to_string(),
+            algorithm,
+            digits: 8,
+            period: 30,
+        }
+    }
+
+    #[test]
+    fn test_decode_base32() {
+        assert_eq!(decode_base32(SHA1_SECRET).unwrap(), b"12345678901234567890");
+        assert_eq!(decode_base32("mzxw 6ytb").unwrap(), b"fooba");
+        assert!(decode_base32("not base32!").is_err());
+    }
+
+    // Test vectors from RFC 6238 appendix B
+    #[test]
+    fn test_rfc6238_vectors() {
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+
+        for (time, sha1, sha256, sha512) in vectors {
+            assert_eq!(totp(SHA1_SECRET, Algorithm::Sha1).generate(time).unwrap(), sha1);
+            assert_eq!(totp(SHA256_SECRET, Algorithm::Sha256).generate(time).unwrap(), sha256);
+            assert_eq!(totp(SHA512_SECRET, Algorithm::Sha512).generate(time).unwrap(), sha512);
+        }
+    }
+
+    // Test vectors from RFC 4226 appendix D
+    #[test]
+    fn test_rfc4226_vectors() {
+        let codes = [
+            "755224", "287082", "359152", "969429", "338314",
+            "254676", "287922", "162583", "399871", "520489",
+        ];
+        let mut hotp = Hotp::new(SHA1_SECRET, 0).unwrap();
+
+        for code in codes {
+            assert_eq!(hotp.next_code().unwrap(), code);
+        }
+        assert_eq!(hotp.counter, 10);
+    }
+
+    #[test]
+    fn test_hotp_from_uri() {
+        let uri = format!("otpauth://hotp/Token?secret={}&counter=5&digits=8", SHA1_SECRET);
+
+        let hotp = Hotp::from_uri(&uri).unwrap();
+
+        assert_eq!(hotp.counter, 5);
+        assert_eq!(hotp.digits, 8);
+        assert!(Hotp::from_uri("otpauth://totp/Example?secret=GEZDGNBV").is_err());
+    }
+
+    #[test]
+    fn test_from_uri() {
+        let uri = format!(
+            "otpauth://totp/Example:alice@example.com?secret={}&issuer=Example&{}",
+            SHA1_SECRET,
+            "algorithm=SHA256&digits=8&period=60"
+        );
+
+        let totp = Totp::parse(&uri).unwrap();
+
+        assert_eq!(totp.secret, SHA1_SECRET);
+        assert_eq!(totp.algorithm, Algorithm::Sha256);
+        assert_eq!(totp.digits, 8);
+        assert_eq!(totp.period, 60);
+        assert!(Totp::parse("otpauth://hotp/Example?secret=GEZDGNBV&counter=1").is_err());
+    }
+
+    #[test]
+    fn test_uri_round_trip() {
+        let mut totp = Totp::new(SHA1_SECRET).unwrap();
+        totp.algorithm = Algorithm::Sha512;
+        let mut hotp = Hotp::new(SHA1_SECRET, 7).unwrap();
+        hotp.digits = 8;
+
+        assert_eq!(Totp::from_uri(&totp.to_uri("My Service")).unwrap(), totp);
+        assert_eq!(Hotp::from_uri(&hotp.to_uri("My Service")).unwrap(), hotp);
+    }
+
+    #[test]
+    fn test_plain_secret_defaults() {
+        let totp = Totp::parse("gezd gnbv gy3t qojq").unwrap();
+
+        assert_eq!(totp.secret, "GEZDGNBVGY3TQOJQ");
+        assert_eq!(totp.digits, 6);
+        assert_eq!(totp.generate(59).unwrap().len(), 6);
+        assert_eq!(totp.remaining_seconds(59), Ok(1));
+        assert_eq!(totp.remaining_seconds(60), Ok(30));
+    }
+
+    #[test]
+    fn test_stored_zero_period() {
+        let json = r#"{"secret": "GEZDGNBVGY3TQOJQ", "period": 0}"#;
+        let totp: Totp = serde_json::from_str(json).unwrap();
+
+        assert!(totp.generate(59).is_err());
+        assert!(totp.remaining_seconds(59).is_err());
+    }
+
+    #[test]
+    fn test_stored_hotp_is_checked() {
+        let json = r#"{"secret": "GEZDGNBVGY3TQOJQ", "digits": 20, "counter": 0}"#;
+        let mut hotp: Hotp = serde_json::from_str(json).unwrap();
+        assert!(hotp.next_code().is_err());
+        assert_eq!(hotp.counter, 0);
+
+        hotp.digits = 6;
+        hotp.counter = u64::MAX;
+        assert!(hotp.next_code().is_err());
+        assert_eq!(hotp.counter, u64::MAX);
+    }
+}
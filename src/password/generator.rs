@@ -0,0 +1,309 @@
+use rand::{ seq::SliceRandom, Rng };
+use serde::{ Deserialize, Serialize };
+
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+pub(crate) const DIGITS: &str = "0123456789";
+pub(crate) const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+
+/** Characters that are easily confused with each other when read or typed by hand */
+const AMBIGUOUS: &str = "0Oo1lI|`'\"";
+
+/** The characters of one class together with the minimum number a password must contain */
+type CharClass = (Vec<char>, usize);
+
+/**
+ * Password Policy
+ * Describes the rules a generated password has to follow: which character classes it is built
+ * from, how long it is and how many characters of each class it must contain at least.
+ *
+ * The default policy matches the original generator: 30 alphanumeric characters.
+ *
+ * A policy can be stored with a password entry, so a site's rules (maximum length, no symbols,
+ * forbidden characters) are applied again whenever its password is regenerated.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PasswordPolicy {
+    pub length: usize,
+    pub uppercase: bool,
+    pub lowercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    pub custom: String,
+    pub min_uppercase: usize,
+    pub min_lowercase: usize,
+    pub min_digits: usize,
+    pub min_symbols: usize,
+    pub exclude_ambiguous: bool,
+    pub forbidden: String,
+    pub no_repeat: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            length: 30,
+            uppercase: true,
+            lowercase: true,
+            digits: true,
+            symbols: false,
+            custom: String::new(),
+            min_uppercase: 0,
+            min_lowercase: 0,
+            min_digits: 0,
+            min_symbols: 0,
+            exclude_ambiguous: false,
+            forbidden: String::new(),
+            no_repeat: false,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /**
+     * Collect the enabled character classes together with their minimum counts.
+     * Ambiguous and forbidden characters are filtered out here so every later step only sees
+     * allowed characters.
+     *
+     * @return Result<Vec<CharClass>, &'static str>
+     */
+    fn classes(&self) -> Result<Vec<CharClass>, &'static str> {
+        let rules = [
+            (self.uppercase, UPPERCASE, self.min_uppercase),
+            (self.lowercase, LOWERCASE, self.min_lowercase),
+            (self.digits, DIGITS, self.min_digits),
+            (self.symbols, SYMBOLS, self.min_symbols),
+            (!self.custom.is_empty(), self.custom.as_str(), 0),
+        ];
+
+        let mut classes = Vec::new();
+
+        for (enabled, chars, min) in rules {
+            if !enabled {
+                if min > 0 {
+                    return Err("A minimum count was set for a disabled character class");
+                }
+                continue;
+            }
+
+            let mut chars: Vec<char> = chars
+                .chars()
+                .filter(|c| !(self.exclude_ambiguous && AMBIGUOUS.contains(*c)))
+                .filter(|c| !self.forbidden.contains(*c))
+                .collect();
+            chars.sort_unstable();
+            chars.dedup();
+
+            if chars.len() < min {
+                return Err("A character class has fewer characters than its minimum count");
+            }
+
+            classes.push((chars, min));
+        }
+
+        Ok(classes)
+    }
+
+    /**
+     * Check that a password can be generated from this policy.
+     *
+     * @return Result<(), &'static str>
+     */
+    pub fn validate(&self) -> Result<(), &'static str> {
+        self.pool().map(|_| ())
+    }
+
+    /**
+     * Collect every allowed character and check the policy can be satisfied with them.
+     *
+     * @return Result<(Vec<CharClass>, Vec<char>), &'static str>
+     */
+    fn pool(&self) -> Result<(Vec<CharClass>, Vec<char>), &'static str> {
+        let classes = self.classes()?;
+
+        let mut pool: Vec<char> = classes
+            .iter()
+            .flat_map(|(chars, _)| chars.iter().copied())
+            .collect();
+        pool.sort_unstable();
+        pool.dedup();
+
+        if self.length == 0 {
+            return Err("Password length must be greater than zero");
+        }
+        if pool.is_empty() {
+            return Err("At least one character class must be enabled");
+        }
+        if classes.iter().map(|(_, min)| min).sum::<usize>() > self.length {
+            return Err("The minimum counts add up to more than the password length");
+        }
+        if self.no_repeat && pool.len() < self.length {
+            return Err("Not enough distinct characters to generate a password without repeats");
+        }
+
+        Ok((classes, pool))
+    }
+
+    /**
+     * Generate a password that follows this policy.
+     * First the minimum number of characters is drawn from every class, the rest is filled up from
+     * all enabled classes and the result is shuffled so the required characters are not grouped.
+     *
+     * @return Result<String, &'static str>
+     */
+    pub fn generate(&self) -> Result<String, &'static str> {
+        let (classes, pool) = self.pool()?;
+
+        let mut rng = rand::thread_rng();
+        let mut password: Vec<char> = Vec::with_capacity(self.length);
+
+        for (chars, min) in classes.iter() {
+            for _ in 0..*min {
+                let candidates: Vec<&char> = chars
+                    .iter()
+                    .filter(|c| !(self.no_repeat && password.contains(c)))
+                    .collect();
+                let c = candidates
+                    .choose(&mut rng)
+                    .ok_or("Not enough distinct characters to satisfy the minimum counts")?;
+                password.push(**c);
+            }
+        }
+
+        while password.len() < self.length {
+            let c = pool[rng.gen_range(0..pool.len())];
+            if self.no_repeat && password.contains(&c) {
+                continue;
+            }
+            password.push(c);
+        }
+
+        password.shuffle(&mut rng);
+
+        Ok(password.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_is_alphanumeric() {
+        let password = PasswordPolicy::default().generate().unwrap();
+
+        assert_eq!(password.len(), 30);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_minimum_counts() {
+        let policy = PasswordPolicy {
+            length: 12,
+            symbols: true,
+            min_uppercase: 3,
+            min_digits: 3,
+            min_symbols: 3,
+            ..PasswordPolicy::default()
+        };
+
+        for _ in 0..50 {
+            let password = policy.generate().unwrap();
+
+            assert_eq!(password.chars().count(), 12);
+            assert!(password.chars().filter(|c| c.is_ascii_uppercase()).count() >= 3);
+            assert!(password.chars().filter(|c| c.is_ascii_digit()).count() >= 3);
+            assert!(password.chars().filter(|c| SYMBOLS.contains(*c)).count() >= 3);
+        }
+    }
+
+    #[test]
+    fn test_custom_set_and_no_repeat() {
+        let policy = PasswordPolicy {
+            length: 6,
+            uppercase: false,
+            lowercase: false,
+            digits: false,
+            custom: "abcdef".to_string(),
+            no_repeat: true,
+            ..PasswordPolicy::default()
+        };
+
+        let mut password: Vec<char> = policy.generate().unwrap().chars().collect();
+        password.sort_unstable();
+
+        assert_eq!(password, vec!['a', 'b', 'c', 'd', 'e', 'f']);
+    }
+
+    #[test]
+    fn test_exclude_ambiguous() {
+        let policy = PasswordPolicy {
+            length: 200,
+            symbols: true,
+            exclude_ambiguous: true,
+            ..PasswordPolicy::default()
+        };
+
+        let password = policy.generate().unwrap();
+
+        assert!(!password.chars().any(|c| AMBIGUOUS.contains(c)));
+    }
+
+    #[test]
+    fn test_forbidden_characters() {
+        let policy = PasswordPolicy {
+            length: 200,
+            symbols: true,
+            forbidden: "<>&'\"".to_string(),
+            ..PasswordPolicy::default()
+        };
+
+        let password = policy.generate().unwrap();
+
+        assert!(!password.chars().any(|c| policy.forbidden.contains(c)));
+    }
+
+    #[test]
+    fn test_policy_serialization() {
+        let policy = PasswordPolicy {
+            length: 16,
+            symbols: true,
+            forbidden: "\\".to_string(),
+            ..PasswordPolicy::default()
+        };
+
+        let json = serde_json::to_string(&policy).unwrap();
+        let partial: PasswordPolicy = serde_json::from_str(r#"{ "length": 16 }"#).unwrap();
+
+        assert_eq!(serde_json::from_str::<PasswordPolicy>(&json).unwrap(), policy);
+        assert_eq!(partial.length, 16);
+        assert!(partial.uppercase);
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        let too_many_minimums = PasswordPolicy {
+            length: 4,
+            min_digits: 3,
+            min_uppercase: 3,
+            ..PasswordPolicy::default()
+        };
+        let disabled_class = PasswordPolicy {
+            digits: false,
+            min_digits: 1,
+            ..PasswordPolicy::default()
+        };
+        let too_long_without_repeats = PasswordPolicy {
+            length: 11,
+            uppercase: false,
+            lowercase: false,
+            no_repeat: true,
+            ..PasswordPolicy::default()
+        };
+
+        assert!(too_many_minimums.generate().is_err());
+        assert!(disabled_class.generate().is_err());
+        assert!(too_long_without_repeats.generate().is_err());
+    }
+}
@@ -1,18 +1,165 @@
+pub mod generator;
+pub mod passphrase;
+pub mod strength;
+
+use std::{ collections::BTreeMap, time::{ SystemTime, UNIX_EPOCH } };
+
 use serde::{ Deserialize, Serialize };
+use zeroize::Zeroize;
+
+use crate::otp::{ Hotp, Totp };
+
+use self::{ generator::PasswordPolicy, passphrase::{ Passphrase, PassphrasePolicy } };
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
 pub struct PasswordEntry {
+    #[serde(default)]
     pub service: String,
+    /**
+     * Random id that stays the same across copies of the vault, used to match entries when
+     * merging. Empty for entries saved before ids were added.
+     */
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
     pub username: String,
     pub password: String, //TODO remove pub
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /** Folder path like `Work/Servers`, `None` for entries at the top level */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+    /** Custom fields, e.g. security questions or PINs */
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PasswordPolicy>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<PasswordHistoryItem>,
+    /** Unix timestamp of when the entry was added, 0 for entries saved before it was tracked */
+    #[serde(default)]
+    pub created_at: u64,
+    /** Unix timestamp of the last change, 0 for entries saved before it was tracked */
+    #[serde(default)]
+    pub modified_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub totp: Option<Totp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hotp: Option<Hotp>,
+}
+
+/**
+ * A password that was previously used for a service.
+ * `changed_at` is the unix timestamp of the moment it was replaced.
+ */
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct PasswordHistoryItem {
+    pub password: String,
+    pub changed_at: u64,
+}
+
+/** A new random entry id, 32 hex characters */
+pub fn new_id() -> String {
+    rand::random::<[u8; 16]>().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/** Whether an id has the form of `new_id`, only such ids are safe to use as file names */
+pub fn is_valid_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/** Current time as seconds since the unix epoch */
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
 }
 
 impl PasswordEntry {
     pub fn new(service: String, username: String, password: String) -> PasswordEntry {
+        let now = unix_timestamp();
+
         PasswordEntry {
             service,
+            id: new_id(),
             username,
             password,
+            url: None,
+            notes: None,
+            folder: None,
+            fields: BTreeMap::new(),
+            policy: None,
+            history: Vec::new(),
+            created_at: now,
+            modified_at: now,
+            totp: None,
+            hotp: None,
+        }
+    }
+
+    /**
+     * Overwrite everything secret in the entry before it is dropped, so it does not linger in
+     * memory: the credentials, notes, custom fields, old passwords and one-time password
+     * secrets.
+     */
+    pub fn wipe(&mut self) {
+        self.username.zeroize();
+        self.password.zeroize();
+        self.url.zeroize();
+        self.notes.zeroize();
+        self.fields.values_mut().for_each(Zeroize::zeroize);
+        self.history.iter_mut().for_each(|item| item.password.zeroize());
+
+        if let Some(totp) = &mut self.totp {
+            totp.secret.zeroize();
+        }
+
+        if let Some(hotp) = &mut self.hotp {
+            hotp.secret.zeroize();
+        }
+    }
+
+    /**
+     * When the current password was set.
+     * That is the last time it was changed, or when the entry was added if it never was.
+     * Entries saved before timestamps were tracked have no known age.
+     *
+     * @return Option<u64>
+     */
+    pub fn password_changed_at(&self) -> Option<u64> {
+        match self.history.last() {
+            Some(item) => Some(item.changed_at),
+            None if self.created_at > 0 => Some(self.created_at),
+            None => None,
+        }
+    }
+
+    /**
+     * Replace the password of this entry.
+     * The current password is moved to the history so it can still be looked up if the change
+     * did not go through on the service's side.
+     *
+     * @param password: String
+     */
+    pub fn change_password(&mut self, password: String) {
+        let previous = std::mem::replace(&mut self.password, password);
+
+        self.history.push(PasswordHistoryItem {
+            password: previous,
+            changed_at: unix_timestamp(),
+        });
+    }
+
+    /**
+     * Generate a new password for this entry.
+     * The policy stored with the entry is used so the password follows the site's rules, entries
+     * without a policy get a password from the default policy.
+     *
+     * @return Result<String, &'static str>
+     */
+    pub fn generate_password(&self) -> Result<String, &'static str> {
+        match &self.policy {
+            Some(policy) => Password::generate_with_policy(policy),
+            None => Ok(Password::generate()),
         }
     }
 }
@@ -26,14 +173,25 @@ impl Passwords {
     }
 }
 
+impl Default for Passwords {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Password();
 
 impl Password {
     pub fn generate() -> String {
-        use rand::Rng;
-        use rand::distributions::Alphanumeric;
+        PasswordPolicy::default().generate().expect("The default password policy is valid")
+    }
+
+    pub fn generate_with_policy(policy: &PasswordPolicy) -> Result<String, &'static str> {
+        policy.generate()
+    }
 
-        rand::thread_rng().sample_iter(&Alphanumeric).take(30).map(char::from).collect()
+    pub fn generate_passphrase(policy: &PassphrasePolicy) -> Result<Passphrase, &'static str> {
+        policy.generate()
     }
 }
 
@@ -45,4 +203,45 @@ mod tests {
     fn test_passwords() {
         assert_eq!(Passwords::new(), Passwords(vec![]));
     }
+
+    #[test]
+    fn test_change_password_keeps_history() {
+        let mut entry = PasswordEntry::new(
+            "service".to_string(),
+            "username".to_string(),
+            "first".to_string()
+        );
+
+        entry.change_password("second".to_string());
+        entry.change_password("third".to_string());
+
+        assert_eq!(entry.password, "third");
+        assert_eq!(
+            entry.history
+                .iter()
+                .map(|item| item.password.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["first", "second"]
+        );
+    }
+
+    #[test]
+    fn test_generate_password_follows_entry_policy() {
+        let mut entry = PasswordEntry::new(
+            "service".to_string(),
+            "username".to_string(),
+            "password".to_string()
+        );
+        entry.policy = Some(PasswordPolicy {
+            length: 12,
+            uppercase: false,
+            forbidden: "xyz".to_string(),
+            ..PasswordPolicy::default()
+        });
+
+        let password = entry.generate_password().unwrap();
+
+        assert_eq!(password.len(), 12);
+        assert!(!password.chars().any(|c| c.is_ascii_uppercase() || "xyz".contains(c)));
+    }
 }
@@ -0,0 +1,163 @@
+use rand::{ seq::SliceRandom, Rng };
+
+use super::generator::{ DIGITS, SYMBOLS };
+
+/** The EFF large wordlist, one `<dice roll>\t<word>` pair per line */
+const EFF_LARGE_WORDLIST: &str = include_str!("eff_large_wordlist.txt");
+
+fn wordlist() -> Vec<&'static str> {
+    EFF_LARGE_WORDLIST.lines().filter_map(|line| line.split('\t').nth(1)).collect()
+}
+
+/**
+ * Passphrase Policy
+ * Describes a diceware-style passphrase: how many words are picked from the wordlist, how they are
+ * joined and whether a number or symbol is mixed in for sites that insist on one.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassphrasePolicy {
+    pub word_count: usize,
+    pub separator: String,
+    pub capitalize: bool,
+    pub include_number: bool,
+    pub include_symbol: bool,
+}
+
+impl Default for PassphrasePolicy {
+    fn default() -> Self {
+        PassphrasePolicy {
+            word_count: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+            include_number: false,
+            include_symbol: false,
+        }
+    }
+}
+
+/**
+ * A generated passphrase together with its entropy in bits.
+ * The entropy only counts the random choices (words, number and symbol), which is what an
+ * attacker who knows the policy and the wordlist would have to guess.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    pub phrase: String,
+    pub entropy: f64,
+}
+
+impl PassphrasePolicy {
+    /**
+     * Generate a passphrase that follows this policy.
+     * Words are picked uniformly from the wordlist, the number and symbol are appended to a
+     * randomly chosen word.
+     *
+     * @return Result<Passphrase, &'static str>
+     */
+    pub fn generate(&self) -> Result<Passphrase, &'static str> {
+        if self.word_count == 0 {
+            return Err("A passphrase needs at least one word");
+        }
+
+        let wordlist = wordlist();
+        let mut rng = rand::thread_rng();
+
+        let mut words: Vec<String> = (0..self.word_count)
+            .map(|_| {
+                let word = *wordlist.choose(&mut rng).expect("The wordlist is not empty");
+                if self.capitalize { capitalize(word) } else { word.to_string() }
+            })
+            .collect();
+        let mut entropy = (self.word_count as f64) * (wordlist.len() as f64).log2();
+
+        if self.include_number {
+            let index = rng.gen_range(0..words.len());
+            words[index].push(random_char(DIGITS, &mut rng));
+            entropy += (DIGITS.len() as f64).log2();
+        }
+
+        if self.include_symbol {
+            let index = rng.gen_range(0..words.len());
+            words[index].push(random_char(SYMBOLS, &mut rng));
+            entropy += (SYMBOLS.len() as f64).log2();
+        }
+
+        Ok(Passphrase {
+            phrase: words.join(&self.separator),
+            entropy,
+        })
+    }
+}
+
+fn random_char<R: Rng>(chars: &str, rng: &mut R) -> char {
+    let chars: Vec<char> = chars.chars().collect();
+    *chars.choose(rng).expect("The character set is not empty")
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist = wordlist();
+
+        assert_eq!(wordlist.len(), 7776);
+        assert_eq!(wordlist[0], "abacus");
+        assert_eq!(wordlist[7775], "zoom");
+    }
+
+    #[test]
+    fn test_default_passphrase() {
+        // A few words like "t-shirt" contain the default separator, so split on a space instead
+        let policy = PassphrasePolicy {
+            separator: " ".to_string(),
+            ..PassphrasePolicy::default()
+        };
+        let passphrase = policy.generate().unwrap();
+        let words: Vec<&str> = passphrase.phrase.split(' ').collect();
+
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|word| wordlist().contains(word)));
+        assert!((passphrase.entropy - 77.54).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_passphrase_options() {
+        let policy = PassphrasePolicy {
+            word_count: 4,
+            separator: " ".to_string(),
+            capitalize: true,
+            include_number: true,
+            include_symbol: true,
+        };
+
+        let passphrase = policy.generate().unwrap();
+        let words: Vec<&str> = passphrase.phrase.split(' ').collect();
+
+        assert_eq!(words.len(), 4);
+        assert!(words.iter().all(|word| word.starts_with(|c: char| c.is_ascii_uppercase())));
+        assert!(passphrase.phrase.chars().any(|c| c.is_ascii_digit()));
+        assert!(passphrase.phrase.chars().any(|c| SYMBOLS.contains(c)));
+        assert!(passphrase.entropy > 4.0 * 12.9 + 3.3 + 5.0);
+    }
+
+    #[test]
+    fn test_empty_passphrase() {
+        let policy = PassphrasePolicy {
+            word_count: 0,
+            ..PassphrasePolicy::default()
+        };
+
+        assert!(policy.generate().is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use zxcvbn::zxcvbn;
+
+/**
+ * Password Strength
+ * An estimate of how hard a password is to guess, based on zxcvbn. Instead of counting character
+ * classes it looks for dictionary words, keyboard patterns, repeats, sequences, dates and l33t
+ * substitutions and estimates the number of guesses an attacker would need.
+ *
+ * The score goes from 0 (guessable within 10^3 guesses) to 4 (more than 10^10 guesses).
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub crack_time_online: String,
+    pub crack_time_offline: String,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+pub const MAX_SCORE: u8 = 4;
+
+impl Strength {
+    /**
+     * Estimate the strength of a password.
+     * The user inputs (e.g. the service and username) are treated as dictionary words, so
+     * passwords built from them are scored as weak.
+     *
+     * @param password: &str
+     * @param user_inputs: &[&str]
+     * @return Strength
+     */
+    pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+        let entropy = zxcvbn(password, user_inputs);
+        let crack_times = entropy.crack_times();
+        let feedback = entropy.feedback();
+
+        Strength {
+            score: entropy.score().into(),
+            guesses_log10: entropy.guesses_log10(),
+            crack_time_online: crack_times.online_throttling_100_per_hour().to_string(),
+            crack_time_offline: crack_times.offline_slow_hashing_1e4_per_second().to_string(),
+            warning: feedback.and_then(|feedback| feedback.warning()).map(|w| w.to_string()),
+            suggestions: feedback
+                .map(|feedback| feedback.suggestions().iter().map(|s| s.to_string()).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /**
+     * A short human readable report of the estimate, one line per fact.
+     *
+     * @return String
+     */
+    pub fn report(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "Password strength: {}/{} (about 10^{:.0} guesses)",
+                self.score,
+                MAX_SCORE,
+                self.guesses_log10
+            ),
+            format!(
+                "Time to crack: {} online (throttled), {} offline (slow hash)",
+                self.crack_time_online,
+                self.crack_time_offline
+            ),
+        ];
+
+        if let Some(warning) = &self.warning {
+            lines.push(format!("Warning: {warning}"));
+        }
+
+        for suggestion in self.suggestions.iter() {
+            lines.push(format!("Suggestion: {suggestion}"));
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_password_is_weak() {
+        let strength = Strength::estimate("password1", &[]);
+
+        assert_eq!(strength.score, 0);
+        assert!(strength.warning.is_some());
+    }
+
+    #[test]
+    fn test_patterns_are_weak() {
+        assert!(Strength::estimate("qwertyuiop", &[]).score <= 1);
+        assert!(Strength::estimate("aaaaaaaaaaaa", &[]).score <= 1);
+        assert!(Strength::estimate("p@ssw0rd", &[]).score <= 1);
+        assert!(Strength::estimate("01/02/1990", &[]).score <= 1);
+    }
+
+    #[test]
+    fn test_user_inputs_are_penalized() {
+        let without_inputs = Strength::estimate("stashpassmaufive", &[]);
+        let with_inputs = Strength::estimate("stashpassmaufive", &["stashpass", "maufive"]);
+
+        assert!(with_inputs.guesses_log10 < without_inputs.guesses_log10);
+    }
+
+    #[test]
+    fn test_generated_password_is_strong() {
+        let strength = Strength::estimate("kT9vQ2mXz7LwP4rB8nYc", &[]);
+
+        assert_eq!(strength.score, MAX_SCORE);
+        assert!(strength.report().starts_with("Password strength: 4/4"));
+    }
+}
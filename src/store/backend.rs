@@ -0,0 +1,183 @@
+use std::{ fmt::Debug, path::{ Path, PathBuf } };
+
+use serde::{ Deserialize, Serialize };
+
+use crate::{
+    backup::Backup,
+    merge::{ Conflict, Resolution },
+    password::PasswordEntry,
+    sync::PullOutcome,
+};
+
+/** Which storage backend holds the vault, set with `storage` in the config */
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /** A single JSON file, rewritten on every change */
+    #[default]
+    Json,
+    /** An embedded SQLite database, only the changed entry is written */
+    Sqlite,
+    /** Nothing is written to disk, everything is gone when the program exits */
+    Memory,
+    /**
+     * A folder with one encrypted file per entry, unlocked with the master password. Only the
+     * changed entry is written, which keeps synced copies from conflicting.
+     */
+    Directory,
+}
+
+impl StorageKind {
+    /** The default vault file for the backend */
+    pub fn default_path(&self) -> PathBuf {
+        match self {
+            StorageKind::Json => PathBuf::from("passwords.json"),
+            StorageKind::Sqlite => PathBuf::from("passwords.sqlite"),
+            StorageKind::Memory => PathBuf::from(":memory:"),
+            StorageKind::Directory => PathBuf::from("vault"),
+        }
+    }
+}
+
+/**
+ * Storage Backend
+ * Where the entries of a `PasswordStore` are persisted. The store keeps every entry in memory
+ * and calls the backend for each change, so a backend only reads and writes.
+ *
+ * Entries are identified by their service name. A backend is `Send`, so a store can be locked
+ * from another thread.
+ */
+pub trait StorageBackend: Debug + Send {
+    /**
+     * Read every entry.
+     *
+     * @return Result<Vec<PasswordEntry>, &'static str>
+     */
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str>;
+
+    /**
+     * Add an entry, or overwrite the entry with the same service.
+     *
+     * @param entry: &PasswordEntry
+     * @return Result<(), &'static str>
+     */
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str>;
+
+    /**
+     * Remove the entry of a service, removing a service that does not exist is not an error.
+     *
+     * @param service: &str
+     * @return Result<(), &'static str>
+     */
+    fn delete(&mut self, service: &str) -> Result<(), &'static str>;
+
+    /**
+     * Store an entry that was stored under another service and remove the old service. The
+     * entry is saved before the old one is removed, so it is never lost.
+     *
+     * @param service: &str the service the entry was stored under
+     * @param entry: &PasswordEntry
+     * @return Result<(), &'static str>
+     */
+    fn rename(&mut self, service: &str, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.save(entry)?;
+        self.delete(service)
+    }
+
+    /**
+     * Remove every entry and store the given ones instead.
+     *
+     * @param entries: &[PasswordEntry]
+     * @return Result<(), &'static str>
+     */
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str>;
+
+    /** Where the entries are stored, shown to the user */
+    fn location(&self) -> PathBuf;
+
+    /**
+     * The backups taken before writes, newest first.
+     *
+     * @return Result<Vec<Backup>, &'static str>
+     */
+    fn backups(&self) -> Result<Vec<Backup>, &'static str> {
+        Err("This storage backend does not keep backups")
+    }
+
+    /**
+     * Check that a backup can be read, so a damaged one is never restored.
+     *
+     * @param backup: &Path
+     * @return Result<usize, &'static str> the number of entries in the backup
+     */
+    fn verify_backup(&self, _backup: &Path) -> Result<usize, &'static str> {
+        Err("This storage backend does not keep backups")
+    }
+
+    /**
+     * Replace the stored entries with a backup.
+     *
+     * @param backup: &Path
+     * @return Result<(), &'static str>
+     */
+    fn restore_backup(&mut self, _backup: &Path) -> Result<(), &'static str> {
+        Err("This storage backend does not keep backups")
+    }
+
+    /** Forget any key held in memory, nothing is read or written until `unlock` */
+    fn lock(&mut self) {}
+
+    /**
+     * Unlock the backend again after `lock`. Backends without encryption have nothing to check,
+     * the caller verifies the master password for them.
+     *
+     * @param master_password: &str
+     * @return Result<(), &'static str>
+     */
+    fn unlock(&mut self, _master_password: &str) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /**
+     * Read the entries from another copy of the stored files, such as an older commit in git.
+     * `read` gives the content of a file by its path relative to the location. `None` when the
+     * copy has no vault at all.
+     *
+     * @param read: &dyn Fn(&Path) -> Option<String>
+     * @return Result<Option<Vec<PasswordEntry>>, &'static str>
+     */
+    fn load_snapshot(
+        &self,
+        _read: &dyn Fn(&Path) -> Option<String>
+    ) -> Result<Option<Vec<PasswordEntry>>, &'static str> {
+        Err("This storage backend can not be synced")
+    }
+
+    /**
+     * Send the recorded changes to a remote.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @return Result<(), &'static str>
+     */
+    fn push(&mut self, _remote: &str, _branch: &str) -> Result<(), &'static str> {
+        Err("Git sync is turned off, set git_sync in the config to turn it on")
+    }
+
+    /**
+     * Take the changes from a remote, `resolve` picks a side for entries changed on both.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @param resolve: &mut dyn FnMut(&Conflict) -> Resolution
+     * @return Result<PullOutcome, &'static str>
+     */
+    fn pull(
+        &mut self,
+        _remote: &str,
+        _branch: &str,
+        _resolve: &mut dyn FnMut(&Conflict) -> Resolution
+    ) -> Result<PullOutcome, &'static str> {
+        Err("Git sync is turned off, set git_sync in the config to turn it on")
+    }
+}
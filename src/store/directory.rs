@@ -0,0 +1,652 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs,
+    path::{ Path, PathBuf },
+};
+
+use base64::{ engine::general_purpose::STANDARD, Engine };
+use serde::{ de::DeserializeOwned, Deserialize, Serialize };
+use zeroize::Zeroize;
+
+use crate::{
+    crypto::{ self, KdfParams, KEY_LENGTH },
+    export,
+    password::{ is_valid_id, new_id, PasswordEntry },
+    store::backend::StorageBackend,
+};
+
+/** Marks a folder as a Stashpass vault */
+pub const VAULT_FORMAT: &str = "stashpass-vault";
+pub const VAULT_VERSION: u32 = 1;
+const CIPHER: &str = "xchacha20poly1305";
+
+const HEADER_FILE: &str = "vault.json";
+const INDEX_FILE: &str = "index.json";
+const ENTRIES_FOLDER: &str = "entries";
+
+/** Encrypted data as it is written to a file */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Sealed {
+    /** Base64 encoded nonce */
+    nonce: String,
+    /** Base64 encoded ciphertext */
+    ciphertext: String,
+}
+
+impl Sealed {
+    fn seal(key: &[u8; KEY_LENGTH], plaintext: &[u8], name: &str) -> Result<Sealed, &'static str> {
+        let (nonce, ciphertext) = crypto::encrypt(key, plaintext, &associated_data(name))?;
+
+        Ok(Sealed { nonce: STANDARD.encode(nonce), ciphertext: STANDARD.encode(ciphertext) })
+    }
+
+    fn open(&self, key: &[u8; KEY_LENGTH], name: &str) -> Result<Vec<u8>, &'static str> {
+        let nonce = STANDARD.decode(&self.nonce).map_err(|_| "A vault file is damaged")?;
+        let ciphertext = STANDARD.decode(&self.ciphertext).map_err(|_| "A vault file is damaged")?;
+
+        crypto::decrypt(key, &nonce, &ciphertext, &associated_data(name))
+    }
+}
+
+/**
+ * Every file is authenticated with its name, so an entry file can not be passed off as another
+ * entry or as the index.
+ */
+fn associated_data(name: &str) -> Vec<u8> {
+    format!("{VAULT_FORMAT}:{name}").into_bytes()
+}
+
+/**
+ * Vault Header
+ * The unencrypted `vault.json` of the folder. The entries are encrypted with a random vault
+ * key, which is stored here encrypted with a key derived from the master password. Changing
+ * the master password only rewrites the header.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct VaultHeader {
+    format: String,
+    version: u32,
+    cipher: String,
+    kdf: KdfParams,
+    key: Sealed,
+}
+
+impl VaultHeader {
+    fn new(
+        vault_key: &[u8; KEY_LENGTH],
+        master_password: &str,
+        kdf: KdfParams
+    ) -> Result<VaultHeader, &'static str> {
+        let password_key = kdf.derive_key(master_password)?;
+
+        Ok(VaultHeader {
+            format: VAULT_FORMAT.to_string(),
+            version: VAULT_VERSION,
+            cipher: CIPHER.to_string(),
+            kdf,
+            key: Sealed::seal(&password_key, vault_key, "key")?,
+        })
+    }
+
+    fn read(directory: &Path) -> Result<VaultHeader, &'static str> {
+        let content = fs
+            ::read_to_string(directory.join(HEADER_FILE))
+            .map_err(|_| "Unable to read the vault header")?;
+        let header: VaultHeader = serde_json
+            ::from_str(&content)
+            .map_err(|_| "The folder is not a Stashpass vault")?;
+
+        if header.format != VAULT_FORMAT {
+            return Err("The folder is not a Stashpass vault");
+        }
+
+        if header.version > VAULT_VERSION || header.cipher != CIPHER {
+            return Err("The vault was written by a newer version of Stashpass");
+        }
+
+        Ok(header)
+    }
+
+    fn vault_key(&self, master_password: &str) -> Result<[u8; KEY_LENGTH], &'static str> {
+        let password_key = self.kdf.derive_key(master_password)?;
+        let key = self.key.open(&password_key, "key").map_err(|_| "Wrong master password")?;
+
+        key.try_into().map_err(|_| "The vault header is damaged")
+    }
+}
+
+/**
+ * Directory Backend
+ * Keeps every entry in its own encrypted file, named by the entry's opaque id, next to an
+ * encrypted index of ids and services. A change only rewrites the file of that entry, and the
+ * index only when an entry is added, renamed or removed, so copies of the vault that were
+ * edited at the same time only differ in the files of the entries that were changed.
+ *
+ * - `vault.json`: the header, with the vault key encrypted by the master password
+ * - `index.json`: the id and service of every entry
+ * - `entries/<id>.json`: one entry
+ */
+pub struct DirectoryBackend {
+    directory: PathBuf,
+    key: [u8; KEY_LENGTH],
+}
+
+/** The key is left out, so it never ends up in a log */
+impl fmt::Debug for DirectoryBackend {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_struct("DirectoryBackend").field("directory", &self.directory).finish()
+    }
+}
+
+/** The key is wiped from memory once the vault is closed */
+impl Drop for DirectoryBackend {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl DirectoryBackend {
+    /**
+     * Whether a folder holds a vault.
+     *
+     * @param directory: &Path
+     * @return bool
+     */
+    pub fn exists(directory: &Path) -> bool {
+        directory.join(HEADER_FILE).exists()
+    }
+
+    /**
+     * Create a new, empty vault in a folder, encrypted with a new random key.
+     *
+     * @param directory: PathBuf
+     * @param master_password: &str
+     * @param kdf: KdfParams
+     * @return Result<DirectoryBackend, &'static str>
+     */
+    pub fn create(
+        directory: PathBuf,
+        master_password: &str,
+        kdf: KdfParams
+    ) -> Result<DirectoryBackend, &'static str> {
+        if DirectoryBackend::exists(&directory) {
+            return Err("There already is a vault in the folder");
+        }
+
+        let key = rand::random::<[u8; KEY_LENGTH]>();
+        let header = VaultHeader::new(&key, master_password, kdf)?;
+
+        fs
+            ::create_dir_all(directory.join(ENTRIES_FOLDER))
+            .map_err(|_| "Unable to create the vault folder")?;
+        write_file(&directory.join(HEADER_FILE), &serde_json::to_string_pretty(&header).unwrap())?;
+
+        let backend = DirectoryBackend { directory, key };
+        backend.write_index(&BTreeMap::new())?;
+
+        Ok(backend)
+    }
+
+    /**
+     * Get the key of a vault with the master password.
+     *
+     * @param directory: &Path
+     * @param master_password: &str
+     * @return Result<[u8; KEY_LENGTH], &'static str>
+     */
+    pub fn unlock(
+        directory: &Path,
+        master_password: &str
+    ) -> Result<[u8; KEY_LENGTH], &'static str> {
+        VaultHeader::read(directory)?.vault_key(master_password)
+    }
+
+    /**
+     * Open a vault with its key, the key is checked against the index.
+     *
+     * @param directory: PathBuf
+     * @param key: [u8; KEY_LENGTH]
+     * @return Result<DirectoryBackend, &'static str>
+     */
+    pub fn open(
+        directory: PathBuf,
+        key: [u8; KEY_LENGTH]
+    ) -> Result<DirectoryBackend, &'static str> {
+        VaultHeader::read(&directory)?;
+
+        let backend = DirectoryBackend { directory, key };
+        backend.read_index()?;
+
+        Ok(backend)
+    }
+
+    /**
+     * Encrypt the vault key with a new master password. The entries keep their key, so only
+     * the header is rewritten.
+     *
+     * @param directory: &Path
+     * @param master_password: &str
+     * @param new_master_password: &str
+     * @param kdf: KdfParams
+     * @return Result<(), &'static str>
+     */
+    pub fn change_master_password(
+        directory: &Path,
+        master_password: &str,
+        new_master_password: &str,
+        kdf: KdfParams
+    ) -> Result<(), &'static str> {
+        let key = DirectoryBackend::unlock(directory, master_password)?;
+        let header = VaultHeader::new(&key, new_master_password, kdf)?;
+
+        write_file(&directory.join(HEADER_FILE), &serde_json::to_string_pretty(&header).unwrap())
+    }
+
+    fn entry_path(&self, id: &str) -> PathBuf {
+        self.directory.join(ENTRIES_FOLDER).join(format!("{id}.json"))
+    }
+
+    fn seal<T: Serialize>(&self, value: &T, name: &str) -> Result<String, &'static str> {
+        let plaintext = serde_json::to_vec(value).map_err(|_| "Unable to serialize the entry")?;
+
+        Ok(serde_json::to_string_pretty(&Sealed::seal(&self.key, &plaintext, name)?).unwrap())
+    }
+
+    fn unseal<T: DeserializeOwned>(&self, content: &str, name: &str) -> Result<T, &'static str> {
+        let sealed: Sealed = serde_json::from_str(content).map_err(|_| "A vault file is damaged")?;
+        let plaintext = sealed.open(&self.key, name)?;
+
+        serde_json::from_slice(&plaintext).map_err(|_| "A vault file can not be read")
+    }
+
+    /**
+     * Read the index from a copy of the vault files and then every entry it lists.
+     *
+     * @param read: &dyn Fn(&Path) -> Option<String>
+     * @return Result<Option<Vec<PasswordEntry>>, &'static str>
+     */
+    fn read_entries(
+        &self,
+        read: &dyn Fn(&Path) -> Option<String>
+    ) -> Result<Option<Vec<PasswordEntry>>, &'static str> {
+        let index: BTreeMap<String, String> = match read(Path::new(INDEX_FILE)) {
+            Some(content) => self.unseal(&content, "index")?,
+            None => {
+                return Ok(None);
+            }
+        };
+        let mut entries = vec![];
+
+        for id in index.keys() {
+            let path = Path::new(ENTRIES_FOLDER).join(format!("{id}.json"));
+            let content = read(&path).ok_or("The index lists an entry that does not exist")?;
+            entries.push(self.unseal(&content, &format!("entry:{id}"))?);
+        }
+
+        Ok(Some(entries))
+    }
+
+    fn read_index(&self) -> Result<BTreeMap<String, String>, &'static str> {
+        let content = fs
+            ::read_to_string(self.directory.join(INDEX_FILE))
+            .map_err(|_| "Unable to read the vault index")?;
+
+        self.unseal(&content, "index")
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, String>) -> Result<(), &'static str> {
+        write_file(&self.directory.join(INDEX_FILE), &self.seal(index, "index")?)
+    }
+
+    fn read_entry(&self, id: &str) -> Option<PasswordEntry> {
+        let content = fs::read_to_string(self.entry_path(id)).ok()?;
+
+        self.unseal(&content, &format!("entry:{id}")).ok()
+    }
+
+    /** Write an entry, its file is left alone when it did not change */
+    fn write_entry(&self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        if self.read_entry(&entry.id).as_ref() == Some(entry) {
+            return Ok(());
+        }
+
+        write_file(&self.entry_path(&entry.id), &self.seal(entry, &format!("entry:{}", entry.id))?)
+    }
+
+    fn remove_entry(&self, id: &str) -> Result<(), &'static str> {
+        match fs::remove_file(self.entry_path(id)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err("Failed to delete the entry file")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /**
+     * Give an entry the id it is stored under: its own, the id of the stored entry with the
+     * same service, or a new one for entries from vaults that had no ids yet. The id names the
+     * entry file, so an id that is not in the form of `new_id` is never used, wherever the
+     * entry came from.
+     */
+    fn with_id(entry: &PasswordEntry, index: &BTreeMap<String, String>) -> PasswordEntry {
+        let mut entry = entry.clone();
+
+        if !is_valid_id(&entry.id) {
+            entry.id = index
+                .iter()
+                .find(|(_, service)| **service == entry.service)
+                .map(|(id, _)| id.clone())
+                .unwrap_or_else(new_id);
+        }
+
+        entry
+    }
+
+    /**
+     * Write an entry and list it in the index. `renamed` is the service the entry was stored
+     * under before, the entry may only take over the id of that service.
+     */
+    fn write(&mut self, entry: &PasswordEntry, renamed: Option<&str>) -> Result<(), &'static str> {
+        let mut index = self.read_index()?;
+        let mut entry = Self::with_id(entry, &index);
+
+        // An id that is stored for another service is only kept when that entry is renamed
+        let taken = index.get(&entry.id).filter(|indexed| **indexed != entry.service);
+        if taken.is_some() && taken.map(String::as_str) != renamed {
+            entry.id = new_id();
+        }
+        self.write_entry(&entry)?;
+
+        let replaced: Vec<String> = index
+            .iter()
+            .filter(|(id, service)| **service == entry.service && **id != entry.id)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        if index.get(&entry.id) == Some(&entry.service) && replaced.is_empty() {
+            return Ok(());
+        }
+
+        index.insert(entry.id.clone(), entry.service.clone());
+        for id in replaced.iter() {
+            index.remove(id);
+        }
+        self.write_index(&index)?;
+
+        replaced.iter().try_for_each(|id| self.remove_entry(id))
+    }
+}
+
+/**
+ * Write a file readable only by its owner, through a temporary file so an interrupted write
+ * never leaves a half written file behind.
+ */
+fn write_file(path: &Path, content: &str) -> Result<(), &'static str> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+
+    export::write_private_file(Path::new(&tmp_path), content)
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .map_err(|_| "Failed to write a vault file")
+}
+
+impl StorageBackend for DirectoryBackend {
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        let read = |path: &Path| fs::read_to_string(self.directory.join(path)).ok();
+
+        Ok(self.read_entries(&read)?.unwrap_or_default())
+    }
+
+    /**
+     * Writes the entry file first and then the index, when the entry is new or renamed. An
+     * entry with the same service but another id is replaced.
+     */
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.write(entry, None)
+    }
+
+    /** The entry keeps its id, so its file is rewritten in place */
+    fn rename(&mut self, service: &str, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.write(entry, Some(service))?;
+        self.delete(service)
+    }
+
+    /** The index is written first, so a removed entry is never listed without its file */
+    fn delete(&mut self, service: &str) -> Result<(), &'static str> {
+        let mut index = self.read_index()?;
+        let ids: Vec<String> = index
+            .iter()
+            .filter(|(_, indexed)| *indexed == service)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        index.retain(|id, _| !ids.contains(id));
+        self.write_index(&index)?;
+
+        ids.iter().try_for_each(|id| self.remove_entry(id))
+    }
+
+    /**
+     * Only the entries that changed are rewritten. When two entries have the same id, the one
+     * stored under it keeps the id and the other gets a new one.
+     */
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str> {
+        let old_index = self.read_index()?;
+        let mut entries: Vec<PasswordEntry> = entries
+            .iter()
+            .map(|entry| Self::with_id(entry, &old_index))
+            .collect();
+        entries.sort_by_key(|entry| old_index.get(&entry.id) != Some(&entry.service));
+
+        let mut index = BTreeMap::new();
+        for entry in entries.iter_mut() {
+            if index.contains_key(&entry.id) {
+                entry.id = new_id();
+            }
+            index.insert(entry.id.clone(), entry.service.clone());
+        }
+
+        for entry in entries.iter() {
+            self.write_entry(entry)?;
+        }
+
+        if index != old_index {
+            self.write_index(&index)?;
+        }
+
+        old_index
+            .keys()
+            .filter(|id| !index.contains_key(*id))
+            .try_for_each(|id| self.remove_entry(id))
+    }
+
+    fn location(&self) -> PathBuf {
+        self.directory.clone()
+    }
+
+    fn lock(&mut self) {
+        self.key.zeroize();
+    }
+
+    fn unlock(&mut self, master_password: &str) -> Result<(), &'static str> {
+        self.key = DirectoryBackend::unlock(&self.directory, master_password)?;
+
+        Ok(())
+    }
+
+    fn load_snapshot(
+        &self,
+        read: &dyn Fn(&Path) -> Option<String>
+    ) -> Result<Option<Vec<PasswordEntry>>, &'static str> {
+        self.read_entries(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&directory);
+
+        directory
+    }
+
+    fn files(directory: &Path) -> Vec<(String, String)> {
+        let mut files: Vec<(String, String)> = fs
+            ::read_dir(directory.join(ENTRIES_FOLDER))
+            .unwrap()
+            .map(|file| {
+                let path = file.unwrap().path();
+                (path.to_string_lossy().to_string(), fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        files.sort();
+
+        files
+    }
+
+    #[test]
+    fn test_entries_are_stored_in_separate_files() {
+        let directory = vault("stashpass_test_directory_vault");
+        let mut backend = DirectoryBackend::create(
+            directory.clone(),
+            "master",
+            KdfParams::fast()
+        ).unwrap();
+
+        let github = PasswordEntry::new(
+            "github".to_string(),
+            "me".to_string(),
+            "correct-horse".to_string()
+        );
+        let mail = PasswordEntry::new("mail".to_string(), "me".to_string(), "staple".to_string());
+        backend.save(&github).unwrap();
+        backend.save(&mail).unwrap();
+
+        let before = files(&directory);
+        let index = fs::read_to_string(directory.join(INDEX_FILE)).unwrap();
+        assert_eq!(before.len(), 2);
+        assert!(before.iter().all(|(name, content)| {
+            !name.contains("github") && !content.contains("correct-horse")
+        }));
+
+        // Changing one entry leaves the other entry and the index alone
+        let mut changed = github.clone();
+        changed.change_password("three".to_string());
+        backend.replace_all(&[changed.clone(), mail.clone()]).unwrap();
+        let after = files(&directory);
+        assert_eq!(after.iter().filter(|file| before.contains(file)).count(), 1);
+        assert_eq!(fs::read_to_string(directory.join(INDEX_FILE)).unwrap(), index);
+
+        assert_eq!(DirectoryBackend::unlock(&directory, "wrong"), Err("Wrong master password"));
+        DirectoryBackend::change_master_password(
+            &directory,
+            "master",
+            "new master",
+            KdfParams::fast()
+        ).unwrap();
+        let key = DirectoryBackend::unlock(&directory, "new master").unwrap();
+        let mut backend = DirectoryBackend::open(directory.clone(), key).unwrap();
+
+        let mut entries = backend.load().unwrap();
+        entries.sort_by(|a, b| a.service.cmp(&b.service));
+        assert_eq!(entries, vec![changed, mail]);
+
+        backend.delete("github").unwrap();
+        assert_eq!(files(&directory).len(), 1);
+        assert_eq!(backend.load().unwrap().len(), 1);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_entries_without_id_keep_their_file() {
+        let directory = vault("stashpass_test_directory_vault_ids");
+        let mut backend = DirectoryBackend::create(
+            directory.clone(),
+            "master",
+            KdfParams::fast()
+        ).unwrap();
+
+        let mut entry = PasswordEntry::new("github".to_string(), "me".to_string(), "1".to_string());
+        entry.id = String::new();
+        backend.save(&entry).unwrap();
+        entry.change_password("2".to_string());
+        backend.save(&entry).unwrap();
+
+        let entries = backend.load().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].password, "2");
+        assert!(!entries[0].id.is_empty());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_ids_are_replaced() {
+        let directory = vault("stashpass_test_directory_vault_invalid_ids");
+        let mut backend = DirectoryBackend::create(
+            directory.clone(),
+            "master",
+            KdfParams::fast()
+        ).unwrap();
+
+        let mut entry = PasswordEntry::new("github".to_string(), "me".to_string(), "1".to_string());
+        entry.id = "../../outside".to_string();
+        backend.save(&entry).unwrap();
+        let mut mail = PasswordEntry::new("mail".to_string(), "me".to_string(), "2".to_string());
+        mail.id = "A".repeat(32);
+        backend.replace_all(&[entry, mail]).unwrap();
+
+        let entries = backend.load().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| is_valid_id(&entry.id)));
+        assert_eq!(files(&directory).len(), 2);
+        assert!(!directory.join(ENTRIES_FOLDER).join("../../outside.json").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_services_with_the_same_id_keep_their_files() {
+        let directory = vault("stashpass_test_directory_vault_same_id");
+        let mut backend = DirectoryBackend::create(
+            directory.clone(),
+            "master",
+            KdfParams::fast()
+        ).unwrap();
+
+        let github = PasswordEntry::new("github".to_string(), "me".to_string(), "1".to_string());
+        backend.save(&github).unwrap();
+
+        // A rename keeps the id
+        let gh = PasswordEntry { service: "gh".to_string(), ..github.clone() };
+        backend.rename("github", &gh).unwrap();
+        assert_eq!(backend.load().unwrap(), vec![gh.clone()]);
+
+        // An entry from a bundle with the id of the renamed entry gets a new id
+        backend.save(&github).unwrap();
+        let mut entries = backend.load().unwrap();
+        entries.sort_by(|a, b| a.service.cmp(&b.service));
+        assert_eq!(entries[0], gh);
+        assert_eq!(entries[1].service, "github");
+        assert_ne!(entries[1].id, gh.id);
+
+        backend.replace_all(&[github.clone(), gh.clone()]).unwrap();
+        let mut entries = backend.load().unwrap();
+        entries.sort_by(|a, b| a.service.cmp(&b.service));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], gh);
+        assert_ne!(entries[1].id, gh.id);
+        assert_eq!(files(&directory).len(), 2);
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+use std::{ collections::HashMap, path::{ Path, PathBuf } };
+
+use crate::{
+    backup::Backup,
+    merge::{ Conflict, Resolution },
+    password::PasswordEntry,
+    store::backend::StorageBackend,
+    sync::{ GitRepository, PullOutcome },
+};
+
+/**
+ * Git Backend
+ * Wraps the backend of an encrypted vault folder that is a git repository and commits the folder
+ * after every change. The commit messages only name the opaque id of an entry, never its service or
+ * any of its values, since the history may be pushed to a remote.
+ */
+#[derive(Debug)]
+pub struct GitBackend {
+    inner: Box<dyn StorageBackend>,
+    repository: GitRepository,
+    /** The id of every stored entry by service, to tell additions from updates */
+    ids: HashMap<String, String>,
+}
+
+impl GitBackend {
+    /**
+     * Open the repository of the vault folder of a backend, it is initialized when needed and the
+     * vault is committed when it has changes that were not committed yet.
+     *
+     * @param inner: Box<dyn StorageBackend>
+     * @return Result<GitBackend, &'static str>
+     */
+    pub fn new(inner: Box<dyn StorageBackend>) -> Result<GitBackend, &'static str> {
+        let repository = GitRepository::for_vault(&inner.location()).map_err(|err| {
+            eprintln!("{err}");
+            "Unable to open the git repository of the vault"
+        })?;
+
+        let entries = inner.load()?;
+        let mut backend = GitBackend { inner, repository, ids: HashMap::new() };
+        backend.remember(&entries);
+        backend.commit("Start tracking the vault")?;
+
+        Ok(backend)
+    }
+
+    fn remember(&mut self, entries: &[PasswordEntry]) {
+        self.ids = entries
+            .iter()
+            .map(|entry| (entry.service.clone(), entry.id.clone()))
+            .collect();
+    }
+
+    /** A short form of an id for commit messages, entries from older vaults may have none */
+    fn describe(id: &str) -> String {
+        match id.get(..8) {
+            Some(short) => format!("entry {short}"),
+            None => "an entry".to_string(),
+        }
+    }
+
+    fn commit(&self, message: &str) -> Result<(), &'static str> {
+        self.repository.commit(message).map_err(|err| {
+            eprintln!("{err}");
+            "The change was saved but could not be committed to git"
+        })
+    }
+}
+
+impl StorageBackend for GitBackend {
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        self.inner.load()
+    }
+
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.inner.save(entry)?;
+
+        let action = match self.ids.insert(entry.service.clone(), entry.id.clone()) {
+            Some(_) => "Update",
+            None => "Add",
+        };
+
+        self.commit(&format!("{action} {}", Self::describe(&entry.id)))
+    }
+
+    fn delete(&mut self, service: &str) -> Result<(), &'static str> {
+        self.inner.delete(service)?;
+
+        match self.ids.remove(service) {
+            Some(id) => self.commit(&format!("Delete {}", Self::describe(&id))),
+            None => Ok(()),
+        }
+    }
+
+    fn rename(&mut self, service: &str, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.inner.rename(service, entry)?;
+        self.ids.remove(service);
+        self.ids.insert(entry.service.clone(), entry.id.clone());
+
+        self.commit(&format!("Rename {}", Self::describe(&entry.id)))
+    }
+
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str> {
+        self.inner.replace_all(entries)?;
+        self.remember(entries);
+
+        self.commit(&format!("Replace the vault with {} entries", entries.len()))
+    }
+
+    fn location(&self) -> PathBuf {
+        self.inner.location()
+    }
+
+    fn backups(&self) -> Result<Vec<Backup>, &'static str> {
+        self.inner.backups()
+    }
+
+    fn verify_backup(&self, backup: &Path) -> Result<usize, &'static str> {
+        self.inner.verify_backup(backup)
+    }
+
+    fn restore_backup(&mut self, backup: &Path) -> Result<(), &'static str> {
+        self.inner.restore_backup(backup)?;
+        let entries = self.inner.load()?;
+        self.remember(&entries);
+
+        self.commit("Restore a backup")
+    }
+
+    fn lock(&mut self) {
+        self.inner.lock();
+    }
+
+    fn unlock(&mut self, master_password: &str) -> Result<(), &'static str> {
+        self.inner.unlock(master_password)
+    }
+
+    fn load_snapshot(
+        &self,
+        read: &dyn Fn(&Path) -> Option<String>
+    ) -> Result<Option<Vec<PasswordEntry>>, &'static str> {
+        self.inner.load_snapshot(read)
+    }
+
+    fn push(&mut self, remote: &str, branch: &str) -> Result<(), &'static str> {
+        self.repository.push(remote, branch).map_err(|err| {
+            eprintln!("{err}");
+            "Unable to push the vault"
+        })
+    }
+
+    /** The merge is written through the wrapped backend, the pull commits it itself */
+    fn pull(
+        &mut self,
+        remote: &str,
+        branch: &str,
+        resolve: &mut dyn FnMut(&Conflict) -> Resolution
+    ) -> Result<PullOutcome, &'static str> {
+        let outcome = self.repository
+            .pull(remote, branch, self.inner.as_mut(), resolve)
+            .map_err(|err| {
+                eprintln!("{err}");
+                "Unable to pull the vault"
+            })?;
+
+        let entries = self.inner.load()?;
+        self.remember(&entries);
+
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    fs::{ self, File },
+    path::{ Path, PathBuf },
+    io::{ BufWriter, Write },
+};
+
+use serde_json::{ Value, Map };
+
+use crate::{
+    backup::{ self, Backup, Backups },
+    password::PasswordEntry,
+    store::backend::StorageBackend,
+};
+
+/**
+ * JSON File Backend
+ * Stores the vault as a single JSON object that maps service names to entries. The whole file
+ * is rewritten on every change, with an optional backup of the previous version.
+ */
+#[derive(Debug)]
+pub struct JsonFileBackend {
+    file_path: PathBuf,
+    backups: Option<Backups>,
+}
+
+impl JsonFileBackend {
+    /**
+     * Open the vault file, it is created when it does not exist yet.
+     *
+     * @param file_path: PathBuf
+     * @return Result<JsonFileBackend, &'static str>
+     */
+    pub fn new(file_path: PathBuf) -> Result<JsonFileBackend, &'static str> {
+        if !file_path.exists() {
+            File::create(&file_path).map_err(|_| "Unable to create file")?;
+        }
+
+        Ok(JsonFileBackend { file_path, backups: None })
+    }
+
+    /**
+     * Keep backups of the file, one is taken before every write.
+     *
+     * @param backups: Backups
+     */
+    pub fn set_backups(&mut self, backups: Backups) {
+        self.backups = Some(backups);
+    }
+
+    /**
+     * Read the JSON object from the file.
+     * An empty file is an empty vault, a file that is not valid JSON is an error so it is never
+     * overwritten with an empty vault.
+     *
+     * @return Result<Map<String, Value>, &'static str>
+     */
+    fn read_json(&self) -> Result<Map<String, Value>, &'static str> {
+        let content = fs
+            ::read_to_string(&self.file_path)
+            .map_err(|_| "Unable to open the vault file")?;
+
+        if content.trim().is_empty() {
+            return Ok(Map::new());
+        }
+
+        serde_json::from_str(&content).map_err(|_| "The vault file can not be read")
+    }
+
+    /**
+     * Read the entries of a vault from the content of a vault file, such as an older version
+     * of it. Empty content is an empty vault.
+     *
+     * @param content: &str
+     * @return Result<Vec<PasswordEntry>, &'static str>
+     */
+    pub fn parse(content: &str) -> Result<Vec<PasswordEntry>, &'static str> {
+        if content.trim().is_empty() {
+            return Ok(vec![]);
+        }
+
+        let json_obj = serde_json
+            ::from_str(content)
+            .map_err(|_| "The vault file can not be read")?;
+
+        Self::entries_from_json(json_obj)
+    }
+
+    /**
+     * The file maps service names to entries, so the service is taken from the key.
+     *
+     * @param json_obj: Map<String, Value>
+     * @return Result<Vec<PasswordEntry>, &'static str>
+     */
+    fn entries_from_json(json_obj: Map<String, Value>) -> Result<Vec<PasswordEntry>, &'static str> {
+        let mut entries = vec![];
+
+        for (service, entry) in json_obj.into_iter() {
+            let mut password_entry: PasswordEntry = serde_json
+                ::from_value(entry)
+                .map_err(|_| "The vault file contains an entry that can not be read")?;
+            password_entry.service = service;
+
+            entries.push(password_entry);
+        }
+
+        Ok(entries)
+    }
+
+    /**
+     * Convert an entry into the JSON value stored under its service name.
+     * The service itself is left out since it is already the key.
+     *
+     * @param entry: &PasswordEntry
+     * @return Value
+     */
+    fn entry_to_json(entry: &PasswordEntry) -> Value {
+        let mut value = serde_json::to_value(entry).unwrap();
+
+        if let Value::Object(map) = &mut value {
+            map.remove("service");
+        }
+
+        value
+    }
+
+    /**
+     * Write the JSON object to the file
+     * The content is written to a temporary file next to the password file first and then moved
+     * over it, so an interrupted write never leaves a half written file behind. When backups are
+     * turned on the current file is backed up first.
+     *
+     * @param json_obj: &Map<String, Value>
+     * @return std::io::Result<()>
+     */
+    fn write_json(&self, json_obj: &Map<String, Value>) -> std::io::Result<()> {
+        if let Some(backups) = &self.backups {
+            backups.create(&self.file_path)?;
+        }
+
+        let mut tmp_path = self.file_path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let file = File::create(&tmp_path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(serde_json::to_string_pretty(json_obj).unwrap().as_bytes())?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+
+        fs::rename(&tmp_path, &self.file_path)
+    }
+}
+
+impl StorageBackend for JsonFileBackend {
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        Self::entries_from_json(self.read_json()?)
+    }
+
+    /**
+     * Reads the existing file, sets the entry in the JSON object and writes it back.
+     */
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        let mut json_obj = self.read_json()?;
+        json_obj.insert(entry.service.clone(), Self::entry_to_json(entry));
+
+        self.write_json(&json_obj).map_err(|_| "Failed to save entry to file")
+    }
+
+    fn delete(&mut self, service: &str) -> Result<(), &'static str> {
+        let mut json_obj = self.read_json()?;
+
+        if json_obj.remove(service).is_none() {
+            return Ok(());
+        }
+
+        self.write_json(&json_obj).map_err(|_| "Failed to delete entry from file")
+    }
+
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str> {
+        let json_obj = entries
+            .iter()
+            .map(|entry| (entry.service.clone(), Self::entry_to_json(entry)))
+            .collect();
+
+        self.write_json(&json_obj).map_err(|_| "Failed to write entries to file")
+    }
+
+    fn location(&self) -> PathBuf {
+        self.file_path.clone()
+    }
+
+    fn backups(&self) -> Result<Vec<Backup>, &'static str> {
+        self.backups
+            .as_ref()
+            .ok_or("Backups are turned off")?
+            .list(&self.file_path)
+            .map_err(|_| "Unable to read the backup folder")
+    }
+
+    fn verify_backup(&self, backup: &Path) -> Result<usize, &'static str> {
+        backup::verify(backup)
+    }
+
+    /**
+     * The backup is only restored when it can be read. The current file is backed up first, so
+     * the restore itself can be undone.
+     */
+    fn restore_backup(&mut self, backup: &Path) -> Result<(), &'static str> {
+        backup::verify(backup)?;
+
+        let content = fs::read_to_string(backup).map_err(|_| "Unable to read the backup")?;
+        let json_obj: Map<String, Value> = serde_json
+            ::from_str(&content)
+            .map_err(|_| "The backup is damaged and can not be read")?;
+
+        self.write_json(&json_obj).map_err(|_| "Failed to restore the backup")
+    }
+}
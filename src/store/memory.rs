@@ -0,0 +1,52 @@
+use std::{ collections::HashMap, path::PathBuf };
+
+use crate::{ password::PasswordEntry, store::backend::StorageBackend };
+
+/**
+ * Memory Backend
+ * Keeps the entries in memory only. Useful for tests and for trying things out without
+ * touching a vault on disk.
+ */
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    entries: HashMap<String, PasswordEntry>,
+}
+
+impl MemoryBackend {
+    pub fn new(entries: Vec<PasswordEntry>) -> MemoryBackend {
+        MemoryBackend {
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.service.clone(), entry))
+                .collect(),
+        }
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        Ok(self.entries.values().cloned().collect())
+    }
+
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.entries.insert(entry.service.clone(), entry.clone());
+
+        Ok(())
+    }
+
+    fn delete(&mut self, service: &str) -> Result<(), &'static str> {
+        self.entries.remove(service);
+
+        Ok(())
+    }
+
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str> {
+        *self = MemoryBackend::new(entries.to_vec());
+
+        Ok(())
+    }
+
+    fn location(&self) -> PathBuf {
+        PathBuf::from(":memory:")
+    }
+}
@@ -1,60 +1,175 @@
-use std::{
-    fs::{ self, OpenOptions, File },
-    collections::HashMap,
-    path::PathBuf,
-    io::{ BufWriter, Write, BufReader },
+pub mod backend;
+pub mod directory;
+pub mod git;
+pub mod json;
+pub mod memory;
+pub mod sqlite;
+
+use std::{ collections::HashMap, path::{ Path, PathBuf } };
+
+use crate::{
+    backup::{ Backup, Backups },
+    config::Config,
+    crypto::KEY_LENGTH,
+    merge::{ Conflict, Resolution },
+    password::{ unix_timestamp, PasswordEntry },
+    sync::PullOutcome,
 };
 
-use crate::password::PasswordEntry;
-use serde_json::{ Value, Map, json };
+use self::{
+    backend::{ StorageBackend, StorageKind },
+    directory::DirectoryBackend,
+    git::GitBackend,
+    json::JsonFileBackend,
+    memory::MemoryBackend,
+    sqlite::SqliteBackend,
+};
 
 /**
  * Password Store
- * The PasswordStore is responsible for managing the passwords, saving them to file and
- * reading into memory.
+ * The PasswordStore is responsible for managing the passwords, keeping them in memory and
+ * persisting every change through its storage backend.
  *
- * It exposes methods to work with password entries and the file.
+ * It exposes methods to work with password entries, the backend decides where they are kept.
  */
 #[derive(Debug)]
 pub struct PasswordStore {
     passwords: HashMap<String, PasswordEntry>,
-    file_path: PathBuf,
+    backend: Box<dyn StorageBackend>,
+    locked: bool,
 }
 
 impl PasswordStore {
+    /**
+     * Open a store backed by a JSON file, the file is created when it does not exist yet.
+     *
+     * @param file_path: PathBuf
+     * @return Result<PasswordStore, &'static str>
+     */
     pub fn new(file_path: PathBuf) -> Result<PasswordStore, &'static str> {
-        let mut store = PasswordStore {
-            passwords: HashMap::new(),
-            file_path: file_path.clone(),
+        PasswordStore::with_backend(Box::new(JsonFileBackend::new(file_path)?))
+    }
+
+    /**
+     * Open the store on the backend chosen in the config. The encrypted directory vault needs
+     * its key, see `open_with_key`.
+     *
+     * @param config: &Config
+     * @return Result<PasswordStore, &'static str>
+     */
+    pub fn open(config: &Config) -> Result<PasswordStore, &'static str> {
+        PasswordStore::open_with_key(config, None)
+    }
+
+    /**
+     * Open the store on the backend chosen in the config, with the key of the encrypted
+     * directory vault when that is the backend. Backups are taken for the JSON file and the
+     * database. With `git_sync` every change to the vault is also
+     * committed to git, the encrypted vault folder is the repository. The other storages are
+     * refused, they would put plaintext passwords in the history.
+     *
+     * @param config: &Config
+     * @param key: Option<[u8; KEY_LENGTH]>
+     * @return Result<PasswordStore, &'static str>
+     */
+    pub fn open_with_key(
+        config: &Config,
+        key: Option<[u8; KEY_LENGTH]>
+    ) -> Result<PasswordStore, &'static str> {
+        if config.git_sync && config.storage != StorageKind::Directory {
+            return Err("Git sync only works with the encrypted directory storage");
+        }
+
+        let file_path = config.storage.default_path();
+        let backend: Box<dyn StorageBackend> = match config.storage {
+            StorageKind::Json => {
+                let mut backend = JsonFileBackend::new(file_path.clone())?;
+                backend.set_backups(Backups::from_config(config, &file_path));
+                Box::new(backend)
+            }
+            StorageKind::Sqlite => {
+                let mut backend = SqliteBackend::open(file_path.clone())?;
+                backend.set_backups(Backups::from_config(config, &file_path));
+                Box::new(backend)
+            }
+            StorageKind::Memory => Box::new(MemoryBackend::default()),
+            StorageKind::Directory => {
+                let key = key.ok_or("The vault is encrypted, unlock it with the master password")?;
+                Box::new(DirectoryBackend::open(file_path, key)?)
+            }
         };
 
-        if file_path.exists() {
-            store.load();
-        } else {
-            fs::File::create(file_path).expect("Unable to create file");
+        if !config.git_sync {
+            return PasswordStore::with_backend(backend);
         }
 
+        PasswordStore::with_backend(Box::new(GitBackend::new(backend)?))
+    }
+
+    /**
+     * Open a store on any storage backend and load its entries.
+     *
+     * @param backend: Box<dyn StorageBackend>
+     * @return Result<PasswordStore, &'static str>
+     */
+    pub fn with_backend(backend: Box<dyn StorageBackend>) -> Result<PasswordStore, &'static str> {
+        let mut store = PasswordStore {
+            passwords: HashMap::new(),
+            backend,
+            locked: false,
+        };
+        store.load()?;
+
         Ok(store)
     }
 
     /**
-     * Load passwords from file into memory
+     * Load passwords from the backend into memory
+     * Replaces whatever was in memory before.
      *
-     * The method will read the file and parse the content into a PasswordEntry object.
-     * The PasswordEntry object will then be added to the in-memory store.
+     * @return Result<(), &'static str>
      */
-    pub fn load(&mut self) {
-        let file = File::open(&self.file_path).unwrap();
-        let reader = BufReader::new(file);
-        let json_obj: Map<String, Value> = serde_json::from_reader(reader).unwrap();
+    pub fn load(&mut self) -> Result<(), &'static str> {
+        self.passwords.clear();
 
-        for (service, entry) in json_obj.iter() {
-            let username = entry["username"].as_str().unwrap().to_string();
-            let password = entry["password"].as_str().unwrap().to_string();
-            let password_entry = PasswordEntry::new(service.clone(), username, password);
+        for entry in self.backend.load()?.into_iter() {
+            self.add(entry);
+        }
 
-            self.add(password_entry);
+        Ok(())
+    }
+
+    /**
+     * Lock the store
+     * Wipes every decrypted entry from memory and has the backend forget its key. The store is
+     * empty until it is unlocked again.
+     */
+    pub fn lock(&mut self) {
+        for (_, mut entry) in self.passwords.drain() {
+            entry.wipe();
         }
+
+        self.backend.lock();
+        self.locked = true;
+    }
+
+    /**
+     * Unlock the store
+     * Unlocks the backend with the master password and loads the entries again.
+     *
+     * @param master_password: &str
+     * @return Result<(), &'static str>
+     */
+    pub fn unlock(&mut self, master_password: &str) -> Result<(), &'static str> {
+        self.backend.unlock(master_password)?;
+        self.load()?;
+        self.locked = false;
+
+        Ok(())
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
     }
 
     fn add(&mut self, entry: PasswordEntry) {
@@ -65,8 +180,20 @@ impl PasswordStore {
         self.passwords.get(service)
     }
 
+    /**
+     * All entries in the store, sorted by service name.
+     *
+     * @return Vec<&PasswordEntry>
+     */
+    pub fn entries(&self) -> Vec<&PasswordEntry> {
+        let mut entries: Vec<&PasswordEntry> = self.passwords.values().collect();
+        entries.sort_by(|a, b| a.service.cmp(&b.service));
+
+        entries
+    }
+
     pub fn get_file_path(&self) -> PathBuf {
-        self.file_path.clone()
+        self.backend.location()
     }
 
     /**
@@ -90,42 +217,19 @@ impl PasswordStore {
     }
 
     /**
-     * Save entry to file
-     * The method will read the existing JSON file, add the new entry to the JSON object and then
-     * write the updated JSON object to the file.
+     * Save entry
+     * The method will hand the entry to the storage backend, which adds it or overwrites the
+     * entry with the same service.
      *
      * @param entry: PasswordEntry
      * @return Result<PasswordEntry, &'static str>
      */
-    fn save_entry(&self, entry: PasswordEntry) -> Result<PasswordEntry, &'static str> {
+    fn save_entry(&mut self, entry: PasswordEntry) -> Result<PasswordEntry, &'static str> {
         println!("Saving entry for service: {} to file...", &entry.service);
 
-        // Read the existing JSON file
-        let file = File::open(&self.file_path).unwrap();
-        let reader = BufReader::new(file);
-        let mut json_obj: Map<String, Value> = match serde_json::from_reader(reader) {
-            Ok(json) => json,
-            Err(_) => Map::new(), // In case the file is empty
-        };
+        self.backend.save(&entry)?;
 
-        // Add new entry to the JSON object
-        json_obj.insert(
-            entry.service.clone(),
-            json!({
-                "username": &entry.username,
-                "password": &entry.password
-            })
-        );
-
-        // Write the updated JSON object to the file
-        let file = OpenOptions::new().write(true).truncate(true).open(&self.file_path).unwrap();
-        let mut writer = BufWriter::new(file);
-        let result = writer.write_all(serde_json::to_string_pretty(&json_obj).unwrap().as_bytes());
-
-        match result {
-            Ok(_) => Ok(entry),
-            Err(_) => Err("Failed to save entry to file"),
-        }
+        Ok(entry)
     }
 
     /**
@@ -150,47 +254,169 @@ impl PasswordStore {
     }
 
     /**
-     * Update entry in file
-     * The method will read the existing JSON file, update the entry with the new password and then
-     * write the updated JSON object to the file.
+     * Update entry
+     * The method will mark the entry as modified now, overwrite the stored entry with the same
+     * service and then update the in-memory store, so a failed write changes nothing.
      *
      * @param entry: PasswordEntry
      * @return Result<(), &'static str>
      */
-    pub fn update_entry(&mut self, entry: PasswordEntry) -> Result<(), &'static str> {
-        let file = File::open(&self.file_path).unwrap();
-        let reader = BufReader::new(file);
+    pub fn update_entry(&mut self, mut entry: PasswordEntry) -> Result<(), &'static str> {
+        entry.modified_at = unix_timestamp();
+        self.backend.save(&entry).map_err(|_| "Failed to update entry in file")?;
+        self.add(entry);
 
-        // Read the existing JSON file
-        let mut json_obj: Map<String, Value> = match serde_json::from_reader(reader) {
-            Ok(json) => json,
-            Err(_) => Map::new(), // In case the file is empty
-        };
+        Ok(())
+    }
 
-        // Update the JSON object with the new entry
-        json_obj.insert(
-            entry.service.clone(),
-            json!({
-                "username": &entry.username,
-                "password": &entry.password
-            })
-        );
+    /**
+     * Rename entry
+     * The method will store the entry under its new service in the backend, where it keeps its
+     * id, and then replace the entry of the old service in the in-memory store.
+     *
+     * @param service: &str the service the entry was stored under
+     * @param entry: PasswordEntry
+     * @return Result<(), &'static str>
+     */
+    pub fn rename_entry(
+        &mut self,
+        service: &str,
+        mut entry: PasswordEntry
+    ) -> Result<(), &'static str> {
+        entry.modified_at = unix_timestamp();
+        self.backend.rename(service, &entry).map_err(|_| "Failed to rename entry in file")?;
+        self.passwords.remove(service);
+        self.add(entry);
+
+        Ok(())
+    }
+
+    /**
+     * Delete entry
+     * The method will remove the entry of a service from the backend and then from the
+     * in-memory store, so a failed write changes nothing.
+     *
+     * @param service: &str
+     * @return Result<(), &'static str>
+     */
+    pub fn delete_entry(&mut self, service: &str) -> Result<(), &'static str> {
+        if !self.passwords.contains_key(service) {
+            return Err("Could not find an entry for service");
+        }
+
+        self.backend.delete(service)?;
+        self.passwords.remove(service);
 
-        // Write the updated JSON object to the file
-        let file = OpenOptions::new().write(true).truncate(true).open(&self.file_path).unwrap();
-        let mut writer = BufWriter::new(file);
-        let result = writer.write_all(serde_json::to_string_pretty(&json_obj).unwrap().as_bytes());
+        Ok(())
+    }
 
-        match result {
-            Ok(_) => Ok(()),
-            Err(_) => Err("Failed to update entry in file"),
+    /**
+     * Replace all entries
+     * Drops every entry in memory and in the backend and stores the given entries instead.
+     *
+     * @param entries: Vec<PasswordEntry>
+     * @return Result<(), &'static str>
+     */
+    pub fn replace_all(&mut self, entries: Vec<PasswordEntry>) -> Result<(), &'static str> {
+        self.backend.replace_all(&entries)?;
+        self.passwords.clear();
+
+        for entry in entries.into_iter() {
+            self.add(entry);
         }
+
+        Ok(())
+    }
+
+    /**
+     * The backups taken by the backend, newest first.
+     *
+     * @return Result<Vec<Backup>, &'static str>
+     */
+    pub fn list_backups(&self) -> Result<Vec<Backup>, &'static str> {
+        self.backend.backups()
+    }
+
+    /**
+     * Check that a backup can be read by the backend.
+     *
+     * @param backup: &Path
+     * @return Result<usize, &'static str> the number of entries in the backup
+     */
+    pub fn verify_backup(&self, backup: &Path) -> Result<usize, &'static str> {
+        self.backend.verify_backup(backup)
+    }
+
+    /**
+     * Restore a backup
+     * The backend checks that the backup can be read before it replaces anything, then the
+     * restored entries are loaded into memory.
+     *
+     * @param backup: &Path
+     * @return Result<usize, &'static str> the number of restored entries
+     */
+    pub fn restore_backup(&mut self, backup: &Path) -> Result<usize, &'static str> {
+        self.backend.restore_backup(backup)?;
+        self.load()?;
+
+        Ok(self.passwords.len())
+    }
+
+    /**
+     * Push the vault to a branch of its git remote.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @return Result<(), &'static str>
+     */
+    pub fn push(&mut self, remote: &str, branch: &str) -> Result<(), &'static str> {
+        self.backend.push(remote, branch)
+    }
+
+    /**
+     * Pull a branch of the git remote into the vault and load the result into memory.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @param resolve: &mut dyn FnMut(&Conflict) -> Resolution
+     * @return Result<PullOutcome, &'static str>
+     */
+    pub fn pull(
+        &mut self,
+        remote: &str,
+        branch: &str,
+        resolve: &mut dyn FnMut(&Conflict) -> Resolution
+    ) -> Result<PullOutcome, &'static str> {
+        let outcome = self.backend.pull(remote, branch, resolve)?;
+        self.load()?;
+
+        Ok(outcome)
+    }
+
+    /**
+     * Next HOTP code
+     * Produces the next one-time password for a service and saves the advanced counter before
+     * returning it, so a code is never handed out without the counter moving past it.
+     *
+     * @param service: &str
+     * @return Result<String, &'static str>
+     */
+    pub fn next_hotp_code(&mut self, service: &str) -> Result<String, &'static str> {
+        let mut entry = self.get(service).cloned().ok_or("Could not find an entry for service")?;
+        let code = entry.hotp.as_mut().ok_or("The service has no HOTP secret")?.next_code()?;
+
+        self.update_entry(entry)?;
+
+        Ok(code)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{ backup::Backups, otp::Hotp, password::generator::PasswordPolicy };
+    use serde_json::{ Map, Value };
+    use std::fs;
 
     #[test]
     fn test_new_password_store() {
@@ -211,7 +437,7 @@ mod tests {
         store.add(entry.clone());
         let is_duplicate = store.check_for_duplicate_service_entry("service");
 
-        assert_eq!(is_duplicate, true);
+        assert!(is_duplicate);
     }
 
     #[test]
@@ -250,5 +476,194 @@ mod tests {
         let password = json_obj.get("service").unwrap();
 
         assert_eq!(password["password"], "new_password");
+        assert_eq!(store.get("service").unwrap().password, "new_password");
+    }
+
+    #[test]
+    fn test_entry_policy_is_saved_and_loaded() {
+        let file_path = std::env::temp_dir().join("stashpass_test_entry_policy.json");
+        let _ = fs::remove_file(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        let mut entry = PasswordEntry::new(
+            "service".to_string(),
+            "username".to_string(),
+            "password".to_string()
+        );
+        entry.policy = Some(PasswordPolicy {
+            length: 16,
+            symbols: false,
+            forbidden: "%".to_string(),
+            ..PasswordPolicy::default()
+        });
+        store.add_and_save_entry(entry.clone()).unwrap();
+
+        let store = PasswordStore::new(file_path.clone()).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(store.get("service"), Some(&entry));
+    }
+
+    #[test]
+    fn test_next_hotp_code_saves_counter() {
+        let file_path = std::env::temp_dir().join("stashpass_test_hotp.json");
+        let _ = fs::remove_file(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        let mut entry = PasswordEntry::new(
+            "token".to_string(),
+            "username".to_string(),
+            "password".to_string()
+        );
+        entry.hotp = Some(Hotp::new("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ", 0).unwrap());
+        store.add_and_save_entry(entry).unwrap();
+
+        assert_eq!(store.next_hotp_code("token"), Ok("755224".to_string()));
+        assert_eq!(store.next_hotp_code("token"), Ok("287082".to_string()));
+
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        assert_eq!(store.get("token").unwrap().hotp.as_ref().unwrap().counter, 2);
+        assert_eq!(store.next_hotp_code("token"), Ok("359152".to_string()));
+
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let file_path = std::env::temp_dir().join("stashpass_test_replace_all.json");
+        let _ = fs::remove_file(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        let old = PasswordEntry::new("old".to_string(), "user".to_string(), "pw".to_string());
+        let new = PasswordEntry::new("new".to_string(), "user".to_string(), "pw".to_string());
+        store.add_and_save_entry(old).unwrap();
+        store.replace_all(vec![new.clone()]).unwrap();
+
+        assert_eq!(store.entries(), vec![&new]);
+
+        let store = PasswordStore::new(file_path.clone()).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(store.entries(), vec![&new]);
+    }
+
+    #[test]
+    fn test_unreadable_vault_file_is_not_emptied() {
+        let file_path = std::env::temp_dir().join("stashpass_test_store_unreadable.json");
+
+        fs::write(&file_path, " \n").unwrap();
+        assert!(PasswordStore::new(file_path.clone()).unwrap().entries().is_empty());
+
+        fs::write(&file_path, "{\"service\": {\"username\": ").unwrap();
+        assert!(PasswordStore::new(file_path.clone()).is_err());
+        let entry = PasswordEntry::new("other".to_string(), "me".to_string(), "pw".to_string());
+        let mut backend = JsonFileBackend::new(file_path.clone()).unwrap();
+        assert!(backend.save(&entry).is_err());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "{\"service\": {\"username\": ");
+
+        fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn test_backup_before_write_and_restore() {
+        let file_path = std::env::temp_dir().join("stashpass_test_store_backups.json");
+        let directory = std::env::temp_dir().join("stashpass_test_store_backups");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut backend = JsonFileBackend::new(file_path.clone()).unwrap();
+        backend.set_backups(Backups { directory: directory.clone(), count: 5 });
+        let mut store = PasswordStore::with_backend(Box::new(backend)).unwrap();
+
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "one".to_string());
+        store.add_and_save_entry(entry.clone()).unwrap();
+        store.update_entry(PasswordEntry { password: "two".to_string(), ..entry }).unwrap();
+
+        let backups = store.list_backups().unwrap();
+        assert_eq!(backups.len(), 1);
+
+        assert_eq!(store.restore_backup(&backups[0].path), Ok(1));
+        assert_eq!(store.get("service").unwrap().password, "one");
+        assert_eq!(store.list_backups().unwrap().len(), 2);
+
+        fs::remove_file(&file_path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "pw".to_string());
+        let backend = MemoryBackend::new(vec![entry.clone()]);
+        let mut store = PasswordStore::with_backend(Box::new(backend)).unwrap();
+
+        assert_eq!(store.get("service"), Some(&entry));
+
+        store.update_entry(PasswordEntry { password: "new".to_string(), ..entry }).unwrap();
+        store.load().unwrap();
+
+        assert_eq!(store.get("service").unwrap().password, "new");
+        assert!(store.list_backups().is_err());
+    }
+
+    #[test]
+    fn test_git_sync_needs_the_directory_storage() {
+        for storage in [StorageKind::Json, StorageKind::Sqlite, StorageKind::Memory] {
+            let config = Config { storage, git_sync: true, ..Config::default() };
+            assert!(PasswordStore::open(&config).is_err());
+        }
+    }
+
+    #[test]
+    fn test_delete_entry() {
+        let file_path = std::env::temp_dir().join("stashpass_test_delete.json");
+        let _ = fs::remove_file(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "pw".to_string());
+        store.add_and_save_entry(entry).unwrap();
+
+        assert_eq!(store.delete_entry("service"), Ok(()));
+        assert!(store.delete_entry("service").is_err());
+
+        let store = PasswordStore::new(file_path.clone()).unwrap();
+        fs::remove_file(&file_path).unwrap();
+
+        assert_eq!(store.get("service"), None);
+    }
+
+    #[test]
+    fn test_failed_delete_keeps_the_entry() {
+        let file_path = std::env::temp_dir().join("stashpass_test_failed_delete.json");
+        let _ = fs::remove_file(&file_path);
+        let _ = fs::remove_dir_all(&file_path);
+        let mut store = PasswordStore::new(file_path.clone()).unwrap();
+
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "pw".to_string());
+        store.add_and_save_entry(entry).unwrap();
+
+        // The vault file can no longer be written
+        fs::remove_file(&file_path).unwrap();
+        fs::create_dir(&file_path).unwrap();
+
+        assert!(store.delete_entry("service").is_err());
+        fs::remove_dir_all(&file_path).unwrap();
+
+        assert!(store.get("service").is_some());
+    }
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let entry = PasswordEntry::new("service".to_string(), "me".to_string(), "pw".to_string());
+        let backend = MemoryBackend::new(vec![entry.clone()]);
+        let mut store = PasswordStore::with_backend(Box::new(backend)).unwrap();
+
+        store.lock();
+        assert!(store.is_locked());
+        assert_eq!(store.get("service"), None);
+
+        store.unlock("master").unwrap();
+        assert!(!store.is_locked());
+        assert_eq!(store.get("service"), Some(&entry));
     }
 }
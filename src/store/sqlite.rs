@@ -0,0 +1,220 @@
+use std::path::{ Path, PathBuf };
+
+use rusqlite::{ params, Connection, OpenFlags };
+
+use crate::{
+    backup::{ Backup, Backups },
+    password::PasswordEntry,
+    store::backend::StorageBackend,
+};
+
+const CREATE_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS entries (service TEXT PRIMARY KEY, entry TEXT NOT NULL)";
+
+/**
+ * SQLite Backend
+ * Stores each entry as a row in an embedded SQLite database, so a change only writes that one
+ * entry instead of the whole vault. The entry itself is kept as JSON, which lets new fields be
+ * added to entries without a schema migration. Backups are copies of the database file.
+ */
+#[derive(Debug)]
+pub struct SqliteBackend {
+    connection: Connection,
+    file_path: PathBuf,
+    backups: Option<Backups>,
+}
+
+impl SqliteBackend {
+    /**
+     * Open the database, it is created along with its table when it does not exist yet.
+     *
+     * @param file_path: PathBuf
+     * @return Result<SqliteBackend, &'static str>
+     */
+    pub fn open(file_path: PathBuf) -> Result<SqliteBackend, &'static str> {
+        let connection = Connection::open(&file_path).map_err(|_| "Unable to open the database")?;
+
+        connection
+            .execute(CREATE_TABLE, ())
+            .map_err(|_| "Unable to create the entries table")?;
+
+        Ok(SqliteBackend { connection, file_path, backups: None })
+    }
+
+    /**
+     * Keep backups of the database file, one is taken before every write.
+     *
+     * @param backups: Backups
+     */
+    pub fn set_backups(&mut self, backups: Backups) {
+        self.backups = Some(backups);
+    }
+
+    fn back_up(&self) -> Result<(), &'static str> {
+        match &self.backups {
+            Some(backups) => {
+                backups
+                    .create(&self.file_path)
+                    .map_err(|_| "Unable to back up the database")?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn read_entries(connection: &Connection) -> Result<Vec<PasswordEntry>, &'static str> {
+        let mut statement = connection
+            .prepare("SELECT entry FROM entries ORDER BY service")
+            .map_err(|_| "Unable to read the database")?;
+
+        let rows = statement
+            .query_map((), |row| row.get::<_, String>(0))
+            .map_err(|_| "Unable to read the database")?;
+
+        rows.map(|row| {
+            let json = row.map_err(|_| "Unable to read the database")?;
+            serde_json::from_str(&json).map_err(|_| "The database contains an unreadable entry")
+        }).collect()
+    }
+
+    /** The entries of a backup, the backup is opened read-only */
+    fn read_backup(backup: &Path) -> Result<Vec<PasswordEntry>, &'static str> {
+        let connection = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|_| "Unable to read the backup")?;
+
+        Self::read_entries(&connection).map_err(|_| "The backup is damaged and can not be read")
+    }
+
+    fn insert(connection: &Connection, entry: &PasswordEntry) -> rusqlite::Result<usize> {
+        connection.execute(
+            "INSERT OR REPLACE INTO entries (service, entry) VALUES (?1, ?2)",
+            params![entry.service, serde_json::to_string(entry).unwrap()]
+        )
+    }
+}
+
+impl StorageBackend for SqliteBackend {
+    fn load(&self) -> Result<Vec<PasswordEntry>, &'static str> {
+        Self::read_entries(&self.connection)
+    }
+
+    fn save(&mut self, entry: &PasswordEntry) -> Result<(), &'static str> {
+        self.back_up()?;
+        Self::insert(&self.connection, entry).map_err(|_| "Failed to save entry to the database")?;
+
+        Ok(())
+    }
+
+    fn delete(&mut self, service: &str) -> Result<(), &'static str> {
+        self.back_up()?;
+        self.connection
+            .execute("DELETE FROM entries WHERE service = ?1", params![service])
+            .map_err(|_| "Failed to delete entry from the database")?;
+
+        Ok(())
+    }
+
+    /**
+     * Runs in a single transaction, so the old entries are kept when any insert fails.
+     */
+    fn replace_all(&mut self, entries: &[PasswordEntry]) -> Result<(), &'static str> {
+        let error = "Failed to write entries to the database";
+        self.back_up()?;
+        let transaction = self.connection.transaction().map_err(|_| error)?;
+
+        transaction.execute("DELETE FROM entries", ()).map_err(|_| error)?;
+        for entry in entries.iter() {
+            Self::insert(&transaction, entry).map_err(|_| error)?;
+        }
+
+        transaction.commit().map_err(|_| error)
+    }
+
+    fn location(&self) -> PathBuf {
+        self.file_path.clone()
+    }
+
+    fn backups(&self) -> Result<Vec<Backup>, &'static str> {
+        self.backups
+            .as_ref()
+            .ok_or("Backups are turned off")?
+            .list(&self.file_path)
+            .map_err(|_| "Unable to read the backup folder")
+    }
+
+    fn verify_backup(&self, backup: &Path) -> Result<usize, &'static str> {
+        Ok(Self::read_backup(backup)?.len())
+    }
+
+    /**
+     * The backup is only restored when it can be read. The current database is backed up
+     * first, so the restore itself can be undone.
+     */
+    fn restore_backup(&mut self, backup: &Path) -> Result<(), &'static str> {
+        let entries = Self::read_backup(backup)?;
+
+        self.replace_all(&entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_and_replace() {
+        let file_path = std::env::temp_dir().join("stashpass_test_vault.sqlite");
+        let _ = std::fs::remove_file(&file_path);
+
+        let mut entry = PasswordEntry::new("github".to_string(), "me".to_string(), "1".to_string());
+        entry.fields.insert("PIN".to_string(), "1234".to_string());
+        let other = PasswordEntry::new("mail".to_string(), "me".to_string(), "pw".to_string());
+
+        let mut backend = SqliteBackend::open(file_path.clone()).unwrap();
+        backend.save(&entry).unwrap();
+        backend.save(&other).unwrap();
+        entry.change_password("two".to_string());
+        backend.save(&entry).unwrap();
+
+        let backend = SqliteBackend::open(file_path.clone()).unwrap();
+        assert_eq!(backend.load(), Ok(vec![entry.clone(), other]));
+
+        let mut backend = backend;
+        backend.delete("mail").unwrap();
+        assert_eq!(backend.load(), Ok(vec![entry.clone()]));
+
+        backend.replace_all(&[]).unwrap();
+        assert_eq!(backend.load(), Ok(vec![]));
+
+        std::fs::remove_file(&file_path).unwrap();
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let file_path = std::env::temp_dir().join("stashpass_test_backups.sqlite");
+        let directory = std::env::temp_dir().join("stashpass_test_sqlite_backups");
+        let _ = std::fs::remove_file(&file_path);
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let entry = PasswordEntry::new("github".to_string(), "me".to_string(), "1".to_string());
+        let mut backend = SqliteBackend::open(file_path.clone()).unwrap();
+        backend.set_backups(Backups { directory: directory.clone(), count: 5 });
+        backend.save(&entry).unwrap();
+        backend.delete("github").unwrap();
+
+        let backups = backend.backups().unwrap();
+        assert_eq!(backups.len(), 2);
+        assert!(backups[0].path.to_string_lossy().ends_with(".sqlite"));
+        assert_eq!(backend.verify_backup(&backups[0].path), Ok(1));
+
+        backend.restore_backup(&backups[0].path).unwrap();
+        assert_eq!(backend.load(), Ok(vec![entry]));
+        assert_eq!(backend.backups().unwrap().len(), 3);
+
+        std::fs::write(&backups[1].path, "not a database").unwrap();
+        assert!(backend.verify_backup(&backups[1].path).is_err());
+
+        std::fs::remove_file(&file_path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
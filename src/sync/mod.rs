@@ -0,0 +1,363 @@
+use std::{ env, fs, path::{ Path, PathBuf }, process::{ Command, Output } };
+
+use crate::{
+    merge::{ self, Conflict, Resolution },
+    password::PasswordEntry,
+    store::backend::StorageBackend,
+};
+
+/** What a pull did to the local vault */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PullOutcome {
+    /** The remote had nothing new */
+    UpToDate,
+    /** Only the remote had changes, they were taken as they are */
+    FastForward,
+    /** Both sides had changes, the vaults were merged entry by entry */
+    Merged {
+        from_theirs: usize,
+        deleted: usize,
+        conflicts: usize,
+    },
+}
+
+/**
+ * Git Repository
+ * The folder of the encrypted vault, kept as a git repository so every change is recorded and
+ * the vault can be synced with a remote. Only the vault folder itself is ever a repository, so
+ * nothing but encrypted files is committed. Runs the `git` command, which has to be installed.
+ *
+ * Commit messages never contain service names or any other part of an entry.
+ */
+#[derive(Debug, Clone)]
+pub struct GitRepository {
+    directory: PathBuf,
+}
+
+impl GitRepository {
+    /**
+     * Open the repository in a folder, it is initialized when the folder is not a repository
+     * yet. A commit identity is set for the repository when git has none configured.
+     *
+     * @param directory: &Path
+     * @return Result<GitRepository, String>
+     */
+    pub fn open(directory: &Path) -> Result<GitRepository, String> {
+        let directory = match directory.as_os_str().is_empty() {
+            true => PathBuf::from("."),
+            false => directory.to_path_buf(),
+        };
+        let repository = GitRepository { directory };
+
+        if !repository.directory.join(".git").exists() {
+            repository.run(&["init", "--quiet"])?;
+        }
+
+        if !repository.succeeds(&["config", "user.email"])? {
+            repository.run(&["config", "user.name", "stashpass"])?;
+            repository.run(&["config", "user.email", "stashpass@localhost"])?;
+        }
+
+        Ok(repository)
+    }
+
+    /**
+     * Open the repository of a vault folder, the folder itself with its absolute path. The
+     * working directory is refused, so a project the user works in is never initialized or
+     * committed to.
+     *
+     * @param vault: &Path
+     * @return Result<GitRepository, String>
+     */
+    pub fn for_vault(vault: &Path) -> Result<GitRepository, String> {
+        let vault = fs
+            ::canonicalize(vault)
+            .map_err(|err| format!("Unable to find the vault folder: {err}"))?;
+        if !vault.is_dir() {
+            return Err("Only a vault folder can be a git repository".to_string());
+        }
+
+        let working_directory = env::current_dir().and_then(fs::canonicalize).ok();
+        if working_directory.is_some_and(|directory| directory == vault) {
+            return Err("The vault folder can not be the working directory".to_string());
+        }
+
+        GitRepository::open(&vault)
+    }
+
+    fn output(&self, args: &[&str]) -> Result<Output, String> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.directory)
+            .args(args)
+            .output()
+            .map_err(|err| format!("Unable to run git: {err}"))
+    }
+
+    /**
+     * Run a git command and return what it printed, or what it printed to stderr when it fails.
+     *
+     * @param args: &[&str]
+     * @return Result<String, String>
+     */
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let output = self.output(args)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(format!("git {} failed: {error}", args[0]));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /** Run a git command that answers a question with its exit code */
+    fn succeeds(&self, args: &[&str]) -> Result<bool, String> {
+        Ok(self.output(args)?.status.success())
+    }
+
+    /**
+     * Commit the current state of the vault, nothing is committed when it did not change.
+     *
+     * @param message: &str
+     * @return Result<(), String>
+     */
+    pub fn commit(&self, message: &str) -> Result<(), String> {
+        self.run(&["add", "--all"])?;
+
+        if self.succeeds(&["diff", "--cached", "--quiet"])? {
+            return Ok(());
+        }
+
+        self.run(&["commit", "--quiet", "-m", message])?;
+
+        Ok(())
+    }
+
+    /**
+     * Push the local commits to a branch of the remote.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @return Result<(), String>
+     */
+    pub fn push(&self, remote: &str, branch: &str) -> Result<(), String> {
+        let refspec = format!("HEAD:refs/heads/{branch}");
+
+        self.run(&["push", "--quiet", remote, &refspec]).map_err(|err| {
+            match err.contains("[rejected]") {
+                true => "The remote has changes that are not here yet, pull them first".to_string(),
+                false => err,
+            }
+        })?;
+
+        Ok(())
+    }
+
+    /**
+     * The entries of the vault at a commit, read through its backend. `None` when the vault did
+     * not exist there.
+     *
+     * @param revision: &str
+     * @param backend: &dyn StorageBackend
+     * @return Result<Option<Vec<PasswordEntry>>, String>
+     */
+    fn entries_at(
+        &self,
+        revision: &str,
+        backend: &dyn StorageBackend
+    ) -> Result<Option<Vec<PasswordEntry>>, String> {
+        let read = |path: &Path| {
+            let object = format!("{revision}:{}", path.to_string_lossy());
+
+            match self.succeeds(&["cat-file", "-e", &object]) {
+                Ok(true) => self.run(&["show", &object]).ok(),
+                _ => None,
+            }
+        };
+
+        backend.load_snapshot(&read).map_err(str::to_string)
+    }
+
+    /**
+     * Pull a branch of the remote into the vault.
+     * Local changes are committed first. When both sides have new commits the vaults are not
+     * merged line by line but entry by entry, with the last common commit as the base, and
+     * `resolve` is asked which side to keep for each conflict. The merged entries are written
+     * through the backend and recorded as a merge commit, so the next push is accepted.
+     *
+     * @param remote: &str
+     * @param branch: &str
+     * @param backend: &mut dyn StorageBackend
+     * @param resolve: &mut dyn FnMut(&Conflict) -> Resolution
+     * @return Result<PullOutcome, String>
+     */
+    pub fn pull(
+        &self,
+        remote: &str,
+        branch: &str,
+        backend: &mut dyn StorageBackend,
+        resolve: &mut dyn FnMut(&Conflict) -> Resolution
+    ) -> Result<PullOutcome, String> {
+        self.commit("Update the vault")?;
+        self.run(&["fetch", "--quiet", remote, branch])?;
+
+        let has_commits = self.succeeds(&["rev-parse", "--verify", "--quiet", "HEAD"])?;
+        if has_commits && self.succeeds(&["merge-base", "--is-ancestor", "FETCH_HEAD", "HEAD"])? {
+            return Ok(PullOutcome::UpToDate);
+        }
+
+        if !has_commits || self.succeeds(&["merge-base", "--is-ancestor", "HEAD", "FETCH_HEAD"])? {
+            self.run(&["merge", "--quiet", "--ff-only", "FETCH_HEAD"])?;
+            return Ok(PullOutcome::FastForward);
+        }
+
+        let base = match self.run(&["merge-base", "HEAD", "FETCH_HEAD"]) {
+            Ok(commit) => self.entries_at(&commit, backend)?,
+            Err(_) => None,
+        };
+        let ours = self.entries_at("HEAD", backend)?.unwrap_or_default();
+        let theirs = self.entries_at("FETCH_HEAD", backend)?.unwrap_or_default();
+        let result = merge::merge(base.as_deref(), &ours, &theirs);
+
+        let outcome = PullOutcome::Merged {
+            from_theirs: result.from_theirs,
+            deleted: result.deleted,
+            conflicts: result.conflicts.len(),
+        };
+
+        let mut entries = result.entries;
+        for conflict in result.conflicts.into_iter() {
+            let resolution = resolve(&conflict);
+            entries.extend(conflict.resolve(resolution));
+        }
+
+        // Record both histories without touching the vault, then commit the merged vault
+        self.run(&["merge", "--quiet", "--no-ff", "--no-commit", "-s", "ours", "FETCH_HEAD"])?;
+
+        if let Err(err) = backend.replace_all(&entries) {
+            let _ = self.run(&["merge", "--abort"]);
+            return Err(err.to_string());
+        }
+
+        self.run(&["add", "--all"])?;
+        self.run(&["commit", "--quiet", "-m", &format!("Merge {remote}/{branch}")])?;
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::KdfParams,
+        store::{
+            directory::DirectoryBackend,
+            git::GitBackend,
+            PasswordStore,
+        },
+    };
+
+    fn git(directory: &Path, args: &[&str]) {
+        let status = Command::new("git").arg("-C").arg(directory).args(args).output().unwrap();
+        assert!(status.status.success(), "{}", String::from_utf8_lossy(&status.stderr));
+    }
+
+    fn entry(service: &str, password: &str) -> PasswordEntry {
+        PasswordEntry::new(service.to_string(), "me".to_string(), password.to_string())
+    }
+
+    fn open_vault(vault: &Path) -> Box<dyn StorageBackend> {
+        if !DirectoryBackend::exists(vault) {
+            DirectoryBackend::create(vault.to_path_buf(), "master", KdfParams::fast()).unwrap();
+        }
+        let key = DirectoryBackend::unlock(vault, "master").unwrap();
+
+        Box::new(DirectoryBackend::open(vault.to_path_buf(), key).unwrap())
+    }
+
+    /**
+     * Two machines change the vault at the same time: different entries, the same entry, and
+     * an entry that one of them deletes.
+     */
+    #[test]
+    fn test_sync_directory_vault() {
+        let root = std::env::temp_dir().join("stashpass_test_git_sync_directory");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        let laptop = root.join("laptop");
+        git(&root, &["init", "--quiet", "--bare", "remote.git"]);
+        let remote = root.join("remote.git").to_string_lossy().to_string();
+
+        let open_store = |vault: &Path| {
+            let backend = GitBackend::new(open_vault(vault)).unwrap();
+            PasswordStore::with_backend(Box::new(backend)).unwrap()
+        };
+
+        let mut laptop_store = open_store(&laptop);
+        laptop_store.add_and_save_entry(entry("github", "one")).unwrap();
+        laptop_store.add_and_save_entry(entry("mail", "one")).unwrap();
+        git(&laptop, &["remote", "add", "origin", &remote]);
+        laptop_store.push("origin", "main").unwrap();
+
+        git(&root, &["clone", "--quiet", "--branch", "main", &remote, "desktop"]);
+        let mut desktop_store = open_store(&root.join("desktop"));
+        assert_eq!(desktop_store.entries().len(), 2);
+
+        let mut github = laptop_store.get("github").unwrap().clone();
+        github.change_password("laptop".to_string());
+        laptop_store.update_entry(github).unwrap();
+        laptop_store.delete_entry("mail").unwrap();
+        laptop_store.push("origin", "main").unwrap();
+
+        let mut github = desktop_store.get("github").unwrap().clone();
+        github.change_password("desktop".to_string());
+        desktop_store.update_entry(github).unwrap();
+        desktop_store.add_and_save_entry(entry("bank", "one")).unwrap();
+        assert!(desktop_store.push("origin", "main").is_err());
+
+        let mut conflicts = vec![];
+        let outcome = desktop_store
+            .pull("origin", "main", &mut |conflict| {
+                conflicts.push(conflict.service().to_string());
+                Resolution::Theirs
+            })
+            .unwrap();
+        assert_eq!(outcome, PullOutcome::Merged { from_theirs: 0, deleted: 1, conflicts: 1 });
+        assert_eq!(conflicts, vec!["github"]);
+        desktop_store.push("origin", "main").unwrap();
+
+        let outcome = laptop_store.pull("origin", "main", &mut |_| Resolution::Ours).unwrap();
+        assert_eq!(outcome, PullOutcome::FastForward);
+
+        let passwords: Vec<(&str, &str)> = laptop_store
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.service.as_str(), entry.password.as_str()))
+            .collect();
+        assert_eq!(passwords, vec![("bank", "one"), ("github", "laptop")]);
+
+        let log = GitRepository::open(&laptop).unwrap().run(&["log", "--format=%s"]).unwrap();
+        assert!(log.contains("Merge origin/main"));
+        assert!(!log.contains("github") && !log.contains("laptop"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_vault_is_its_own_repository() {
+        let vault = std::env::temp_dir().join("stashpass_test_git_vault_repository");
+        let _ = std::fs::remove_dir_all(&vault);
+
+        assert!(GitRepository::for_vault(&vault).is_err());
+        GitBackend::new(open_vault(&vault)).unwrap();
+        assert!(vault.join(".git").exists());
+        assert!(!std::env::temp_dir().join(".git").exists());
+
+        let files = GitRepository::open(&vault).unwrap().run(&["ls-files"]).unwrap();
+        assert!(files.lines().any(|file| file == "vault.json"));
+
+        std::fs::remove_dir_all(&vault).unwrap();
+    }
+}
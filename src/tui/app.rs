@@ -0,0 +1,649 @@
+use ratatui::crossterm::event::{ KeyCode, KeyEvent, KeyModifiers };
+use zeroize::Zeroizing;
+
+use crate::{
+    cli::copy_to_clipboard,
+    config::Config,
+    otp::Totp,
+    password::{ generator::PasswordPolicy, strength::Strength, unix_timestamp, PasswordEntry },
+    store::PasswordStore,
+};
+
+/** The fields of an entry that can be edited inline, in the order they are shown */
+pub const EDITOR_FIELDS: [&str; 5] = ["Service", "Username", "Password", "URL", "Notes"];
+const SERVICE: usize = 0;
+const USERNAME: usize = 1;
+pub const PASSWORD: usize = 2;
+const URL: usize = 3;
+const NOTES: usize = 4;
+
+/** How far page up and page down move in the entry list */
+const PAGE_SIZE: usize = 10;
+/** The range of lengths the generator panel can be set to */
+const MIN_GENERATED_LENGTH: usize = 4;
+const MAX_GENERATED_LENGTH: usize = 128;
+
+/**
+ * Editor
+ * The values of an entry while it is edited inline. The values are wiped when the editor is
+ * dropped, whether the changes were saved or not.
+ */
+pub struct Editor {
+    /** The service of the entry that is edited, `None` for a new entry */
+    pub original: Option<String>,
+    pub values: [Zeroizing<String>; 5],
+    pub focus: usize,
+}
+
+impl Editor {
+    fn new_entry() -> Editor {
+        Editor {
+            original: None,
+            values: Default::default(),
+            focus: SERVICE,
+        }
+    }
+
+    fn for_entry(entry: &PasswordEntry) -> Editor {
+        let value = |value: &str| Zeroizing::new(value.to_string());
+
+        Editor {
+            original: Some(entry.service.clone()),
+            values: [
+                value(&entry.service),
+                value(&entry.username),
+                value(&entry.password),
+                value(entry.url.as_deref().unwrap_or_default()),
+                value(entry.notes.as_deref().unwrap_or_default()),
+            ],
+            focus: SERVICE,
+        }
+    }
+
+    fn optional(&self, field: usize) -> Option<String> {
+        let value = self.values[field].trim();
+        if value.is_empty() { None } else { Some(value.to_string()) }
+    }
+}
+
+/**
+ * Generator
+ * The generator panel: a password generated from a policy that can be adjusted with single
+ * keys. When it was opened from the editor the password can be taken over into it.
+ */
+pub struct Generator {
+    pub policy: PasswordPolicy,
+    pub password: Zeroizing<String>,
+    editor: Option<Editor>,
+}
+
+impl Generator {
+    fn new(policy: PasswordPolicy, editor: Option<Editor>) -> Generator {
+        let mut generator = Generator { policy, password: Zeroizing::default(), editor };
+
+        if generator.regenerate().is_err() {
+            generator.policy = PasswordPolicy::default();
+            generator.regenerate().expect("The default policy is valid");
+        }
+
+        generator
+    }
+
+    fn regenerate(&mut self) -> Result<(), &'static str> {
+        self.password = Zeroizing::new(self.policy.generate()?);
+
+        Ok(())
+    }
+
+    /** Change the policy, a change that leaves no valid password is undone */
+    fn adjust(&mut self, change: impl FnOnce(&mut PasswordPolicy)) -> Result<(), &'static str> {
+        let previous = self.policy.clone();
+        change(&mut self.policy);
+
+        self.regenerate().inspect_err(|_| {
+            self.policy = previous;
+        })
+    }
+
+    /** Whether the generator was opened from the editor and hands the password back to it */
+    pub fn for_editor(&self) -> bool {
+        self.editor.is_some()
+    }
+}
+
+pub enum Mode {
+    Browse,
+    Search,
+    Edit(Editor),
+    Generate(Generator),
+    ConfirmDelete(String),
+}
+
+/**
+ * App
+ * The state of the terminal interface: the search query, which entry is selected and what the
+ * keyboard currently controls. Every change to the vault goes through the `PasswordStore`.
+ */
+pub struct App {
+    pub query: String,
+    pub selected: usize,
+    pub mode: Mode,
+    /** Whether secrets are shown in the detail pane */
+    pub reveal: bool,
+    /** The outcome of the last action, shown in the status line */
+    pub status: Option<String>,
+    pub quit: bool,
+    /** Set after writing the vault failed, the backend may have printed over the screen */
+    pub redraw: bool,
+    min_password_score: u8,
+}
+
+impl App {
+    pub fn new(config: &Config) -> App {
+        App {
+            query: String::new(),
+            selected: 0,
+            mode: Mode::Browse,
+            reveal: false,
+            status: None,
+            quit: false,
+            redraw: false,
+            min_password_score: config.min_password_score,
+        }
+    }
+
+    /**
+     * The entries matching the search query, sorted by service. The query is matched without
+     * case against the service, username, URL and folder.
+     *
+     * @param store: &'a PasswordStore
+     * @return Vec<&'a PasswordEntry>
+     */
+    pub fn visible<'a>(&self, store: &'a PasswordStore) -> Vec<&'a PasswordEntry> {
+        let query = self.query.to_lowercase();
+
+        store
+            .entries()
+            .into_iter()
+            .filter(|entry| {
+                let url = entry.url.as_deref().unwrap_or_default();
+                let folder = entry.folder.as_deref().unwrap_or_default();
+
+                [entry.service.as_str(), entry.username.as_str(), url, folder]
+                    .iter()
+                    .any(|value| value.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    pub fn selected_entry<'a>(&self, store: &'a PasswordStore) -> Option<&'a PasswordEntry> {
+        let visible = self.visible(store);
+        let selected = self.selected.min(visible.len().saturating_sub(1));
+
+        visible.get(selected).copied()
+    }
+
+    /**
+     * Handle a key press. Ctrl-C quits from anywhere.
+     *
+     * @param store: &mut PasswordStore
+     * @param key: KeyEvent
+     */
+    pub fn handle_key(&mut self, store: &mut PasswordStore, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        self.status = None;
+
+        match std::mem::replace(&mut self.mode, Mode::Browse) {
+            Mode::Browse => self.browse(store, key),
+            Mode::Search => self.search(store, key),
+            Mode::Edit(editor) => self.edit(store, editor, key),
+            Mode::Generate(generator) => self.generate(generator, key),
+            Mode::ConfirmDelete(service) => self.confirm_delete(store, service, key),
+        }
+
+        let count = self.visible(store).len();
+        self.selected = self.selected.min(count.saturating_sub(1));
+    }
+
+    fn browse(&mut self, store: &mut PasswordStore, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') => {
+                self.quit = true;
+            }
+            KeyCode::Esc if self.query.is_empty() => {
+                self.quit = true;
+            }
+            KeyCode::Esc => {
+                self.query.clear();
+                self.selected = 0;
+            }
+            KeyCode::Char('/') => {
+                self.mode = Mode::Search;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected += 1;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            KeyCode::PageDown => {
+                self.selected += PAGE_SIZE;
+            }
+            KeyCode::PageUp => {
+                self.selected = self.selected.saturating_sub(PAGE_SIZE);
+            }
+            KeyCode::Home => {
+                self.selected = 0;
+            }
+            KeyCode::End => {
+                self.selected = usize::MAX;
+            }
+            KeyCode::Char('v') => {
+                self.reveal = !self.reveal;
+            }
+            KeyCode::Char('n') => {
+                self.mode = Mode::Edit(Editor::new_entry());
+            }
+            KeyCode::Char('g') => {
+                self.mode = Mode::Generate(Generator::new(PasswordPolicy::default(), None));
+            }
+            _ => {
+                let Some(entry) = self.selected_entry(store) else {
+                    return;
+                };
+
+                match key.code {
+                    KeyCode::Char('u') => self.copy("Username", entry.username.clone()),
+                    KeyCode::Char('p') => self.copy("Password", entry.password.clone()),
+                    KeyCode::Char('t') => self.copy_totp(entry.totp.as_ref()),
+                    KeyCode::Char('e') | KeyCode::Enter => {
+                        self.mode = Mode::Edit(Editor::for_entry(entry));
+                    }
+                    KeyCode::Char('d') | KeyCode::Delete => {
+                        self.mode = Mode::ConfirmDelete(entry.service.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn search(&mut self, store: &PasswordStore, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.query.clear();
+            }
+            KeyCode::Enter | KeyCode::Down | KeyCode::Tab => {}
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.selected = 0;
+                self.mode = Mode::Search;
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.selected = 0;
+                self.mode = Mode::Search;
+            }
+            _ => {
+                self.mode = Mode::Search;
+            }
+        }
+
+        if matches!(self.mode, Mode::Browse) && self.visible(store).is_empty() {
+            self.status = Some("No entry matches the search".to_string());
+        }
+    }
+
+    fn edit(&mut self, store: &mut PasswordStore, mut editor: Editor, key: KeyEvent) {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Esc => {
+                self.status = Some("Nothing was changed".to_string());
+                return;
+            }
+            KeyCode::Enter => {
+                match self.save(store, &editor) {
+                    Ok(service) => {
+                        self.status = Some(format!("Saved the entry for {service}"));
+                        self.select(store, &service);
+                        return;
+                    }
+                    Err(err) => {
+                        self.status = Some(err.to_string());
+                    }
+                }
+            }
+            KeyCode::Char('g') if control => {
+                let policy = editor.original
+                    .as_deref()
+                    .and_then(|service| store.get(service))
+                    .and_then(|entry| entry.policy.clone())
+                    .unwrap_or_default();
+
+                self.mode = Mode::Generate(Generator::new(policy, Some(editor)));
+                return;
+            }
+            KeyCode::Tab | KeyCode::Down => {
+                editor.focus = (editor.focus + 1) % EDITOR_FIELDS.len();
+            }
+            KeyCode::BackTab | KeyCode::Up => {
+                editor.focus = (editor.focus + EDITOR_FIELDS.len() - 1) % EDITOR_FIELDS.len();
+            }
+            KeyCode::Backspace => {
+                editor.values[editor.focus].pop();
+            }
+            KeyCode::Char(c) if !control => {
+                editor.values[editor.focus].push(c);
+            }
+            _ => {}
+        }
+
+        self.mode = Mode::Edit(editor);
+    }
+
+    /**
+     * Save the values of the editor. A new password is checked against the configured minimum
+     * strength, and the replaced password is kept in the entry's history.
+     *
+     * @param store: &mut PasswordStore
+     * @param editor: &Editor
+     * @return Result<String, &'static str> the service of the saved entry
+     */
+    fn save(&mut self, store: &mut PasswordStore, editor: &Editor) -> Result<String, &'static str> {
+        let service = editor.values[SERVICE].trim().to_string();
+        let username = editor.values[USERNAME].trim().to_string();
+        let password = editor.values[PASSWORD].to_string();
+
+        if service.is_empty() {
+            return Err("The service name can not be empty");
+        }
+        if password.is_empty() {
+            return Err("The password can not be empty");
+        }
+
+        let renamed = editor.original.as_deref() != Some(service.as_str());
+        if renamed && store.check_for_duplicate_service_entry(&service) {
+            return Err("There is already an entry for this service");
+        }
+
+        let mut entry = match &editor.original {
+            Some(original) => store
+                .get(original)
+                .cloned()
+                .ok_or("Could not find an entry for service")?,
+            None => PasswordEntry::new(service.clone(), username.clone(), String::new()),
+        };
+
+        if entry.password != password {
+            let strength = Strength::estimate(&password, &[&service, &username]);
+            if strength.score < self.min_password_score {
+                return Err("This password is too weak for the configured minimum strength");
+            }
+
+            match editor.original {
+                Some(_) => entry.change_password(password),
+                None => {
+                    entry.password = password;
+                }
+            }
+        }
+
+        entry.service = service.clone();
+        entry.username = username;
+        entry.url = editor.optional(URL);
+        entry.notes = editor.optional(NOTES);
+
+        let result = match editor.original.as_deref().filter(|_| renamed) {
+            Some(original) => store.rename_entry(original, entry),
+            None => store.update_entry(entry),
+        };
+        self.redraw = result.is_err();
+
+        result.map(|_| service)
+    }
+
+    fn generate(&mut self, mut generator: Generator, key: KeyEvent) {
+        let result = match key.code {
+            KeyCode::Esc => {
+                if let Some(editor) = generator.editor.take() {
+                    self.mode = Mode::Edit(editor);
+                }
+                return;
+            }
+            KeyCode::Enter => {
+                match generator.editor.take() {
+                    Some(mut editor) => {
+                        editor.values[PASSWORD] = generator.password.clone();
+                        editor.focus = PASSWORD;
+                        self.mode = Mode::Edit(editor);
+                    }
+                    None => self.copy("Password", generator.password.to_string()),
+                }
+                return;
+            }
+            KeyCode::Char('c') => {
+                self.copy("Password", generator.password.to_string());
+                Ok(())
+            }
+            KeyCode::Char('r') | KeyCode::Char(' ') => generator.regenerate(),
+            KeyCode::Char('+') | KeyCode::Right => {
+                generator.adjust(|policy| {
+                    policy.length = (policy.length + 1).min(MAX_GENERATED_LENGTH);
+                })
+            }
+            KeyCode::Char('-') | KeyCode::Left => {
+                generator.adjust(|policy| {
+                    policy.length = policy.length.saturating_sub(1).max(MIN_GENERATED_LENGTH);
+                })
+            }
+            KeyCode::Char('u') => generator.adjust(|policy| {
+                policy.uppercase = !policy.uppercase;
+            }),
+            KeyCode::Char('l') => generator.adjust(|policy| {
+                policy.lowercase = !policy.lowercase;
+            }),
+            KeyCode::Char('d') => generator.adjust(|policy| {
+                policy.digits = !policy.digits;
+            }),
+            KeyCode::Char('s') => generator.adjust(|policy| {
+                policy.symbols = !policy.symbols;
+            }),
+            KeyCode::Char('a') => generator.adjust(|policy| {
+                policy.exclude_ambiguous = !policy.exclude_ambiguous;
+            }),
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            self.status = Some(err.to_string());
+        }
+
+        self.mode = Mode::Generate(generator);
+    }
+
+    fn confirm_delete(&mut self, store: &mut PasswordStore, service: String, key: KeyEvent) {
+        if key.code != KeyCode::Char('y') {
+            self.status = Some("Nothing was deleted".to_string());
+            return;
+        }
+
+        self.status = Some(match store.delete_entry(&service) {
+            Ok(()) => format!("Deleted the entry for {service}"),
+            Err(err) => {
+                self.redraw = true;
+                err.to_string()
+            }
+        });
+    }
+
+    /** Select the entry of a service, clearing the search if it hides the entry */
+    fn select(&mut self, store: &PasswordStore, service: &str) {
+        if !self.visible(store).iter().any(|entry| entry.service == service) {
+            self.query.clear();
+        }
+
+        self.selected = self
+            .visible(store)
+            .iter()
+            .position(|entry| entry.service == service)
+            .unwrap_or(0);
+    }
+
+    fn copy(&mut self, what: &str, contents: String) {
+        self.status = Some(match copy_to_clipboard(contents) {
+            Ok(()) => format!("{what} was copied to the clipboard"),
+            Err(err) => err.to_string(),
+        });
+    }
+
+    fn copy_totp(&mut self, totp: Option<&Totp>) {
+        let Some(totp) = totp else {
+            self.status = Some("This entry has no TOTP secret".to_string());
+            return;
+        };
+
+        match totp.generate(unix_timestamp()) {
+            Ok(code) => self.copy("TOTP code", code),
+            Err(err) => {
+                self.status = Some(err.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryBackend;
+
+    fn press(app: &mut App, store: &mut PasswordStore, keys: &str) {
+        for c in keys.chars() {
+            app.handle_key(store, KeyEvent::from(KeyCode::Char(c)));
+        }
+    }
+
+    fn key(app: &mut App, store: &mut PasswordStore, code: KeyCode) {
+        app.handle_key(store, KeyEvent::from(code));
+    }
+
+    fn test_store() -> PasswordStore {
+        let entries = ["github", "gitlab", "mail"]
+            .iter()
+            .map(|service| {
+                PasswordEntry::new(service.to_string(), "me".to_string(), "pw".to_string())
+            })
+            .collect();
+
+        PasswordStore::with_backend(Box::new(MemoryBackend::new(entries))).unwrap()
+    }
+
+    fn test_app() -> App {
+        let config = Config { min_password_score: 0, ..Config::default() };
+        App::new(&config)
+    }
+
+    #[test]
+    fn test_search_and_select() {
+        let mut store = test_store();
+        let mut app = test_app();
+
+        press(&mut app, &mut store, "/GIT");
+        assert_eq!(app.visible(&store).len(), 2);
+
+        key(&mut app, &mut store, KeyCode::Enter);
+        press(&mut app, &mut store, "jjj");
+        assert_eq!(app.selected_entry(&store).unwrap().service, "gitlab");
+
+        key(&mut app, &mut store, KeyCode::Esc);
+        assert_eq!(app.visible(&store).len(), 3);
+        assert!(!app.quit);
+
+        key(&mut app, &mut store, KeyCode::Esc);
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn test_edit_entry() {
+        let mut store = test_store();
+        let mut app = test_app();
+
+        // Rename mail and change its password, the old password goes to the history
+        key(&mut app, &mut store, KeyCode::End);
+        key(&mut app, &mut store, KeyCode::Char('e'));
+        for _ in 0..4 {
+            key(&mut app, &mut store, KeyCode::Backspace);
+        }
+        press(&mut app, &mut store, "email");
+        key(&mut app, &mut store, KeyCode::Tab);
+        key(&mut app, &mut store, KeyCode::Tab);
+        press(&mut app, &mut store, "-new");
+        key(&mut app, &mut store, KeyCode::Enter);
+
+        assert!(store.get("mail").is_none());
+        let entry = store.get("email").unwrap();
+        assert_eq!(entry.password, "pw-new");
+        assert_eq!(entry.history[0].password, "pw");
+        assert_eq!(app.selected_entry(&store).unwrap().service, "email");
+
+        // A new entry can not take the name of an existing one
+        press(&mut app, &mut store, "ngithub");
+        key(&mut app, &mut store, KeyCode::Tab);
+        key(&mut app, &mut store, KeyCode::Tab);
+        press(&mut app, &mut store, "secret");
+        key(&mut app, &mut store, KeyCode::Enter);
+        assert!(matches!(app.mode, Mode::Edit(_)));
+        assert_eq!(store.entries().len(), 3);
+
+        key(&mut app, &mut store, KeyCode::Esc);
+        assert!(matches!(app.mode, Mode::Browse));
+    }
+
+    #[test]
+    fn test_generator_fills_editor() {
+        let mut store = test_store();
+        let mut app = test_app();
+
+        key(&mut app, &mut store, KeyCode::Char('e'));
+        app.handle_key(&mut store, KeyEvent::new(KeyCode::Char('g'), KeyModifiers::CONTROL));
+
+        let Mode::Generate(generator) = &app.mode else {
+            panic!("The generator did not open");
+        };
+        assert!(generator.for_editor());
+
+        // Turning off the last character class is refused
+        press(&mut app, &mut store, "uld");
+        let Mode::Generate(generator) = &app.mode else {
+            panic!("The generator closed");
+        };
+        assert!(!generator.policy.uppercase && !generator.policy.lowercase);
+        assert!(generator.policy.digits);
+        assert!(app.status.is_some());
+
+        key(&mut app, &mut store, KeyCode::Enter);
+        let Mode::Edit(editor) = &app.mode else {
+            panic!("The editor did not return");
+        };
+        assert_eq!(editor.focus, PASSWORD);
+        assert_eq!(editor.values[PASSWORD].len(), 30);
+        assert!(editor.values[PASSWORD].chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_delete_needs_confirmation() {
+        let mut store = test_store();
+        let mut app = test_app();
+
+        press(&mut app, &mut store, "dn");
+        assert_eq!(store.entries().len(), 3);
+
+        press(&mut app, &mut store, "dy");
+        assert!(store.get("github").is_none());
+        assert_eq!(app.selected_entry(&store).unwrap().service, "gitlab");
+    }
+}
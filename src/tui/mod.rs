@@ -0,0 +1,78 @@
+pub mod app;
+mod ui;
+
+use std::time::{ Duration, Instant };
+
+use ratatui::{ crossterm::event::{ self, Event, KeyEventKind }, DefaultTerminal };
+
+use crate::{ cli::session::Session, config::Config, store::PasswordStore };
+
+use self::app::App;
+
+/** Redraw at least this often, so the TOTP countdown stays current */
+const TICK: Duration = Duration::from_secs(1);
+
+/**
+ * Run the full-screen terminal interface until the user quits. Like the dialog, the interface
+ * ends when it was not used for the session idle timeout.
+ *
+ * @param store: &mut PasswordStore
+ * @param config: &Config
+ * @return Result<(), &'static str>
+ */
+pub fn run(store: &mut PasswordStore, config: &Config) -> Result<(), &'static str> {
+    let mut terminal = ratatui::try_init().map_err(|_| "Unable to start the terminal interface")?;
+    let result = run_app(&mut terminal, store, config);
+    ratatui::restore();
+
+    if let Ok(true) = result {
+        println!("The session was locked because it was not used for a while");
+    }
+
+    result.map(|_| ())
+}
+
+/**
+ * The event loop of the interface.
+ *
+ * @return Result<bool, &'static str> whether the session ended because it was idle
+ */
+fn run_app(
+    terminal: &mut DefaultTerminal,
+    store: &mut PasswordStore,
+    config: &Config
+) -> Result<bool, &'static str> {
+    let mut app = App::new(config);
+    let idle_timeout = Session::enabled(config).then_some(config.session_idle_timeout);
+    let mut last_used = Instant::now();
+
+    while !app.quit {
+        if let Some(idle_timeout) = idle_timeout {
+            if last_used.elapsed() >= Duration::from_secs(idle_timeout) {
+                return Ok(true);
+            }
+        }
+
+        // A screen that could not be cleared is only redrawn where it changes
+        if app.redraw {
+            terminal.clear().unwrap_or(());
+            app.redraw = false;
+        }
+        terminal
+            .draw(|frame| ui::draw(frame, &app, store))
+            .map_err(|_| "Unable to draw the terminal interface")?;
+
+        if !event::poll(TICK).map_err(|_| "Unable to read the keyboard")? {
+            continue;
+        }
+
+        if let Event::Key(key) = event::read().map_err(|_| "Unable to read the keyboard")? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(store, key);
+                last_used = Instant::now();
+            }
+        }
+    }
+
+    Ok(false)
+}
@@ -0,0 +1,226 @@
+use ratatui::{
+    layout::{ Constraint, Layout, Position, Rect },
+    style::{ Style, Stylize },
+    text::{ Line, Span },
+    widgets::{ Block, List, ListState, Paragraph, Wrap },
+    Frame,
+};
+
+use crate::{
+    password::{ strength::{ Strength, MAX_SCORE }, unix_timestamp, PasswordEntry },
+    store::PasswordStore,
+};
+
+use super::app::{ App, Editor, Generator, Mode, EDITOR_FIELDS, PASSWORD };
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+/** What is shown instead of a secret that is not revealed */
+const HIDDEN: &str = "••••••••";
+
+/**
+ * Draw the whole interface: the search bar on top, the entry list next to the detail pane, the
+ * editor or the generator, and the status and key help lines at the bottom.
+ *
+ * @param frame: &mut Frame
+ * @param app: &App
+ * @param store: &PasswordStore
+ */
+pub fn draw(frame: &mut Frame, app: &App, store: &PasswordStore) {
+    let [search, main, status, help] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    let [list, detail] = Layout::horizontal([
+        Constraint::Percentage(35),
+        Constraint::Percentage(65),
+    ]).areas(main);
+
+    draw_search(frame, app, search);
+    draw_list(frame, app, store, list);
+
+    match &app.mode {
+        Mode::Edit(editor) => draw_editor(frame, app, editor, detail),
+        Mode::Generate(generator) => draw_generator(frame, generator, detail),
+        _ => draw_detail(frame, app, app.selected_entry(store), detail),
+    }
+
+    let status_line = match &app.mode {
+        Mode::ConfirmDelete(service) => {
+            format!("Delete the entry for {service}? It can not be undone [y/N]").bold()
+        }
+        _ => app.status.clone().unwrap_or_default().into(),
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+    frame.render_widget(Paragraph::new(help_text(&app.mode).dim()), help);
+}
+
+fn help_text(mode: &Mode) -> &'static str {
+    match mode {
+        Mode::Browse => {
+            "/ search  ↑↓ select  u user  p password  t TOTP  v show  e edit  n new  d delete  \
+             g generator  q quit"
+        }
+        Mode::Search => "Type to search  Enter done  Esc clear",
+        Mode::Edit(_) => "Tab next field  Enter save  Ctrl-G generate password  Esc cancel",
+        Mode::Generate(generator) if generator.for_editor() => {
+            "+/- length  u l d s characters  a ambiguous  r new  c copy  Enter use  Esc back"
+        }
+        Mode::Generate(_) => {
+            "+/- length  u l d s characters  a ambiguous  r new  c copy  Enter copy  Esc back"
+        }
+        Mode::ConfirmDelete(_) => "y delete  any other key keeps the entry",
+    }
+}
+
+fn draw_search(frame: &mut Frame, app: &App, area: Rect) {
+    let block = match app.mode {
+        Mode::Search => Block::bordered().title("Search").bold(),
+        _ => Block::bordered().title("Search"),
+    };
+    frame.render_widget(Paragraph::new(app.query.as_str()).block(block), area);
+
+    if let Mode::Search = app.mode {
+        let x = area.x + 1 + (app.query.chars().count() as u16);
+        frame.set_cursor_position(Position::new(x.min(area.right() - 2), area.y + 1));
+    }
+}
+
+fn draw_list(frame: &mut Frame, app: &App, store: &PasswordStore, area: Rect) {
+    let visible = app.visible(store);
+    let title = format!("Entries ({})", visible.len());
+    let items: Vec<String> = visible
+        .iter()
+        .map(|entry| {
+            match &entry.folder {
+                Some(folder) => format!("{} ({folder})", entry.service),
+                None => entry.service.clone(),
+            }
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().reversed());
+    let mut state = ListState::default().with_selected(
+        (!visible.is_empty()).then_some(app.selected)
+    );
+
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn field<'a>(label: &'a str, value: impl Into<String>) -> Line<'a> {
+    Line::from(vec![Span::from(format!("{label:<10}")).bold(), Span::from(value.into())])
+}
+
+fn secret(value: &str, reveal: bool) -> String {
+    if reveal { value.to_string() } else { HIDDEN.to_string() }
+}
+
+fn draw_detail(frame: &mut Frame, app: &App, entry: Option<&PasswordEntry>, area: Rect) {
+    let block = Block::bordered().title("Details");
+    let Some(entry) = entry else {
+        let empty = Paragraph::new("No entries, press n to add one").block(block);
+        frame.render_widget(empty, area);
+        return;
+    };
+
+    let mut lines = vec![field("Service", &entry.service)];
+    if let Some(folder) = &entry.folder {
+        lines.push(field("Folder", folder));
+    }
+    lines.push(field("Username", &entry.username));
+    lines.push(field("Password", secret(&entry.password, app.reveal)));
+    if let Some(url) = &entry.url {
+        lines.push(field("URL", url));
+    }
+
+    if let Some(totp) = &entry.totp {
+        let now = unix_timestamp();
+        let generated = totp
+            .generate(now)
+            .and_then(|code| Ok((code, totp.remaining_seconds(now)?)));
+        let code = match generated {
+            Ok((code, remaining)) => format!("{} ({remaining}s left)", secret(&code, app.reveal)),
+            Err(err) => err.to_string(),
+        };
+        lines.push(field("TOTP", code));
+    }
+    if let Some(hotp) = &entry.hotp {
+        lines.push(field("HOTP", format!("next code at counter {}", hotp.counter)));
+    }
+
+    for (name, value) in entry.fields.iter() {
+        lines.push(field(name, secret(value, app.reveal)));
+    }
+
+    if entry.modified_at > 0 {
+        let days = unix_timestamp().saturating_sub(entry.modified_at) / SECONDS_PER_DAY;
+        lines.push(field("Changed", format!("{days} days ago")));
+    }
+    if !entry.history.is_empty() {
+        lines.push(field("History", format!("{} previous passwords", entry.history.len())));
+    }
+
+    if let Some(notes) = &entry.notes {
+        lines.push(Line::default());
+        lines.extend(notes.lines().map(|line| Line::from(line.to_string())));
+    }
+
+    let detail = Paragraph::new(lines).block(block).wrap(Wrap { trim: false });
+    frame.render_widget(detail, area);
+}
+
+fn draw_editor(frame: &mut Frame, app: &App, editor: &Editor, area: Rect) {
+    let title = match &editor.original {
+        Some(service) => format!("Edit {service}"),
+        None => "New entry".to_string(),
+    };
+
+    let lines: Vec<Line> = EDITOR_FIELDS.iter()
+        .zip(editor.values.iter())
+        .enumerate()
+        .map(|(index, (label, value))| {
+            let value = match index {
+                PASSWORD if !app.reveal => "•".repeat(value.chars().count()),
+                _ => value.to_string(),
+            };
+            let line = field(label, value);
+
+            if index == editor.focus { line.reversed() } else { line }
+        })
+        .collect();
+
+    let editor_area = Block::bordered().title(title);
+    frame.render_widget(Paragraph::new(lines).block(editor_area), area);
+
+    let column = 10 + editor.values[editor.focus].chars().count() as u16;
+    let x = (area.x + 1 + column).min(area.right().saturating_sub(2));
+    frame.set_cursor_position(Position::new(x, area.y + 1 + (editor.focus as u16)));
+}
+
+fn draw_generator(frame: &mut Frame, generator: &Generator, area: Rect) {
+    let policy = &generator.policy;
+    let strength = Strength::estimate(&generator.password, &[]);
+    let option = |enabled: bool, key: &str, label: &str| {
+        Line::from(format!("[{}] {key}  {label}", if enabled { "x" } else { " " }))
+    };
+
+    let lines = vec![
+        Line::from(generator.password.to_string()).bold(),
+        Line::default(),
+        field("Length", policy.length.to_string()),
+        field("Strength", format!("{}/{MAX_SCORE}", strength.score)),
+        Line::default(),
+        option(policy.uppercase, "u", "Uppercase letters"),
+        option(policy.lowercase, "l", "Lowercase letters"),
+        option(policy.digits, "d", "Digits"),
+        option(policy.symbols, "s", "Symbols"),
+        option(policy.exclude_ambiguous, "a", "Exclude ambiguous characters"),
+    ];
+
+    let generator_area = Block::bordered().title("Generator");
+    let panel = Paragraph::new(lines).block(generator_area).wrap(Wrap { trim: false });
+    frame.render_widget(panel, area);
+}